@@ -1,3 +1,9 @@
+; parses the upper bound of the sieve into b, the sieve itself is not implemented yet
+; input: "100\n"
+; expect stdout: ""
+; expect register b: 100
+; expect cycles: 132..132
+
 #include j_std_lib.asm
 
 _rom
@@ -20,11 +26,11 @@ _code
     ; allocate buffer
     call .STDmalloc
 
-
-
+    ; f gets overwritten by the amount of read bytes
+    mov b,f
 
     ; input syscall
-    push f
+    push b
     push 10
     call .STDInput
 
@@ -36,16 +42,16 @@ _code
 
     ; check input for digits
     ; loop unitl null termintion
-    ; b holds next address, f is base ptr and add iterations from c
-    mov b,0
-    add b,f
-    add b,c
+    ; e holds next address, b is base ptr and add iterations from c
+    .inputloop:
+    mov e,b
+    add e,c
     ; check null termintion
-    cmp [b],0
+    cmp [e],0
     je .endloopinput
     ; continue if not finished
     ; move char into register d
-    mov d,[b]
+    mov d,[e]
     
     ; test if the char is ascii
     ; lower boundary
@@ -59,15 +65,28 @@ _code
     
     ; get numeric-value form ascii char
     sub d,48
-    
-    shl d,c
 
+    ; a = a*10 + d, mulBy10 overwrites a and f
+    push d
+    push a
+    call .mulBy10
+    pop d
+    mov a,f
+    add a,d
 
+    add c,1
+    jmp .inputloop
 
-    .endloopinput:
+    ; the input is not a number
     .errinput:
-    push f
+    mov a,0
+
+    .endloopinput:
+    ; STDfree overwrites a
+    push a
+    push b
     call .STDfree
+    pop a
     ret 0
 
 
@@ -88,7 +107,9 @@ _code
 
 
 
-    call .STDPrintF
+    push f
+    call .STDPrintInt
+    call .STDPrintLn
     
     call .STDend
 
@@ -10,7 +10,9 @@ _code
 	mov c,0
 	.loop:
 	;print the current value
-	call .STDPrintA
+	push a
+	call .STDPrintInt
+	call .STDPrintLn
 	cmp a,1
 	je .end
 	; test if a is even
@@ -37,7 +39,9 @@ _code
 	jmp .loop
 	.end:
 	;print the steps:
-	call .STDPrintC
+	push c
+	call .STDPrintInt
+	call .STDPrintLn
     call .STDend
 
 	.div:
@@ -1,3 +1,8 @@
+; collatz sequence of 89375983457349593, prints every value and the amount of steps
+; expect register a: 1
+; expect register c: 359
+; expect cycles: 9491..9491
+
 #include j_std_lib.asm;
 
 _rom
@@ -9,7 +14,9 @@ _code
 	mov c,0
 	.loop:
 	;print the current value
-	call .STDPrintA
+	push a
+	call .STDPrintInt
+	call .STDPrintLn
 	cmp a,1
 	je .end
 	; test if a is even
@@ -36,7 +43,9 @@ _code
 	jmp .loop
 	.end:
 	;print the steps:
-	call .STDPrintC
+	push c
+	call .STDPrintInt
+	call .STDPrintLn
     call .STDend
 		
 	.div:
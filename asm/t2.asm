@@ -1,3 +1,6 @@
+; expect stdout: "42\n"
+; expect cycles: 40..40
+
 #include j_std_lib.asm;
 
 _rom
@@ -13,7 +16,9 @@ _code
 	mov b,[f]
 
 	; print the value
-	call .STDPrintB
+	push b
+	call .STDPrintInt
+	call .STDPrintLn
 
 	;save the pointer to the allocation 
 	push f
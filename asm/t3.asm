@@ -1,3 +1,6 @@
+; expect stdout: "cdefghijklmnopqrstuvwxyz\n"
+; expect cycles: 12..12
+
 #include j_std_lib.asm;
 
 _rom
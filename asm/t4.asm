@@ -1,4 +1,6 @@
 ; testing null pointer dereferencing:
+; expect exit: error "null pointer dereference"
+; expect stdout: ""
 _rom
 _code
     .start:
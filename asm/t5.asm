@@ -1,3 +1,6 @@
+; expect stdout: "421\n"
+; expect cycles: 16..16
+
 #include j_std_lib.asm;
 
 #define idk 421
@@ -6,5 +9,7 @@ _rom
 _code
 	.start:
 		mov a, $idk
-		call .STDPrintA
+		push a
+		call .STDPrintInt
+		call .STDPrintLn
 		call .STDend
@@ -1,3 +1,8 @@
+; echoes a line of the input
+; input: "hello\n"
+; expect stdout: "hello\n"
+; expect cycles: 34..34
+
 #include j_std_lib.asm
 
 #define buffersize 10
@@ -7,12 +12,14 @@ _code
     .start:
         push $buffersize
         call .STDmalloc
-        push f
+        ; f gets overwritten by the amount of read bytes
+        mov b, f
+        push b
         push $buffersize
         call .STDInput
-        push f
+        push b
         call .STDPrintString
-        push f
+        push b
         call .STDfree
         call .STDend
 
@@ -1,7 +1,13 @@
+; the stack is empty at the start
+; expect stdout: "0\n"
+; expect cycles: 16..16
+
 #include j_std_lib.asm;
 _rom
 _code
     .start:
         mov  a, tos
-        call .STDPrintA
+        push a
+        call .STDPrintInt
+        call .STDPrintLn
         call .STDend
@@ -0,0 +1,64 @@
+import build.basic_build as bb
+import os
+import re
+import sys
+
+# compares the speed of the interpreter with and without the decoded instructions
+# and of the block engine.
+# usage: python3 bench.py [runs] [file.asm ...]
+# all programs in ./asm are used if no files are supplied
+
+runs = 3
+programs = sorted(f for f in os.listdir("./asm") if f.endswith(".asm"))
+
+if len(sys.argv) >= 2:
+	runs = int(sys.argv[1])
+
+if len(sys.argv) >= 3:
+	programs = sys.argv[2:]
+
+# input for the programms that read from stdin
+inputs = {
+	"sieb.asm": "100\n",
+}
+
+def time_per_run(output):
+	match = re.search(r"time per run: ([0-9.]+) ms", output)
+	return float(match.group(1)) if match else None
+
+print("checking interpreter build... ",end="")
+if bb.check_build("./j_system/j_interpreter"):
+    print("OK")
+else:
+    print("failed")
+    exit()
+
+print("{:<12} {:>14} {:>14} {:>14} {:>8} {:>14} {:>8}".format("programm", "cycles", "cached ms", "uncached ms", "speedup", "blocks ms", "speedup"))
+
+for file_name in programs:
+	bb.copy_asm_to_assembler(file_name)
+
+	if not bb.check_assembler_output(bb.run_assembler()):
+		print("{:<12} assembler failed".format(file_name))
+		continue
+
+	bb.copy_bin_to_interpreter()
+
+	input = inputs.get(file_name, "")
+	ok_cached, cached = bb.run_interpreter_bench(runs, [], input)
+	ok_uncached, uncached = bb.run_interpreter_bench(runs, ["--no-decode-cache"], input)
+	ok_blocks, blocks = bb.run_interpreter_bench(runs, ["--engine", "blocks"], input)
+
+	if not (ok_cached and ok_uncached and ok_blocks):
+		error = next(out for ok, out in [(ok_cached, cached), (ok_uncached, uncached), (ok_blocks, blocks)] if not ok)
+		print("{:<12} failed: {}".format(file_name, error.splitlines()[-1] if error else ""))
+		continue
+
+	cycles = re.search(r"cycles per run: (\d+)", cached).group(1)
+	t_cached, t_uncached, t_blocks = time_per_run(cached), time_per_run(uncached), time_per_run(blocks)
+	speedup = t_uncached / t_cached if t_cached > 0 else 0
+	blocks_speedup = t_uncached / t_blocks if t_blocks > 0 else 0
+
+	print("{:<12} {:>14} {:>14.3f} {:>14.3f} {:>7.2f}x {:>14.3f} {:>7.2f}x".format(file_name, cycles, t_cached, t_uncached, speedup, t_blocks, blocks_speedup))
+
+print("--done--")
@@ -77,3 +77,22 @@ def _check_build(path):
     h.close()
     os.remove(path+"/output.json")
     return json_output.splitlines()[-1] == "{\"reason\":\"build-finished\",\"success\":true}"
+
+# runs the programm in the interpreter `runs` times with the --bench flag.
+# the interpreter has to be build with check_build first.
+# returns (success, output of the interpreter)
+def run_interpreter_bench(runs, extra_args, input):
+    p = subprocess.run(["./target/release/j_interpreter", "--bench", str(runs)] + extra_args,
+                       cwd="./j_system/j_interpreter/", input=input.encode(), stdout=subprocess.PIPE, stderr=subprocess.PIPE)
+    output = p.stdout.decode() if p.returncode == 0 else p.stderr.decode()
+    return p.returncode == 0, output.strip()
+
+# runs the programm in the interpreter with the --lockstep flag, which compares
+# the configuration of extra_args with the plain interpreter after every instruction.
+# the interpreter has to be build with check_build first.
+# returns (no divergence, output of the interpreter)
+def run_interpreter_lockstep(extra_args, input):
+    p = subprocess.run(["./target/release/j_interpreter", "--lockstep"] + extra_args,
+                       cwd="./j_system/j_interpreter/", input=input.encode(), stdout=subprocess.PIPE, stderr=subprocess.PIPE)
+    output = p.stdout.decode() if p.returncode == 0 else p.stderr.decode()
+    return p.returncode == 0, output.strip()
@@ -0,0 +1,13 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use j_assembler::assembler::assemble;
+
+// any source has to be assembled or rejected with an error, the assembler must not panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data)
+    {
+        let lines = source.lines().map(|line| line.to_string()).collect();
+        let _ = assemble(lines, "fuzz.asm".to_string());
+    }
+});
@@ -21,75 +21,69 @@ _code
 		sys
 		ret 0
 		
-	.STDPrintA:
-		push 1
-		push 1
-		push 8
-		sys
-		ret 0
-
-	.STDPrintB:
-		push 2
-		push 1
-		push 8
-		sys
-		ret 0
-		
-	.STDPrintC:
+	; print a value as decimal number
+	; push the value
+	.STDPrintInt:
+		push [tos+1]
 		push 3
-		push 1
 		push 8
 		sys
-		ret 0
+		ret 1
 
-	.STDPrintD:
+	; print a value as hex number
+	; push the value
+	.STDPrintHex:
+		push [tos+1]
 		push 4
-		push 1
 		push 8
 		sys
-		ret 0
-	
-	.STDPrintE:
+		ret 1
+
+	; print a value as binary number
+	; push the value
+	.STDPrintBin:
+		push [tos+1]
 		push 5
-		push 1
 		push 8
 		sys
-		ret 0
+		ret 1
 
-	.STDPrintF:
+	; print a single ascii char
+	; push the char
+	.STDPrintChar:
+		push [tos+1]
 		push 6
-		push 1
 		push 8
 		sys
-		ret 0
+		ret 1
 
-	.STDPrintTOS:
-		push 7
-		push 1
+	; print a newline
+	.STDPrintLn:
+		push 10
+		push 6
 		push 8
 		sys
 		ret 0
 
-	.STDPrintBOS:
-		push 8
-		push 1
+	; print a null terminated string without a newline
+	; push the pointer to the string
+	.STDPrintStr:
+		push [tos+1]
+		push 7
 		push 8
 		sys
-		ret 0
+		ret 1
 
-	.STDPrintPC:
-		push 9
-		push 1
+	; print a buffer with a given length
+	; first push the pointer to the buffer
+	; second push the length
+	.STDPrintBuf:
+		push [tos+1]
+		push [tos+3]
 		push 8
-		sys
-		ret 0
-
-	.STDPrintS:
-		push 10
-		push 1
 		push 8
 		sys
-		ret 0
+		ret 2
 
 	.STDPrintString:
 		mov f, [tos+1]
@@ -99,16 +93,182 @@ _code
 		sys
 		ret 1
 
+	; read a line from stdin into a buffer.
+	; the line gets null terminated and the newline is removed.
 	; first push the buffer ptr
 	; second push the buffer size
 	; size includes the null termination
+	; returns the amount of read chars in f
+	; e contains 0 on success and 1 on EOF
 	.STDInput:
-		mov a, [tos+2]
-		mov b, [tos+1]
+		push [tos+1]
+		push [tos+3]
+		push 1
+		push 7
+		sys
+		ret 2
+
+	; read bytes from stdin into a buffer without null termination
+	; first push the buffer ptr
+	; second push the amount of bytes to read
+	; returns the amount of read bytes in f
+	; e contains 0 on success and 1 on EOF
+	.STDReadBytes:
+		push [tos+1]
+		push [tos+3]
+		push 2
+		push 7
+		sys
+		ret 2
+
+	; read a single char from stdin, result in f
+	; e contains 0 on success and 1 on EOF
+	.STDReadChar:
+		push 3
+		push 7
+		sys
+		ret 0
+
+	; read a decimal integer from stdin, result in f
+	; e contains 0 on success, 1 on EOF and 2 on invalid input
+	.STDReadInt:
+		push 4
+		push 7
+		sys
+		ret 0
+
+	; read a hex integer from stdin, result in f
+	; e contains 0 on success, 1 on EOF and 2 on invalid input
+	.STDReadHex:
+		push 5
+		push 7
+		sys
+		ret 0
+
+	; copy a block of memory
+	; first push the destination ptr
+	; second push the source ptr
+	; third push the amount of values
+	; overwrites a, b and c
+	.STDMemCopy:
+		mov a, [tos+3]
+		mov b, [tos+2]
+		mov c, [tos+1]
+		push c
+		push b
 		push a
+		push 1
+		push 3
+		sys
+		ret 3
+
+	; fill a block of memory with a value
+	; first push the destination ptr
+	; second push the value
+	; third push the amount of values
+	; overwrites a, b and c
+	.STDMemFill:
+		mov a, [tos+3]
+		mov b, [tos+2]
+		mov c, [tos+1]
+		push c
 		push b
-		push 7
+		push a
+		push 2
+		push 3
+		sys
+		ret 3
+
+	; get information about the machine, result in f
+	; push the type of information:
+	; 1 mem size, 2 heap-cutoff, 3 cycles, 4 code base ptr,
+	; 5 code size, 6 rom base ptr, 7 rom size
+	.STDSysInfo:
+		mov a, [tos+1]
+		push a
+		push 4
+		sys
+		ret 1
+
+	; get a random number, result in f
+	; push the upper bound (exclusive), 0 for no bound
+	.STDRand:
+		mov a, [tos+1]
+		push a
+		push 5
+		sys
+		ret 1
+
+	; get the time, result in f
+	; push the type of time:
+	; 1 cycles, 2 milliseconds since start, 3 unix time in seconds
+	.STDTime:
+		mov a, [tos+1]
+		push a
+		push 6
+		sys
+		ret 1
+
+	; open a file, the file descriptor is returned in f
+	; first push the pointer to the null terminated file name
+	; second push the mode:
+	; 1 read, 2 write (create/truncate), 3 append, 4 read and write
+	; e contains 0 on success and 2 on failure
+	.STDFileOpen:
+		push [tos+1]
+		push [tos+3]
+		push 10
+		sys
+		ret 2
+
+	; read from a file into a buffer
+	; first push the file descriptor (0 is stdin)
+	; second push the buffer ptr
+	; third push the maximum amount of bytes
+	; returns the amount of read bytes in f
+	; e contains 0 on success, 1 on EOF and 2 on failure
+	.STDFileRead:
+		push [tos+1]
+		push [tos+3]
+		push [tos+5]
+		push 11
+		sys
+		ret 3
+
+	; write a buffer to a file
+	; first push the file descriptor (1 is stdout)
+	; second push the buffer ptr
+	; third push the amount of bytes
+	; returns the amount of written bytes in f
+	; e contains 0 on success and 2 on failure
+	.STDFileWrite:
+		push [tos+1]
+		push [tos+3]
+		push [tos+5]
+		push 12
+		sys
+		ret 3
+
+	; close a file
+	; push the file descriptor
+	; e contains 0 on success and 2 on failure
+	.STDFileClose:
+		push [tos+1]
+		push 13
+		sys
+		ret 1
+
+	; change the position in a file, the new position is returned in f
+	; first push the file descriptor
+	; second push 0 (from start), 1 (from current position) or 2 (from end)
+	; third push the offset
+	; e contains 0 on success and 2 on failure
+	.STDFileSeek:
+		push [tos+1]
+		push [tos+3]
+		push [tos+5]
+		push 14
 		sys
-		ret 2	
+		ret 3
 
 _rom
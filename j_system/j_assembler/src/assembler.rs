@@ -1,35 +1,100 @@
 use crate::label_resolve::*;
 use crate::serialization::*;
 use crate::decode_instructons::*;
-use crate::debug::*;
 
-use std::collections::HashMap;
+use j_system_definition::instructions::AsmLine;
 
-pub fn assemble_into_u64_vec(input: Vec<String>, main_file_name: String) -> Vec<u64>
+use std::path::PathBuf;
+
+/// first value of a binary with a header, "JBIN" in ascii.
+/// Binaries without a header start with the NULL value of the rom (0)
+pub const BINARY_MAGIC: u64 = 0x4A42_494E;
+
+/// amount of values in front of the rom: magic, rom base and code base
+pub const HEADER_SIZE: usize = 3;
+
+/// where rom and code are placed in the adress space.
+/// The labels are resolved to adresses in these sections
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct SectionBases
+{
+    pub rom: u64,
+
+    /// directly after the rom if `None`
+    pub code: Option<u64>,
+}
+
+/// the assembled programm and what the debug output needs
+pub struct Assembly
+{
+    /// header, rom, code, size of the rom and start of execution
+    pub binary: Vec<u64>,
+
+    /// the instructions with resolved labels
+    pub code: Vec<AsmLine>,
+
+    /// the adress of every instruction in code
+    pub instruction_position: Vec<u64>,
+
+    pub start: u64,
+    pub rom_base: u64,
+    pub rom_len: u64,
+    pub code_base: u64,
+    pub code_len: u64,
+
+    /// name and adress of every label
+    pub labels: Vec<(String,u64)>,
+
+    /// adress of every instruction and where it was defined
+    pub line_table: Vec<(u64,OriginInformation)>,
+}
+
+pub fn assemble(input: Vec<String>, main_file_name: String) -> Result<Assembly,String>
+{
+    assemble_with_include_dirs(input, main_file_name, &[])
+}
+
+/// the included files are searched in the include directories before the working directory
+pub fn assemble_with_include_dirs(input: Vec<String>, main_file_name: String, include_dirs: &[PathBuf]) -> Result<Assembly,String>
+{
+    assemble_with_bases(input, main_file_name, include_dirs, SectionBases::default())
+}
+
+/// places rom and code at the given adresses
+pub fn assemble_with_bases(input: Vec<String>, main_file_name: String, include_dirs: &[PathBuf], bases: SectionBases) -> Result<Assembly,String>
 {   
-    let preprocessed = preprocess_input(input, main_file_name);
+    let preprocessed = preprocess_input(input, main_file_name, include_dirs)?;
     let code_section = preprocessed.code; 
     let rom_section  = preprocessed.rom;
     let defines      = preprocessed.defines;
 
     // TODO: why is this called rom table? doesnt it incluce all labels???
-    let (rom_raw, mut rom_table) = parse_rom(rom_section);
+    let (rom_raw, mut rom_table) = parse_rom(rom_section)?;
 
     // save len for later insertion since it will be "moved" into binary
     let rom_len = rom_raw.len() as u64;
 
-    let code_with_labels = parse_code(code_section, &mut rom_table);
+    // the rom labels point into the rom, the code labels are resolved with the code base
+    rom_table.iter_mut().for_each(|label| label.pos += bases.rom);
+
+    let code_with_labels = parse_code(code_section, &mut rom_table)?;
+    let code_len = code_with_labels.iter().map(instruction_len).sum();
+    let code_base = check_bases(bases, rom_len, code_len)?;
+
+    // origin of every instruction for the line table
+    let origins:Vec<OriginInformation> = code_with_labels.iter().map(|inst| inst.info.clone()).collect();
 
     let mut debug_symbols = vec![];
 
-    let (final_code,start_of_execution_ptr, instruction_position) = remove_labels_from_asm(code_with_labels, &mut rom_table, defines, &mut debug_symbols ,rom_len);
+    let (final_code,start_of_execution_ptr, instruction_position) = remove_labels_from_asm(code_with_labels, &mut rom_table, defines, &mut debug_symbols ,code_base)?;
     
-    // create debug output
-    debug_ouput(&final_code, instruction_position, start_of_execution_ptr, rom_len, debug_symbols);
+    let line_table = instruction_position.iter().copied().zip(origins).collect();
+
+    let mut binary: Vec<u64> = vec![BINARY_MAGIC, bases.rom, code_base];
 
-    let mut binary: Vec<u64> = rom_raw;
+    binary.extend(rom_raw);
 
-    binary.append(&mut serialize_asm(final_code));
+    binary.append(&mut serialize_asm(final_code.clone()));
 
     // instert point of seclection split
     binary.push(rom_len);
@@ -37,5 +102,32 @@ pub fn assemble_into_u64_vec(input: Vec<String>, main_file_name: String) -> Vec<
     // inset start of execution
     binary.push(start_of_execution_ptr);
 
-    binary
+    Ok(Assembly{
+        binary,
+        code: final_code,
+        instruction_position,
+        start: start_of_execution_ptr,
+        rom_base: bases.rom,
+        rom_len,
+        code_base,
+        code_len,
+        labels: debug_symbols,
+        line_table,
+    })
+}
+
+/// the adress of the code. Fails if a section does not fit into the
+/// adress space or rom and code overlap
+fn check_bases(bases: SectionBases, rom_len: u64, code_len: u64) -> Result<u64,String>
+{
+    let rom_end = bases.rom.checked_add(rom_len).ok_or(format!("the rom does not fit behind {}", bases.rom))?;
+    let code_base = bases.code.unwrap_or(rom_end);
+    let code_end = code_base.checked_add(code_len).ok_or(format!("the code does not fit behind {}", code_base))?;
+
+    if code_base < rom_end && bases.rom < code_end
+    {
+        return Err(format!("the code ({}..{}) overlaps the rom ({}..{})", code_base, code_end, bases.rom, rom_end));
+    }
+
+    Ok(code_base)
 }
@@ -1,24 +1,21 @@
-use j_system_definition::instructions::AsmLine;
-use crate::decode_instructons::LabelPointer;
+use crate::assembler::Assembly;
 use std::fs;
 
-pub fn debug_ouput(
-    code: &Vec<AsmLine>, 
-    instruction_position: Vec<u64>, 
-    start: u64, 
-    rom_len: u64, 
-    labels: Vec<(String,u64)>)
+/// writes debug.txt, labels.dbg and lines.dbg
+pub fn debug_ouput(assembly: &Assembly)
 {
     let mut s = String::new();
 
-    let ziped = code.iter().zip(instruction_position.iter());
+    let ziped = assembly.code.iter().zip(assembly.instruction_position.iter());
 
-    s.push_str(&format!("start of execution: {}\n",start));
-    s.push_str(&format!("rom size: {}\n\n\n",rom_len));
+    s.push_str(&format!("start of execution: {}\n",assembly.start));
+    s.push_str(&format!("rom base: {}\n",assembly.rom_base));
+    s.push_str(&format!("code base: {}\n",assembly.code_base));
+    s.push_str(&format!("rom size: {}\n\n\n",assembly.rom_len));
 
-    for (ins, pos) in ziped
+    for ((ins, pos),(_,origin)) in ziped.zip(assembly.line_table.iter())
     {
-        s.push_str(&format!("{}\t", pos));
+        s.push_str(&format!("{}\t{}:{}\t", pos, origin.file, origin.line));
         s.push_str(&ins.as_string());
         s.push('\n');
     }
@@ -26,9 +23,17 @@ pub fn debug_ouput(
     fs::write("debug.txt", s).unwrap();
 
     let mut s = String::new();
-    for label in labels
+    for label in &assembly.labels
     {
         s.push_str(&format!("{}\t{}\n",label.1,label.0))
     }
     fs::write("labels.dbg",s).unwrap();
+
+    // line table: adress of the instruction, line and file it was defined in
+    let mut s = String::new();
+    for (addr,origin) in &assembly.line_table
+    {
+        s.push_str(&format!("{}\t{}\t{}\n", addr, origin.line, origin.file))
+    }
+    fs::write("lines.dbg",s).unwrap();
 }
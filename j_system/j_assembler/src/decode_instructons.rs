@@ -8,6 +8,7 @@ use crate::file_save_load::*;
 use crate::serialization::*;
 
 use std::collections::hash_map::HashMap;
+use std::path::PathBuf;
 
 #[derive(Clone,PartialEq,Debug)]
 pub struct LabelPointer
@@ -26,6 +27,7 @@ pub enum LabelType
     ArdressToRomData
 }
 
+#[derive(Clone,PartialEq,Debug)]
 pub struct OriginInformation
 {
     /// contains the filename (and path?) to the file that defines this datapoint
@@ -34,6 +36,22 @@ pub struct OriginInformation
     pub line: u64,
 }
 
+/// a line of the input together with the file and line it came from
+#[derive(Clone,PartialEq,Debug)]
+pub struct SourceLine
+{
+    pub info: OriginInformation,
+    pub content: String,
+}
+
+/// attaches the file name and the line numbers (starting at 1) to the lines of a file
+pub fn into_source_lines(lines: Vec<String>, file: &str) -> Vec<SourceLine>
+{
+    lines.into_iter().enumerate()
+        .map(|(ii,content)| SourceLine{info: OriginInformation{file: file.into(), line: ii as u64 +1}, content})
+        .collect()
+}
+
 pub struct AsmLineLabel
 {
     pub info: OriginInformation,
@@ -118,7 +136,7 @@ lazy_static!
     static ref RE_LABEL_DEREF_OFFSET:   Regex = Regex::new(r"^\s*\[\s*\.([a-zA-Z0-9]+)\s*(\+|\-)\s*([0-9]+)\s*\]\s*$").unwrap();
 }
 
-pub fn cleanup_input(code: &mut Vec<String>)
+pub fn cleanup_input(code: &mut Vec<SourceLine>)
 {
     //debug_print_vec_string(&code_lines);
     
@@ -152,16 +170,16 @@ pub fn cleanup_input(code: &mut Vec<String>)
 }
 
 
-pub fn parse_lables_and_remove_labels(text_list: & mut Vec<String>, label_list:& mut Vec<LabelPointer>)
+pub fn parse_lables_and_remove_labels(text_list: & mut Vec<SourceLine>, label_list:& mut Vec<LabelPointer>) -> Result<(),String>
 {
     let mut ii = 0usize;
 
     while text_list.len() > ii
     {
-        if RE_GET_CODE_LABEL.is_match(&text_list[ii])
+        if RE_GET_CODE_LABEL.is_match(&text_list[ii].content)
         {
             let mut label_name = String::from(""); 
-            for cap in RE_GET_CODE_LABEL.captures_iter(&text_list[ii])
+            for cap in RE_GET_CODE_LABEL.captures_iter(&text_list[ii].content)
             {
                 label_name = String::from(&cap[1]);
                 break;
@@ -171,7 +189,7 @@ pub fn parse_lables_and_remove_labels(text_list: & mut Vec<String>, label_list:&
             {
                 if label_list_elem.identifier == label_name
                 { 
-                    panic!("found duplicate label name: {}", label_name);
+                    return Err(format!("found duplicate label name: {}", label_name));
                 }
             }
 
@@ -193,6 +211,7 @@ pub fn parse_lables_and_remove_labels(text_list: & mut Vec<String>, label_list:&
             ii+=1;
         }
     }
+    Ok(())
 }
 
 // fn set_jump_adresses(label_list:Vec<Label>, text_list: & mut Vec<String>)
@@ -232,7 +251,7 @@ pub fn parse_lables_and_remove_labels(text_list: & mut Vec<String>, label_list:&
 //     }
 // }
 
-fn remove_empty_lines(text_list: &mut Vec<String>)
+fn remove_empty_lines(text_list: &mut Vec<SourceLine>)
 {
     let re = Regex::new(r"^\s*$").unwrap();
 
@@ -240,7 +259,7 @@ fn remove_empty_lines(text_list: &mut Vec<String>)
     while text_list.len() > ii
     {
 
-        if re.is_match(&text_list[ii])
+        if re.is_match(&text_list[ii].content)
         {
             // remove empty line on match of regex
             text_list.remove(ii);
@@ -256,14 +275,14 @@ fn remove_empty_lines(text_list: &mut Vec<String>)
     }
 }
 
-fn remove_comments(text_list: & mut Vec<String>)
+fn remove_comments(text_list: & mut Vec<SourceLine>)
 {
     let only_comment = Regex::new(r"^\s*;.*").unwrap();
     
     let mut ii = 0usize;
     while text_list.len() > ii
     {
-        if only_comment.is_match(&text_list[ii])
+        if only_comment.is_match(&text_list[ii].content)
         {
             // remove empty line on match of regex
             text_list.remove(ii);
@@ -282,15 +301,15 @@ fn remove_comments(text_list: & mut Vec<String>)
     
     while text_list.len() > ii2
     {
-        if comment_after_code.is_match(&text_list[ii2])
+        if comment_after_code.is_match(&text_list[ii2].content)
         {
             let mut code_line_no_comment = String::from("");
-            for cap in comment_after_code.captures_iter(&text_list[ii2])
+            for cap in comment_after_code.captures_iter(&text_list[ii2].content)
             {
                 code_line_no_comment = String::from(&cap[1]);
                 break;
             }
-            text_list[ii2] = code_line_no_comment;
+            text_list[ii2].content = code_line_no_comment;
             ii2 +=1;
         }
         else
@@ -300,8 +319,10 @@ fn remove_comments(text_list: & mut Vec<String>)
     }
 }
 
-fn parse_line(line_code: String) -> AsmLineLabel
+fn parse_line(line: SourceLine) -> Result<AsmLineLabel,String>
 {
+    let SourceLine{info, content: line_code} = line;
+
     let mut line_is_ok = false;
 
     let mut cap1 = String::from("");
@@ -316,25 +337,25 @@ fn parse_line(line_code: String) -> AsmLineLabel
         // checking if there even are parameters
         if let Some(_) = cap.get(2)
         {
-            parse_parameters(cap[2].to_string(), &mut parsed_param1, &mut parsed_param2);
+            parse_parameters(cap[2].to_string(), &mut parsed_param1, &mut parsed_param2)
+                .map_err(|e| format!("{}:{}: {}", info.file, info.line, e))?;
         }
     }
 
-    if !line_is_ok{ panic!("could not parse line: {}",line_code);}
+    if !line_is_ok{ return Err(format!("could not parse line {}:{}: {}",info.file,info.line,line_code));}
 
-    let parsed_instruction = match_instructtion(cap1);
+    let parsed_instruction = match_instructtion(cap1)?;
 
-    // TODO: include line number from the original input file for debug output
-    AsmLineLabel
+    Ok(AsmLineLabel
     {   
-        info: OriginInformation { file: "".into(), line: 0 },
+        info,
         instruction: parsed_instruction, 
         param1: parsed_param1, 
         param2: parsed_param2,
-    }
+    })
 } 
 
-fn parse_parameters(snippet: String,p1: &mut ParamOrLabel,p2: &mut ParamOrLabel) 
+fn parse_parameters(snippet: String,p1: &mut ParamOrLabel,p2: &mut ParamOrLabel) -> Result<(),String>
 {
     // RE_PARSE_PRAMS:
     // cap 1 -> complete param line with comma if parameters exit
@@ -362,17 +383,18 @@ fn parse_parameters(snippet: String,p1: &mut ParamOrLabel,p2: &mut ParamOrLabel)
     }
     if &param1_str != "" 
     {
-        *p1 = parse_one_parameter(param1_str);
+        *p1 = parse_one_parameter(param1_str)?;
         
         if &param2_str != "" 
         {
-            *p2 = parse_one_parameter(param2_str);
+            *p2 = parse_one_parameter(param2_str)?;
         }
     }
     //(param1,param2)
+    Ok(())
 }
 
-fn parse_one_parameter(snippet: String) -> ParamOrLabel
+fn parse_one_parameter(snippet: String) -> Result<ParamOrLabel,String>
 {
     let mut p:ParamOrLabel = ParamOrLabel::Nothing;
 
@@ -381,7 +403,7 @@ fn parse_one_parameter(snippet: String) -> ParamOrLabel
     { 
         if let Some(_) = cap.get(1)
         {
-            p = ParamOrLabel::Param(Param::Register(match_register(cap[1].to_string())));
+            p = ParamOrLabel::Param(Param::Register(match_register(cap[1].to_string())?));
         }
     }
 
@@ -390,7 +412,7 @@ fn parse_one_parameter(snippet: String) -> ParamOrLabel
     { 
         if let Some(_) = cap.get(1)
         {
-            p = ParamOrLabel::Param(Param::Constant(parse_number_u64(cap[1].to_string()).unwrap())); //TODO: check for save parse
+            p = ParamOrLabel::Param(Param::Constant(parse_number_u64(cap[1].to_string()).ok_or(format!("number too large: {}", snippet))?));
         }
     }
 
@@ -398,7 +420,7 @@ fn parse_one_parameter(snippet: String) -> ParamOrLabel
     { 
         if let Some(_) = cap.get(1)
         {
-            p = ParamOrLabel::Param(Param::MemPtr(parse_integer_u64(cap[1].to_string()).unwrap())); //TODO: check for save parse
+            p = ParamOrLabel::Param(Param::MemPtr(parse_integer_u64(cap[1].to_string()).ok_or(format!("adress too large: {}", snippet))?));
         }
     }
 
@@ -406,27 +428,27 @@ fn parse_one_parameter(snippet: String) -> ParamOrLabel
     { 
         if let Some(_) = cap.get(1) 
         {
-            let reg = match_register(cap[1].to_string());
+            let reg = match_register(cap[1].to_string())?;
 
             if let Some(_) = cap.get(2)
             {
-                if let Some(_) = cap.get(3)
+                // the offset is stored with its sign, so it can't be i64::MIN
+                let val = crate::type_cov_parse::parse_integer_u64(cap[3].to_string())
+                    .filter(|val| *val <= i64::MAX as u64)
+                    .ok_or(format!("invalid offset: {}", snippet))?;
+                if cap[2].to_string() == "+"
                 {
-                    let val = crate::type_cov_parse::parse_integer_u64(cap[3].to_string()).unwrap();
-                    if cap[2].to_string() == "+"
-                    {
-                        p = ParamOrLabel::Param(Param::MemPtrOffset(reg,val as i64));
-                    }
-                    else if cap[2].to_string() == "-"
-                    {
-                        p = ParamOrLabel::Param(Param::MemPtrOffset(reg,-(val as i64)));
-                    }
+                    p = ParamOrLabel::Param(Param::MemPtrOffset(reg,val as i64));
+                }
+                else if cap[2].to_string() == "-"
+                {
+                    p = ParamOrLabel::Param(Param::MemPtrOffset(reg,-(val as i64)));
                 }
             }
             else
             {
                 //TODO: should be ok, right?
-                p = ParamOrLabel::Param(Param::MemPtrOffset(match_register(cap[1].to_string()),0));
+                p = ParamOrLabel::Param(Param::MemPtrOffset(reg,0));
             }
         }
     }
@@ -446,13 +468,14 @@ fn parse_one_parameter(snippet: String) -> ParamOrLabel
 
         for cap in RE_LABEL_DEREF_OFFSET.captures_iter(&snippet)
         {
+            let offset = cap[3].parse::<i64>().map_err(|_| format!("invalid offset: {}", snippet))?;
             if cap[2].to_string() == "-"
             {
-                p = ParamOrLabel::Label(cap[1].to_string(),LabelUse::DerefOffset(-(cap[3].parse::<i64>().unwrap())));
+                p = ParamOrLabel::Label(cap[1].to_string(),LabelUse::DerefOffset(-offset));
             }
             else
             {
-                p = ParamOrLabel::Label(cap[1].to_string(),LabelUse::DerefOffset(cap[3].parse::<i64>().unwrap()));
+                p = ParamOrLabel::Label(cap[1].to_string(),LabelUse::DerefOffset(offset));
             }
         }
     }
@@ -465,18 +488,18 @@ fn parse_one_parameter(snippet: String) -> ParamOrLabel
         }
     }
 
-    // We can just panic here since this method shouldt get called
+    // this method shouldt get called
     // if there were no input that could be a parameter
     if p == ParamOrLabel::Nothing
     {
-        panic!("could not match parameter for: {}",snippet);
+        return Err(format!("could not match parameter for: {}",snippet));
     }
-    p
+    Ok(p)
 } 
 
-fn match_register(snippet: String) -> Register
+fn match_register(snippet: String) -> Result<Register,String>
 {
-    match &snippet[..]
+    Ok(match &snippet[..]
     {
         "a"     => Register::a,
         "b"     => Register::b,
@@ -488,13 +511,13 @@ fn match_register(snippet: String) -> Register
         "pc"    => Register::pc,
         "tos"   => Register::tos,
         "bos"   => Register::bos,
-        _       => panic!("could not match register")
-    }
+        _       => return Err(format!("could not match register: {}", snippet))
+    })
 }
 
-fn match_instructtion(snippet: String) -> InstructionEnum
+fn match_instructtion(snippet: String) -> Result<InstructionEnum,String>
 {
-    match &snippet[..]
+    Ok(match &snippet[..]
     {
         "add"   => InstructionEnum::add,
         "sub"   => InstructionEnum::sub,
@@ -518,24 +541,24 @@ fn match_instructtion(snippet: String) -> InstructionEnum
         "call"  => InstructionEnum::call,
         "ret"   => InstructionEnum::ret,
         "sys"   => InstructionEnum::sys,
-        _       => panic!("could not mach instruction")
-    }
+        _       => return Err(format!("could not mach instruction: {}", snippet))
+    })
 }
 
-pub fn parse_code(mut text: Vec<String>, lable_table: & mut Vec<LabelPointer>) -> Vec<AsmLineLabel>
+pub fn parse_code(mut text: Vec<SourceLine>, lable_table: & mut Vec<LabelPointer>) -> Result<Vec<AsmLineLabel>,String>
 {
-    parse_lables_and_remove_labels(&mut text, lable_table);
+    parse_lables_and_remove_labels(&mut text, lable_table)?;
     
     let mut parsed_code = vec![];
     for line in text
     {
-        parsed_code.push(parse_line(line));
+        parsed_code.push(parse_line(line)?);
     }
 
-    parsed_code
+    Ok(parsed_code)
 }
 
-pub fn parse_rom(mut r: Vec<String>) -> (Vec<u64>, Vec<LabelPointer>)
+pub fn parse_rom(mut r: Vec<String>) -> Result<(Vec<u64>, Vec<LabelPointer>),String>
 {
     let mut label_list:Vec<LabelPointer> = vec![];
     
@@ -557,17 +580,17 @@ pub fn parse_rom(mut r: Vec<String>) -> (Vec<u64>, Vec<LabelPointer>)
                     "s"     => RomDataType::String,
                     "ai"    => RomDataType::IntegerArray,
                     "as"    => RomDataType::StringArray,
-                    _       => panic!("could to determine data type of: {}", r[0])
+                    _       => return Err(format!("could to determine data type of: {}", r[0]))
                 };
                 let label_content = cap[3].to_string();
 
-                let mut sersed = serialize_rom(label_content,rom_data_type);
+                let mut sersed = serialize_rom(label_content,rom_data_type)?;
                 
                 for x in &label_list
                 {
                     if x.identifier == label_name
                     {
-                        panic!("duplicate rom data name: {}", label_name);
+                        return Err(format!("duplicate rom data name: {}", label_name));
                     } 
                 }
   
@@ -584,59 +607,60 @@ pub fn parse_rom(mut r: Vec<String>) -> (Vec<u64>, Vec<LabelPointer>)
             }
             else
             {
-                panic!("cant parse rom line: {}",r[0]);
+                return Err(format!("cant parse rom line: {}",r[0]));
             }
         }
         r.remove(0);
     }
     
-    (rom_mem,label_list)
+    Ok((rom_mem,label_list))
 }
 
-pub fn split_sections(mut input: Vec<String>) -> (Vec<String>, Vec<String>)
+pub fn split_sections(mut input: Vec<SourceLine>) -> Result<(Vec<SourceLine>, Vec<SourceLine>),String>
 {
     // TODO: does one really need to define a _rom sections if it is not needed? 
     
     // the first after cleanup line should either be "_code" or "_rom"
+    let first = input.first().map(|line| line.content.clone()).unwrap_or_default();
 
-    if RE_CODE_SECTION_START.is_match(&input[0])
+    if RE_CODE_SECTION_START.is_match(&first)
     {
         // search for the end of code section aka start of rom section
         for ii in 1..input.len()
         {
-            if RE_ROM_SECTION_START.is_match(&input[ii])
+            if RE_ROM_SECTION_START.is_match(&input[ii].content)
             {
                 // remove ".code" and ".rom" from vec
                 input.remove(ii);
                 input.remove(0);
 
                 let rom_section = input.split_off(ii-1);
-                return (input,rom_section)
+                return Ok((input,rom_section))
             }
         }
     }
 
-    if RE_ROM_SECTION_START.is_match(&input[0])
+    if RE_ROM_SECTION_START.is_match(&first)
     {
         // search for the end of rom section aka start of code section
         for ii in 1..input.len()
         {
-            if RE_CODE_SECTION_START.is_match(&input[ii])
+            if RE_CODE_SECTION_START.is_match(&input[ii].content)
             {
                 // remove ".code" and ".rom" from vec
                 input.remove(ii);
                 input.remove(0);
                 
                 let code_section = input.split_off(ii-1);
-                return (code_section,input)
+                return Ok((code_section,input))
             }
         }
     }
 
-    panic!("PARSE ERROR: Could not split sections! Did you define a '_rom' and '_code 'section? Did you define anything outside of these sections?");
+    Err("PARSE ERROR: Could not split sections! Did you define a '_rom' and '_code 'section? Did you define anything outside of these sections?".to_string())
 }
 
-fn get_includes(input:&mut Vec<String>, already_included: &mut Vec<String>, to_be_included: &mut Vec<String>) 
+fn get_includes(input:&mut Vec<SourceLine>, already_included: &mut Vec<String>, to_be_included: &mut Vec<String>) 
 {
     cleanup_input(input);
     let mut ii = 0;
@@ -649,9 +673,9 @@ fn get_includes(input:&mut Vec<String>, already_included: &mut Vec<String>, to_b
             break;
         }
 
-        if RE_INCLUDE.is_match(&input[ii])
+        if RE_INCLUDE.is_match(&input[ii].content)
         {
-            for cap in RE_INCLUDE.captures_iter(&input[ii])
+            for cap in RE_INCLUDE.captures_iter(&input[ii].content)
             {
                 let newly_found_include = cap[1].to_string();
                 
@@ -671,7 +695,7 @@ fn get_includes(input:&mut Vec<String>, already_included: &mut Vec<String>, to_b
     }
 }
 
-pub fn get_defines(input: &mut Vec<String>, defines: &mut HashMap<String,u64>)
+pub fn get_defines(input: &mut Vec<SourceLine>, defines: &mut HashMap<String,u64>) -> Result<(),String>
 {
     //let mut defines:HashMap<String,u64> = HashMap::new();
     let mut ii = 0;
@@ -682,7 +706,7 @@ pub fn get_defines(input: &mut Vec<String>, defines: &mut HashMap<String,u64>)
         {
             break
         }
-        let current_line = input[ii].clone();
+        let current_line = input[ii].content.clone();
 
         if RE_DEFINE_CONST.is_match(&current_line)
         {
@@ -690,12 +714,12 @@ pub fn get_defines(input: &mut Vec<String>, defines: &mut HashMap<String,u64>)
             {
                 let def_name    = elem[1].to_string();
                 //panic!("{}",elem[2].to_string());
-                let value       = (&elem[2]).parse::<u64>().unwrap();
+                let value       = (&elem[2]).parse::<u64>().map_err(|_| format!("number too large: {}", &elem[2]))?;
 
                 // check if key is already defined
                 if defines.contains_key(&def_name)
                 {
-                    panic!("double definition of key: {} as define", def_name);
+                    return Err(format!("double definition of key: {} as define", def_name));
                 }
                 defines.insert(def_name, value);
             }
@@ -703,17 +727,31 @@ pub fn get_defines(input: &mut Vec<String>, defines: &mut HashMap<String,u64>)
         }
         ii+=1;
     }
+    Ok(())
 }
 
 pub struct PreprocessedInput
 {
     pub rom: Vec<String>,
-    pub code: Vec<String>,
+    /// the code with the origin of every line
+    pub code: Vec<SourceLine>,
     pub defines: HashMap<String,u64>,
 }
 
-pub fn preprocess_input(mut input: Vec<String>, main_file_name: String) -> PreprocessedInput
+/// the path of an included file: the first include directory that contains it,
+/// otherwise the name is used relative to the working directory
+fn find_include(name: &str, include_dirs: &[PathBuf]) -> String
+{
+    include_dirs.iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .map_or(name.to_string(), |path| path.to_string_lossy().to_string())
+}
+
+pub fn preprocess_input(input: Vec<String>, main_file_name: String, include_dirs: &[PathBuf]) -> Result<PreprocessedInput,String>
 {
+    let mut input = into_source_lines(input, &main_file_name);
+
     // add the first file name to the already inluded vec
     let mut already_inluded = vec![main_file_name];
     let mut to_be_included = vec![];
@@ -725,8 +763,8 @@ pub fn preprocess_input(mut input: Vec<String>, main_file_name: String) -> Prepr
     // first iteration outside of the loop to fill to_be_included
     cleanup_input(&mut input);
     get_includes(&mut input, &mut already_inluded, &mut to_be_included);
-    get_defines(&mut input, &mut defines);
-    let (mut code,mut rom) = split_sections(input);
+    get_defines(&mut input, &mut defines)?;
+    let (mut code,mut rom) = split_sections(input)?;
 
     while to_be_included.len() !=0 && count_include_actions <= MAX_INCLUDE_ACTIONS
     {
@@ -736,7 +774,7 @@ pub fn preprocess_input(mut input: Vec<String>, main_file_name: String) -> Prepr
         let next_include = to_be_included.pop().unwrap();
         
         // laod new file and push it to vec off already included files
-        let mut new_file = load_file(&next_include);
+        let mut new_file = into_source_lines(read_lines(&find_include(&next_include, include_dirs))?, &next_include);
         already_inluded.push(next_include);
         
         // extract includes from NEW file and remove the include statements
@@ -744,11 +782,11 @@ pub fn preprocess_input(mut input: Vec<String>, main_file_name: String) -> Prepr
         get_includes(&mut new_file, &mut already_inluded, &mut to_be_included);
 
         // get the new defines
-        get_defines(&mut new_file, &mut defines);
+        get_defines(&mut new_file, &mut defines)?;
 
         // merge sections of both old and new file
         cleanup_input(&mut new_file);
-        let (mut new_code,mut new_rom) = split_sections(new_file);
+        let (mut new_code,mut new_rom) = split_sections(new_file)?;
 
         code.append(&mut new_code);
         rom.append(&mut new_rom);
@@ -756,5 +794,30 @@ pub fn preprocess_input(mut input: Vec<String>, main_file_name: String) -> Prepr
         count_include_actions +=1;
     }
 
-    PreprocessedInput{rom, code, defines}
+    let rom = rom.into_iter().map(|line| line.content).collect();
+
+    Ok(PreprocessedInput{rom, code, defines})
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn origin_of_parsed_code_test()
+    {
+        let input = vec!["_code", "; comment", "", ".start:", "  mov a, 1 ; comment", "  ret 0"];
+        let mut lines = into_source_lines(input.into_iter().map(|x| x.to_string()).collect(), "in.asm");
+
+        cleanup_input(&mut lines);
+        let (code,_) = split_sections([lines, into_source_lines(vec!["_rom".into()], "in.asm")].concat()).unwrap();
+
+        let mut labels = vec![];
+        let parsed = parse_code(code, &mut labels).unwrap();
+
+        assert_eq!(parsed.iter().map(|x| x.info.line).collect::<Vec<_>>(), vec![5,6]);
+        assert_eq!(parsed[0].info.file, "in.asm");
+        assert_eq!(labels[0].identifier, "start");
+    }
 }
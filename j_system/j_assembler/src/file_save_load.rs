@@ -2,9 +2,9 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 
-pub fn load_file(file_name: &str) -> Vec<String>
+pub fn read_lines(file_name: &str) -> Result<Vec<String>,String>
 {
-    let s = get_programm_from_fs(file_name);
+    let s = get_programm_from_fs(file_name)?;
     
     let mut ret =  vec![];
     for line in s.lines()
@@ -13,24 +13,24 @@ pub fn load_file(file_name: &str) -> Vec<String>
         ret.push(line.to_string());
     }
    
-    ret
+    Ok(ret)
 }
 
-fn get_programm_from_fs(file_name: &str) -> String
+fn get_programm_from_fs(file_name: &str) -> Result<String,String>
 {
     let path = Path::new(file_name);
     let display = path.display();
 
     let mut file = match File::open(&path) {
-        Err(why) => panic!("couldn't open {}: {}", display, why),
+        Err(why) => return Err(format!("couldn't open {}: {}", display, why)),
         Ok(file) => file,
     };
 
     let mut s = String::new();
     match file.read_to_string(&mut s){
-        Err(why) => panic!("couldn't read {}: {}", display, why),
+        Err(why) => return Err(format!("couldn't read {}: {}", display, why)),
         Ok(_) => {},
     };
     
-    s
+    Ok(s)
 }
\ No newline at end of file
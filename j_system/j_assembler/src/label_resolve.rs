@@ -8,11 +8,11 @@ pub fn remove_labels_from_asm(
     lable_table: &mut Vec<LabelPointer>, 
     defines: HashMap<String,u64>,
     debug_symbols: &mut Vec<(String,u64)>,
-    rom_size:u64) -> (Vec<AsmLine>, u64, Vec<u64>)
+    rom_size:u64) -> Result<(Vec<AsmLine>, u64, Vec<u64>),String>
 {
     let instruction_position = calc_sersed_code_positions(&code_with_labels, rom_size);
     let mut code_without_labels:Vec<AsmLine> = vec![];
-    let start_of_execution_ptr = label_to_adress("start".to_string(), lable_table, &instruction_position);
+    let start_of_execution_ptr = label_to_adress("start".to_string(), lable_table, &instruction_position)?;
 
     // get position of labels for debug output
     // INFO: the label tabel seems to hold the incorrect adress
@@ -20,7 +20,7 @@ pub fn remove_labels_from_asm(
     {
         // get the identifier/name of the label
         let name = lab.identifier.clone();
-        let addr = label_to_adress(name.clone(), lable_table, &instruction_position);
+        let addr = label_to_adress(name.clone(), lable_table, &instruction_position)?;
         debug_symbols.push((name,addr));
     }
     
@@ -28,8 +28,10 @@ pub fn remove_labels_from_asm(
     {
         let instr_type = instr.instruction;
         let info = instr.info;
-        let p1 = resolve_labels(&instr.param1, lable_table, &defines, &instruction_position);
-        let p2 = resolve_labels(&instr.param2, lable_table, &defines, &instruction_position);
+        let p1 = resolve_labels(&instr.param1, lable_table, &defines, &instruction_position)
+            .map_err(|e| format!("{}:{}: {}", info.file, info.line, e))?;
+        let p2 = resolve_labels(&instr.param2, lable_table, &defines, &instruction_position)
+            .map_err(|e| format!("{}:{}: {}", info.file, info.line, e))?;
 
         code_without_labels.push(
             AsmLine{
@@ -41,35 +43,41 @@ pub fn remove_labels_from_asm(
     }
 
     // returns code without labels and the adress where execution stars
-    (code_without_labels, start_of_execution_ptr, instruction_position)
+    Ok((code_without_labels, start_of_execution_ptr, instruction_position))
 }
 
-fn resolve_labels(param:& ParamOrLabel,lable_table: &Vec<LabelPointer>, defines: &HashMap<String,u64>, instruction_position:&Vec<u64>) -> Option<Param>
+fn resolve_labels(param:& ParamOrLabel,lable_table: &Vec<LabelPointer>, defines: &HashMap<String,u64>, instruction_position:&Vec<u64>) -> Result<Option<Param>,String>
 {
-    match param
+    Ok(match param
     {
         ParamOrLabel::Nothing                                   => None,
         ParamOrLabel::Param(x)                                  => Some(*x),
-        ParamOrLabel::Label(x,LabelUse::Raw)                    => Some(Param::Constant(label_to_adress(x.clone(), lable_table, instruction_position))),
-        ParamOrLabel::Label(x,LabelUse::Deref)                  => Some(Param::MemPtr(label_to_adress(x.clone(), lable_table, instruction_position))),
-        ParamOrLabel::Label(x,LabelUse::DerefOffset(offset))    => Some(Param::MemPtr((label_to_adress(x.clone(), lable_table, instruction_position)as i64 + offset)as u64)), //TODO: is this right? and check for save parse
-        ParamOrLabel::DefineLabel(x)                            => Some(Param::Constant(define_to_constant(x.clone(), defines)))
-    }
+        ParamOrLabel::Label(x,LabelUse::Raw)                    => Some(Param::Constant(label_to_adress(x.clone(), lable_table, instruction_position)?)),
+        ParamOrLabel::Label(x,LabelUse::Deref)                  => Some(Param::MemPtr(label_to_adress(x.clone(), lable_table, instruction_position)?)),
+        ParamOrLabel::Label(x,LabelUse::DerefOffset(offset))    =>
+        {
+            let adress = label_to_adress(x.clone(), lable_table, instruction_position)?;
+            let adress = (adress as i64).checked_add(*offset).filter(|adress| *adress >= 0)
+                .ok_or(format!("adress of .{} with offset {} is out of range", x, offset))?;
+            Some(Param::MemPtr(adress as u64))
+        },
+        ParamOrLabel::DefineLabel(x)                            => Some(Param::Constant(define_to_constant(x.clone(), defines)?))
+    })
 }
 
-fn define_to_constant(define_name: String, defines: &HashMap<String,u64>) -> u64
+fn define_to_constant(define_name: String, defines: &HashMap<String,u64>) -> Result<u64,String>
 {
     if let Some(val) = defines.get(&define_name)
     {
-        *val
+        Ok(*val)
     }
     else
     {
-        panic!("cound not find definition for {}", define_name)
+        Err(format!("cound not find definition for {}", define_name))
     }
 }
 
-fn label_to_adress(label_use: String, lable_table:& Vec<LabelPointer>, instruction_position:&Vec<u64>) -> u64
+fn label_to_adress(label_use: String, lable_table:& Vec<LabelPointer>, instruction_position:&Vec<u64>) -> Result<u64,String>
 {
     for label in lable_table
     {  
@@ -78,20 +86,18 @@ fn label_to_adress(label_use: String, lable_table:& Vec<LabelPointer>, instructi
             if label.label_type == LabelType::ArdressToRomData
             {
                 // the pos is the correct ptr to memory
-                return label.pos;
+                return Ok(label.pos);
             }
             else
             {
-                return instruction_position[label.pos as usize];
+                // a label after the last instruction points to nothing
+                return instruction_position.get(label.pos as usize).copied()
+                    .ok_or(format!("label {} is not followed by an instruction", label_use));
             }
         }
     }
 
-    // print label table:
-    print!("label table:\n");
-    lable_table.into_iter().for_each(|x| print!("{:#?}\n",x));
-    panic!("clound not find label: {}", label_use);
-
+    Err(format!("clound not find label: {}", label_use))
 }
 
 fn calc_sersed_code_positions(code: &Vec<AsmLineLabel>, start_of_code_section: u64) -> Vec<u64>
@@ -105,12 +111,8 @@ fn calc_sersed_code_positions(code: &Vec<AsmLineLabel>, start_of_code_section: u
 
     for inst in code
     {
-        // the instruction itself is always one vec-entry (u64) 
-        // add the size of both parameters
-        let size:u64 = 1+instruction_size(inst.param1.clone())+instruction_size(inst.param2.clone());
-        
         // add the size of the instruction to the last position
-        instruction_position.push(instruction_position[instruction_position.len()-1]+size);
+        instruction_position.push(instruction_position[instruction_position.len()-1]+instruction_len(inst));
     }
 
     // remove the last entry since it points to a instruction that does not exsist
@@ -119,6 +121,13 @@ fn calc_sersed_code_positions(code: &Vec<AsmLineLabel>, start_of_code_section: u
     instruction_position
 }
 
+/// amount of values of the serialized instruction
+pub fn instruction_len(inst: &AsmLineLabel) -> u64
+{
+    // the instruction itself is always one vec-entry (u64) 
+    // add the size of both parameters
+    1+instruction_size(inst.param1.clone())+instruction_size(inst.param2.clone())
+}
 
 fn instruction_size(ins: ParamOrLabel) -> u64
 {
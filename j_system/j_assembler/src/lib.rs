@@ -0,0 +1,9 @@
+extern crate j_system_definition;
+pub mod decode_instructons;
+pub mod label_resolve;
+pub mod serialization;
+pub mod type_cov_parse;
+pub mod assembler;
+pub mod file_save_load;
+pub mod debug;
+pub mod preprocessor;
@@ -1,31 +1,53 @@
-extern crate j_system_definition;
-mod decode_instructons;
-mod label_resolve;
-mod serialization;
-mod type_cov_parse;
-mod assembler;
-mod file_save_load;
-mod debug;
-mod preprocessor;
+use j_assembler::file_save_load::*;
+use j_assembler::assembler::*;
+use j_assembler::debug::*;
 
-use crate::file_save_load::*;
-use crate::assembler::*;
-use crate::preprocessor::*;
+use std::fs;
+use std::process::exit;
 
+/// `--rom-base ADRESS` and `--code-base ADRESS` place the sections,
+/// by default the rom starts at 0 and the code directly after it
+fn parse_bases(args: &[String]) -> Result<SectionBases,String>
+{
+    let mut bases = SectionBases::default();
+    let mut args = args.iter();
 
+    while let Some(arg) = args.next()
+    {
+        let mut value = || args.next().and_then(|val| val.parse::<u64>().ok()).ok_or(format!("{} needs an adress", arg));
+        match arg.as_str()
+        {
+            "--rom-base" => bases.rom = value()?,
+            "--code-base" => bases.code = Some(value()?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
 
-use std::fs;
+    Ok(bases)
+}
 
 fn main() {
     let main_file_name = "./in.asm";
     
-    let file = load_file(main_file_name);
-    
-    let fin = assemble_into_u64_vec(file, main_file_name.to_string());
+    let args:Vec<String> = std::env::args().skip(1).collect();
+    let assembly = parse_bases(&args).and_then(|bases|
+        read_lines(main_file_name).and_then(|file| assemble_with_bases(file, main_file_name.to_string(), &[], bases)));
+    let assembly = match assembly
+    {
+        Ok(assembly) => assembly,
+        Err(e) =>
+        {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    // create debug output
+    debug_ouput(&assembly);
     
     let mut result = String::new(); 
 
-    for x in fin
+    for x in &assembly.binary
     {
         result.push_str(&format!("{:0>20}\n",x));
     }
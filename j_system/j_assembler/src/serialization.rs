@@ -85,7 +85,7 @@ pub enum RomDataType
     StringArray,
 }
 
-pub fn serialize_rom(r: String,teip: RomDataType) -> Vec<u64>
+pub fn serialize_rom(r: String,teip: RomDataType) -> Result<Vec<u64>,String>
 {
     match teip
     {
@@ -95,14 +95,16 @@ pub fn serialize_rom(r: String,teip: RomDataType) -> Vec<u64>
         {
             let mut res = vec![]; 
             // remove "[" and "]"
-            let seperated_ints = r[1..r.len()-1].split(',');
+            let seperated_ints = strip_delimiters(&r, '[', ']')
+                .ok_or(format!("could not parse {} as int array",r))?
+                .split(',');
 
             for e in seperated_ints
             {
                 let element =  e.split_whitespace().filter(|x| x.len()>0).collect::<Vec<&str>>();
 
-                // panic of the format is wrong
-                if element.len() != 1 {panic!("could not parse {} as int array",r)}
+                // the format is wrong
+                if element.len() != 1 {return Err(format!("could not parse {} as int array",r))}
 
                 if let Ok(found_int) = element[0].parse::<u64>()
                 {
@@ -115,19 +117,21 @@ pub fn serialize_rom(r: String,teip: RomDataType) -> Vec<u64>
             {
                 if c & 0x80!= 0
                 {
-                    panic!("only ascii chars are supported");
+                    return Err("only ascii chars are supported".to_string());
                 }
             }
             
-            res
+            Ok(res)
         },
         
         RomDataType::StringArray     => 
         {
             let mut res = vec![];
-            for element in r[1..r.len()-1].split(',')
+            let elements = strip_delimiters(&r, '[', ']').ok_or(format!("could not parse {} as string array",r))?;
+            for element in elements.split(',')
             {
-                res.append(&mut element.split('"').collect::<Vec<&str>>()[1].as_bytes().iter().map(|x| *x as u64).collect::<Vec<_>>());
+                let string = element.split('"').nth(1).ok_or(format!("could not parse {} as string array",r))?;
+                res.append(&mut string.as_bytes().iter().map(|x| *x as u64).collect::<Vec<_>>());
                 
                 // null terminate the string
                 res.push(0);
@@ -139,11 +143,11 @@ pub fn serialize_rom(r: String,teip: RomDataType) -> Vec<u64>
                 // TODO: this is wrong!!!???
                 if c & 0x80 != 0
                 {
-                    panic!("only ascii chars are supported");
+                    return Err("only ascii chars are supported".to_string());
                 }
             }
 
-            res
+            Ok(res)
         }
     }
 
@@ -170,23 +174,29 @@ pub fn serialize_rom(r: String,teip: RomDataType) -> Vec<u64>
     */
 }
 
-fn string_to_vec_u64(s: String) -> Vec<u64> 
+/// the content between the first and the last char, if they are open and close
+fn strip_delimiters(s: &str, open: char, close: char) -> Option<&str>
 {
-    if s.chars().nth(0) == Some('"') && s.chars().nth(s.len()-1) == Some('"') 
+    s.strip_prefix(open)?.strip_suffix(close)
+}
+
+fn string_to_vec_u64(s: String) -> Result<Vec<u64>,String> 
+{
+    if let Some(content) = strip_delimiters(&s, '"', '"') 
     {
-        let mut ret = s[1..s.len()-1].to_owned().as_bytes().into_iter().map(|x| *x as u64).collect::<Vec<_>>();
+        let mut ret = content.as_bytes().into_iter().map(|x| *x as u64).collect::<Vec<_>>();
         
         // null terminate the string
         ret.push(0);
-        ret
+        Ok(ret)
     } 
     else
     {
-        panic!("could not parse {} as a string", s)
+        Err(format!("could not parse {} as a string", s))
     }
 }
 
-fn number_to_vec_u64(s: String) -> Vec<u64>
+fn number_to_vec_u64(s: String) -> Result<Vec<u64>,String>
 {
     // bitwise conversion that allows any number that fits
     // inside a i64 
@@ -194,11 +204,11 @@ fn number_to_vec_u64(s: String) -> Vec<u64>
 
     if let Ok(found_int) = s.parse::<u64>()
     {
-        return vec![found_int];
+        Ok(vec![found_int])
     }
     else
     {
-        panic!("could not parse {} as a number", s);
+        Err(format!("could not parse {} as a number", s))
     }
 }
 
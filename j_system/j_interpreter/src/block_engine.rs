@@ -0,0 +1,463 @@
+use crate::machine::{MachineState,InstructionReturn,Exec};
+use crate::check_instruction::is_writable;
+
+use j_system_definition::instructions::{AsmLine,Param,InstructionEnum};
+use j_system_definition::register::Register;
+
+use std::collections::{HashMap,HashSet};
+
+/// maximum number of instructions in a block
+const MAX_BLOCK_SIZE: usize = 64;
+
+/// an instruction is at most 3 values long (instruction and 2 parameters)
+const MAX_INSTRUCTION_SIZE: u64 = 3;
+
+type Closure = Box<dyn Fn(&mut MachineState) -> InstructionReturn>;
+
+/// how the instructions get executed
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Engine
+{
+    /// decode and run one instruction after another
+    Interpreter,
+
+    /// run translated basic blocks, see `BlockEngine`
+    Blocks,
+}
+
+impl Engine
+{
+    pub fn parse(s: &str) -> Result<Self,String>
+    {
+        match s
+        {
+            "interpreter"   => Ok(Engine::Interpreter),
+            "blocks"        => Ok(Engine::Blocks),
+            _               => Err(format!("unknown engine: {} (use interpreter or blocks)", s)),
+        }
+    }
+
+    /// the block engine to set in the machine
+    pub fn block_engine(self) -> Option<BlockEngine>
+    {
+        match self
+        {
+            Engine::Interpreter => None,
+            Engine::Blocks => Some(BlockEngine::new()),
+        }
+    }
+}
+
+/// runs the programm as basic blocks that are translated to closures.
+///
+/// A block starts at a jump target and ends after a jump, `call`, `ret` or `sys`.
+/// Every instruction of a block is a closure with the decoded parameters bound to it,
+/// so running a block does not decode anything. A block remembers the blocks it jumped
+/// to, so the next block is found without a lookup.
+///
+/// Writes to the code section remove the blocks that contain the written adress, the
+/// instructions at the adress are always run by the interpreter after that
+pub struct BlockEngine
+{
+    max_block_size: usize,
+
+    blocks: Vec<Block>,
+
+    /// Key: adress of the first instruction, index in `blocks`
+    starts: HashMap<u64,usize>,
+
+    /// adresses of instructions that may have been overwritten
+    interpreted: HashSet<u64>,
+
+    /// the block that ran last, jumps from it are linked to the next block
+    previous: Option<usize>,
+
+    /// amount of instructions that were started, including the ones that failed
+    executed: u64,
+}
+
+struct Block
+{
+    /// adress of the first instruction
+    start: u64,
+
+    /// adress after the last instruction
+    end: u64,
+
+    ops: Vec<Op>,
+
+    /// the blocks that followed this block: (pc, index in `blocks`)
+    links: [Option<(u64,usize)>;2],
+
+    /// false if the code of the block was overwritten
+    valid: bool,
+}
+
+struct Op
+{
+    pc: u64,
+    next: u64,
+
+    /// cycles of the instruction, `None` if it depends on the state (`sys`)
+    cost: Option<u64>,
+
+    inst: AsmLine,
+    exec: Closure,
+}
+
+impl Default for BlockEngine
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl BlockEngine
+{
+    pub fn new() -> Self
+    {
+        Self::with_max_block_size(MAX_BLOCK_SIZE)
+    }
+
+    pub fn with_max_block_size(max_block_size: usize) -> Self
+    {
+        Self{max_block_size: max_block_size.max(1), blocks: vec![], starts: HashMap::new(), interpreted: HashSet::new(), previous: None, executed: 0}
+    }
+
+    /// amount of instructions that were started, including the ones that failed.
+    /// Used to compare the engine with the interpreter at the end of every block
+    pub fn executed(&self) -> u64
+    {
+        self.executed
+    }
+
+    /// removes all blocks. Has to be called when new code is loaded
+    pub fn reset(&mut self)
+    {
+        *self = Self::with_max_block_size(self.max_block_size);
+    }
+
+    /// runs the programm until it ends
+    pub fn run(&mut self, machine: &mut MachineState) -> Result<(),String>
+    {
+        while !self.step_block(machine)?
+        {
+        }
+        Ok(())
+    }
+
+    /// runs the block `pc` points to, or a single instruction if it can't be
+    /// in a block. Returns true if the machine ended
+    pub fn step_block(&mut self, machine: &mut MachineState) -> Result<bool,String>
+    {
+        let pc = machine.reg_state.read(Register::pc);
+
+        let index = match self.find_block(machine, pc)
+        {
+            Some(index) => index,
+            None =>
+            {
+                self.previous = None;
+                self.executed += 1;
+                let ended = machine.step()?;
+                self.invalidate_written(machine);
+                return Ok(ended);
+            },
+        };
+
+        self.previous = Some(index);
+        self.run_block(machine, index)
+    }
+
+    /// the block that starts at the adress, translates it if needed
+    fn find_block(&mut self, machine: &mut MachineState, pc: u64) -> Option<usize>
+    {
+        // follow the link of the previous block
+        if let Some(previous) = self.previous
+        {
+            let linked = self.blocks[previous].links.iter().flatten()
+                .find(|(target,index)| *target == pc && self.blocks[*index].valid)
+                .map(|(_,index)| *index);
+
+            if linked.is_some()
+            {
+                return linked;
+            }
+        }
+
+        let index = match self.starts.get(&pc)
+        {
+            Some(index) => *index,
+            None => self.translate(machine, pc)?,
+        };
+
+        if let Some(previous) = self.previous
+        {
+            let links = &mut self.blocks[previous].links;
+            // replace the older link if both are used
+            let slot = links.iter().position(|link| link.is_none()).unwrap_or(1);
+            if slot == 1 && links[0].is_some() && links[1].is_some()
+            {
+                links[0] = links[1];
+            }
+            links[slot] = Some((pc,index));
+        }
+        Some(index)
+    }
+
+    /// decodes the instructions from the adress to the end of the block
+    fn translate(&mut self, machine: &mut MachineState, start: u64) -> Option<usize>
+    {
+        let code_start = machine.mem_state.get_code_base_ptr();
+        let code_end = code_start + machine.mem_state.get_code_size();
+
+        let mut ops = vec![];
+        let mut pc = start;
+
+        // code outside of the code section may change without being noticed
+        while ops.len() < self.max_block_size && pc >= code_start && pc < code_end && !self.interpreted.contains(&pc)
+        {
+            let (inst,next) = match machine.decode_cache.fetch(&mut machine.mem_state, pc)
+            {
+                Some(decoded) => decoded,
+                None => break,
+            };
+
+            let ends_block = ends_block(inst.instruction);
+            let cost = match inst.instruction
+            {
+                InstructionEnum::sys => None,
+                _ => Some(machine.cost_model.cost(&inst, None)),
+            };
+
+            ops.push(Op{pc, next, cost, exec: translate_instruction(&inst), inst});
+            pc = next;
+
+            if ends_block
+            {
+                break;
+            }
+        }
+
+        if ops.is_empty()
+        {
+            return None;
+        }
+
+        self.blocks.push(Block{start, end: pc, ops, links: [None;2], valid: true});
+        self.starts.insert(start, self.blocks.len()-1);
+        Some(self.blocks.len()-1)
+    }
+
+    fn run_block(&mut self, machine: &mut MachineState, index: usize) -> Result<bool,String>
+    {
+        for op in &self.blocks[index].ops
+        {
+            self.executed += 1;
+
+            if machine.machine_information.cycles_exhausted()
+            {
+                return Err("cycle count surpassed".into());
+            }
+
+            let cost = match op.cost
+            {
+                Some(cost) => cost,
+                None => machine.instruction_cost(&op.inst),
+            };
+
+            machine.next_ptr = op.next;
+            let ret = (op.exec)(machine);
+
+            if machine.apply_return(op.pc, ret)?
+            {
+                return Ok(true);
+            }
+            machine.advance_cycles(cost);
+
+            // the rest of the block may have been overwritten
+            if machine.mem_state.has_code_writes()
+            {
+                self.invalidate_written(machine);
+                return Ok(false);
+            }
+        }
+        Ok(false)
+    }
+
+    /// removes the blocks that contain written adresses and runs the
+    /// instructions at the adresses in the interpreter from now on
+    fn invalidate_written(&mut self, machine: &mut MachineState)
+    {
+        if !machine.mem_state.has_code_writes()
+        {
+            return;
+        }
+
+        let writes = machine.mem_state.take_code_writes();
+        machine.decode_cache.invalidate(&writes);
+
+        for written in writes
+        {
+            for addr in written.saturating_sub(MAX_INSTRUCTION_SIZE-1)..=written
+            {
+                self.interpreted.insert(addr);
+            }
+
+            for block in self.blocks.iter_mut().filter(|block| block.valid && block.start <= written && written < block.end)
+            {
+                block.valid = false;
+                self.starts.remove(&block.start);
+            }
+        }
+        self.previous = None;
+    }
+}
+
+fn ends_block(instruction: InstructionEnum) -> bool
+{
+    matches!(instruction, InstructionEnum::jmp | InstructionEnum::je | InstructionEnum::jeg | InstructionEnum::jel
+        | InstructionEnum::jg | InstructionEnum::jl | InstructionEnum::call | InstructionEnum::ret | InstructionEnum::sys)
+}
+
+/// the bits of `s` a conditional jump tests
+fn jump_mask(instruction: InstructionEnum) -> Option<u64>
+{
+    match instruction
+    {
+        InstructionEnum::je => Some(1<<3),
+        InstructionEnum::jl => Some(1<<1),
+        InstructionEnum::jg => Some(1<<2),
+        InstructionEnum::jel => Some(1<<3 | 1<<1),
+        InstructionEnum::jeg => Some(1<<3 | 1<<2),
+        _ => None,
+    }
+}
+
+/// the closure that runs the instruction. The common instructions with registers and
+/// constants are specialized, all others call the interpreter
+fn translate_instruction(inst: &AsmLine) -> Closure
+{
+    match (inst.instruction, inst.get_param1(), inst.get_param2())
+    {
+        (InstructionEnum::mov, Some(Param::Register(dest)), Some(Param::Constant(val))) if is_writable(dest) =>
+            Box::new(move |machine| {machine.reg_state.store(dest, val); InstructionReturn::Next}),
+
+        (InstructionEnum::mov, Some(Param::Register(dest)), Some(Param::Register(src))) if is_writable(dest) =>
+            Box::new(move |machine| {machine.reg_state.store(dest, machine.reg_state.read(src)); InstructionReturn::Next}),
+
+        (InstructionEnum::add, Some(Param::Register(dest)), Some(Param::Constant(val))) if is_writable(dest) =>
+            Box::new(move |machine| {machine.reg_state.store(dest, machine.reg_state.read(dest).wrapping_add(val)); InstructionReturn::Next}),
+
+        (InstructionEnum::jmp, Some(Param::Constant(target)), None) =>
+            Box::new(move |_| InstructionReturn::JumpTo(target)),
+
+        (instruction, Some(Param::Constant(target)), None) if jump_mask(instruction).is_some() =>
+        {
+            let mask = jump_mask(instruction).unwrap();
+            Box::new(move |machine| match machine.reg_state.read(Register::s) & mask
+            {
+                0 => InstructionReturn::Next,
+                _ => InstructionReturn::JumpTo(target),
+            })
+        },
+
+        _ =>
+        {
+            let inst = inst.clone();
+            Box::new(move |machine| machine.run_instruction(inst.clone()))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::MachineInitInfo;
+    use crate::snapshot;
+    use crate::test_util::{assembled_machine,machine_with};
+
+    /// runs the machine with the block engine and with the interpreter and compares
+    /// the state after every block
+    fn lockstep(machine: impl Fn(MachineInitInfo) -> MachineState, max_block_size: usize) -> MachineState
+    {
+        let load = ||
+        {
+            let mut machine = machine(MachineInitInfo{seed: Some(1), ..Default::default()});
+            machine.reg_state.store_to_read_only(Register::pc, machine.next_ptr);
+            machine
+        };
+
+        let mut engine = BlockEngine::with_max_block_size(max_block_size);
+        let (mut blocks, mut reference) = (load(), load());
+
+        loop
+        {
+            let ended = engine.step_block(&mut blocks).unwrap();
+
+            let mut reference_ended = false;
+            while !reference_ended && reference.machine_information.get_cycles() < blocks.machine_information.get_cycles()
+            {
+                reference_ended = reference.step().unwrap();
+            }
+            if ended
+            {
+                reference_ended = reference.step().unwrap();
+            }
+
+            assert_eq!(ended, reference_ended);
+            assert_eq!(snapshot::save(&blocks), snapshot::save(&reference));
+
+            if ended
+            {
+                return blocks;
+            }
+        }
+    }
+
+    #[test]
+    fn lockstep_test()
+    {
+        let source = "
+_rom
+_code
+    .start:
+        mov a, 0
+    .loop:
+        call .double
+        add a, 1
+        cmp a, 3
+        jl .loop
+        push 9
+        sys
+
+    .double:
+        add b, 2
+        mov c, b
+        ret 0
+";
+
+        for max_block_size in [1, 2, 64]
+        {
+            let machine = lockstep(|init| assembled_machine(init, source), max_block_size);
+            assert_eq!(machine.reg_state.read(Register::a), 3);
+            assert_eq!(machine.reg_state.read(Register::c), 6);
+        }
+    }
+
+    #[test]
+    fn self_modifying_code_test()
+    {
+        // loop: mov [4], 0x0121_0000_0001_0000; mov a, 5 (becomes add a, 5)
+        //       add b, 1; cmp b, 3; jl loop
+        //       push 9; sys (End)
+        let code = vec![0x0F31_0000_0000_0000, 4, 0x0121_0000_0001_0000, 0x0F21_0000_0001_0000, 5,
+                        0x0121_0000_0002_0000, 1, 0x0921_0000_0002_0000, 3, 0x0E10_0000_0000_0000, 1,
+                        0x1010_0000_0000_0000, 9, 0x1600_0000_0000_0000];
+
+        let machine = lockstep(|init| machine_with(init, &code), 64);
+        assert_eq!(machine.reg_state.read(Register::a), 15);
+    }
+}
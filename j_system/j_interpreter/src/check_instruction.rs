@@ -29,6 +29,34 @@ pub fn is_register(param: &Option<Param>) -> bool
     false
 }
 
+/// registers that can be written by `store`
+pub fn is_writable(reg: Register) -> bool
+{
+    !matches!(reg, Register::pc | Register::s)
+}
+
+/// checks that an instruction that stores its result in the first parameter
+/// has a destination it can write to
+pub fn check_destination(inst: &AsmLine) -> Result<(),String>
+{
+    let writes_param1 = matches!(inst.instruction, InstructionEnum::add | InstructionEnum::sub | InstructionEnum::xor
+        | InstructionEnum::or | InstructionEnum::and | InstructionEnum::shr | InstructionEnum::shl
+        | InstructionEnum::mov | InstructionEnum::pop);
+
+    if !writes_param1
+    {
+        return Ok(());
+    }
+
+    match inst.param1
+    {
+        None => Err(format!("{} needs a destination", ins_as_string(inst.instruction))),
+        Some(Param::Register(reg)) if !is_writable(reg) => Err(format!("{:?} is a read only register", reg)),
+        Some(Param::Constant(_)) => Err("a constant can't be a destination".to_string()),
+        _ => Ok(()),
+    }
+}
+
 pub trait StateHelperFunctions
 {
     fn store_in_dest(&mut self, val:u64, param: &Option<Param>);
@@ -50,8 +78,10 @@ impl StateHelperFunctions for MachineState {
                 
                 Param::MemPtr(dest) => 
                 {
-                    // TODO: handle result
-                    self.mem_state.store(val, *dest).unwrap()
+                    if let Err(x) = self.mem_state.store(val, *dest)
+                    {
+                        self.fault = Some(x);
+                    }
                 },
 
                 Param::MemPtrOffset(reg,offset) =>
@@ -59,20 +89,20 @@ impl StateHelperFunctions for MachineState {
                     let addr = self.reg_state.read(*reg) as i128 + *offset as i128;
                     
                     // TODO: check the i128 to u64 conversion for loss
-                    self.mem_state.store(val, addr as u64).unwrap()
+                    if let Err(x) = self.mem_state.store(val, addr as u64)
+                    {
+                        self.fault = Some(x);
+                    }
                 }
 
-                Param::Constant(_) => 
-                {
-                    panic!("a constant can't be a destination");
-                    //crate::output::dump_and_panic(format!("a constant can't be a destination"), register_state, stack_state);
-                },
+                // `check_destination` rejects these before the instruction runs
+                Param::Constant(_) => self.fault = Some("a constant can't be a destination".into()),
                 //Param::ParamStack(z) => {return stack_state[*z as usize]},
             }
         }
         else
         {
-            panic!("no destination defined");
+            self.fault = Some("no destination defined".into());
             //crate::output::dump_and_panic(format!("no destination defined"), register_state, stack_state);
         }
     }
@@ -83,9 +113,9 @@ impl StateHelperFunctions for MachineState {
 
         let tos = self.reg_state.read(Register::tos);
 
-        let ret = self.mem_state.read(tos).unwrap();
+        let ret = self.mem_state.read(tos)?;
 
-        if tos == self.mem_state.get_mem_size()-1
+        if tos == self.mem_state.get_stack_top()
         {
             self.reg_state.store(Register::tos, 0);
         }
@@ -102,13 +132,18 @@ impl StateHelperFunctions for MachineState {
 
         if self.reg_state.read(Register::tos) == 0
         {
-            self.mem_state.store(val, self.mem_state.get_mem_size()-1).unwrap();
-            self.reg_state.store(Register::tos, self.mem_state.get_mem_size()-1);
+            self.mem_state.store(val, self.mem_state.get_stack_top())?;
+            self.reg_state.store(Register::tos, self.mem_state.get_stack_top());
             return Ok(());
         }
 
+        if self.reg_state.read(Register::tos) <= self.mem_state.get_stack_limit()
+        {
+            return Err("stack overflow".into());
+        }
+
         self.reg_state.change(Register::tos, |x| x-1);
-        self.mem_state.store(val, self.reg_state.read(Register::tos)).unwrap();
+        self.mem_state.store(val, self.reg_state.read(Register::tos))?;
     
 
         Ok(())
@@ -123,17 +158,21 @@ impl StateHelperFunctions for MachineState {
                 Param::Register(z)         => self.reg_state.read(*z),
                 Param::Constant(z)              => *z,
 
-                // TODO: do not just unwrap but check if is Some(..)
-                // TODO: check for vaild index
-                Param::MemPtr(z)            => self.mem_state.read(*z).unwrap(), 
+                // a failed read stops the machine after the instruction (see `MachineState::fault`)
+                Param::MemPtr(z)            => self.mem_state.read(*z).unwrap_or_else(|x| {self.fault = Some(x); 0}), 
                 // TODO: check for save conversion
-                Param::MemPtrOffset(reg,of) => self.mem_state.read((self.reg_state.read(*reg) as i64+*of) as u64).expect(&format!("could not read form {}",self.reg_state.read(*reg) as i64+*of))
+                Param::MemPtrOffset(reg,of) => 
+                {
+                    let addr = (self.reg_state.read(*reg) as i64).wrapping_add(*of) as u64;
+                    self.mem_state.read(addr).unwrap_or_else(|x| {self.fault = Some(format!("could not read from {}: {}", addr, x)); 0})
+                }
             }
         }
         else
         {
             //crate::output::dump_and_panic(format!(""), register_state, stack_state);
-            panic!("existence of a second parameter should already be checked!");
+            self.fault = Some("missing parameter".into());
+            0
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+use crate::syscall::SysCallType;
+
+use j_system_definition::instructions::{AsmLine,Param,InstructionEnum,ins_as_string};
+
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::fs;
+
+/// the instructions in the order of `InstructionEnum`
+const INSTRUCTIONS: [InstructionEnum;22] = [
+    InstructionEnum::add, InstructionEnum::sub, InstructionEnum::xor, InstructionEnum::or, InstructionEnum::and,
+    InstructionEnum::shr, InstructionEnum::shl, InstructionEnum::jmp, InstructionEnum::cmp, InstructionEnum::je,
+    InstructionEnum::jeg, InstructionEnum::jel, InstructionEnum::jg, InstructionEnum::jl, InstructionEnum::mov,
+    InstructionEnum::push, InstructionEnum::pop, InstructionEnum::pusha, InstructionEnum::popa, InstructionEnum::call,
+    InstructionEnum::ret, InstructionEnum::sys,
+];
+
+/// the amount of cycles an instruction takes.
+///
+/// Gets loaded from a json file, all fields are optional:
+/// ```text
+/// {
+///     "default": 1,
+///     "instructions": {"mov": 1, "call": 3, "sys": 5},
+///     "memory": 2,
+///     "syscalls": {"Malloc": 40, "PrintToStdOut": 100, "20": 10}
+/// }
+/// ```
+/// `memory` is added for every parameter that reads or writes memory (`[a]`, `[tos+1]`),
+/// `syscalls` is added to the cost of `sys`. Syscalls are named like in `SysCallType`
+/// or by their number. The default model counts every instruction as 1 cycle
+#[derive(Clone,Debug,PartialEq)]
+pub struct CostModel
+{
+    /// cost of the instructions that are not in `instructions`
+    default: u64,
+
+    /// the costs that are set in the file.
+    /// Index: the instruction as `usize`, so no lookup is needed for every instruction
+    instructions: [Option<u64>;INSTRUCTIONS.len()],
+
+    /// extra cost of every memory parameter
+    memory: u64,
+
+    /// extra cost of the syscalls. Key: syscall number
+    syscalls: HashMap<u64,u64>,
+}
+
+impl Default for CostModel
+{
+    fn default() -> Self
+    {
+        Self{default: 1, instructions: [None;INSTRUCTIONS.len()], memory: 0, syscalls: HashMap::new()}
+    }
+}
+
+impl CostModel
+{
+    pub fn parse(input: &str) -> Result<Self,String>
+    {
+        let input:Value = serde_json::from_str(input).map_err(|e| format!("invalid cost model: {}", e))?;
+        let fields = input.as_object().ok_or("the cost model has to be a json object")?;
+
+        let mut model = CostModel::default();
+
+        for (key,val) in fields
+        {
+            match key.as_str()
+            {
+                "default" => model.default = cost(key, val)?,
+                "memory" => model.memory = cost(key, val)?,
+                "instructions" => for (name,val) in entries(key, val)?
+                {
+                    let instruction = INSTRUCTIONS.iter().find(|ins| ins_as_string(**ins) == *name)
+                        .ok_or(format!("unknown instruction in the cost model: {}", name))?;
+                    model.instructions[*instruction as usize] = Some(cost(name, val)?);
+                },
+                "syscalls" => for (name,val) in entries(key, val)?
+                {
+                    let number = SysCallType::ALL.iter().find(|sys| format!("{:?}", sys) == *name).map(|sys| sys.number())
+                        .or_else(|| name.parse().ok())
+                        .ok_or(format!("unknown syscall in the cost model: {}", name))?;
+                    model.syscalls.insert(number, cost(name, val)?);
+                },
+                _ => return Err(format!("unknown field in the cost model: {}", key)),
+            }
+        }
+        Ok(model)
+    }
+
+    pub fn load(file: &str) -> Result<Self,String>
+    {
+        let input = fs::read_to_string(file).map_err(|e| format!("could not read {}: {}", file, e))?;
+        Self::parse(&input)
+    }
+
+    /// the cycles of the instruction. `syscall` is the number of the syscall if the instruction is `sys`
+    pub fn cost(&self, inst: &AsmLine, syscall: Option<u64>) -> u64
+    {
+        let base = self.instructions.get(inst.instruction as usize).copied().flatten().unwrap_or(self.default);
+
+        let memory_params = [inst.get_param1(), inst.get_param2()].iter()
+            .filter(|param| matches!(param, Some(Param::MemPtr(_)) | Some(Param::MemPtrOffset(..))))
+            .count() as u64;
+
+        let syscall = syscall.and_then(|number| self.syscalls.get(&number)).copied().unwrap_or(0);
+
+        // huge costs in the file end the machine instead of overflowing
+        base.saturating_add(memory_params.saturating_mul(self.memory)).saturating_add(syscall)
+    }
+}
+
+fn cost(name: &str, val: &Value) -> Result<u64,String>
+{
+    val.as_u64().ok_or(format!("the cost of {} has to be a positive integer", name))
+}
+
+fn entries<'a>(name: &str, val: &'a Value) -> Result<&'a serde_json::Map<String,Value>,String>
+{
+    val.as_object().ok_or(format!("{} has to be a json object", name))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::MachineInitInfo;
+    use crate::test_util::assembled_machine;
+
+    #[test]
+    fn parse_cost_model_test()
+    {
+        let model = CostModel::parse(r#"{"memory": 2, "instructions": {"push": 3}, "syscalls": {"End": 10, "20": 4}}"#).unwrap();
+
+        assert_eq!(model.default, 1);
+        assert_eq!(model.instructions[InstructionEnum::push as usize], Some(3));
+        assert_eq!(model.instructions[InstructionEnum::pop as usize], None);
+        assert_eq!(model.syscalls[&9], 10);
+        assert_eq!(model.syscalls[&20], 4);
+
+        assert!(CostModel::parse(r#"{"instructions": {"mvo": 1}}"#).unwrap_err().contains("mvo"));
+        assert!(CostModel::parse(r#"{"syscalls": {"Exit": 1}}"#).unwrap_err().contains("Exit"));
+        assert!(CostModel::parse(r#"{"memroy": 1}"#).is_err());
+        assert!(CostModel::parse(r#"{"default": -1}"#).is_err());
+
+        // the costs don't overflow
+        let model = CostModel::parse(&format!(r#"{{"memory": {}, "syscalls": {{"End": {}}}}}"#, u64::MAX, u64::MAX)).unwrap();
+        let inst = AsmLine{instruction: InstructionEnum::sys, param1: Some(Param::MemPtr(1)), param2: Some(Param::MemPtr(2)), line: 0};
+        assert_eq!(model.cost(&inst, Some(9)), u64::MAX);
+
+        // the costs are indexed by the instruction
+        assert!(INSTRUCTIONS.iter().enumerate().all(|(ii,ins)| *ins as usize == ii));
+    }
+
+    #[test]
+    fn weighted_cycles_test()
+    {
+        let run = |model: CostModel, max_cycles: u128|
+        {
+            let mut machine = assembled_machine(MachineInitInfo{max_cycles, ..Default::default()}, "
+_rom
+_code
+    .start:
+        mov a, 5
+        push a
+        mov b, [tos]
+        push 9
+        sys
+");
+            machine.cost_model = model;
+            machine.run().map(|_| machine.machine_information.get_cycles())
+        };
+
+        // the last instruction is not counted since the machine ends with it
+        assert_eq!(run(CostModel::default(), 100), Ok(4));
+
+        let model = CostModel::parse(r#"{"memory": 5, "instructions": {"push": 2}, "syscalls": {"End": 50}}"#).unwrap();
+        // mov + push + (mov + memory) + push
+        assert_eq!(run(model.clone(), 100), Ok(1 + 2 + 6 + 2));
+        assert_eq!(run(model, 5), Err("cycle count surpassed".into()));
+    }
+}
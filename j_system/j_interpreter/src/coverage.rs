@@ -0,0 +1,256 @@
+use crate::deserialization::deserialize_asm;
+use crate::line_table::LineTable;
+use crate::memory::MemModel;
+
+use j_system_definition::instructions::InstructionEnum;
+
+use std::collections::{BTreeMap,HashMap};
+
+/// records which instructions were executed and which conditional jumps were taken
+#[derive(Default)]
+pub struct Coverage
+{
+    /// executions per instruction adress
+    hits: HashMap<u64,u64>,
+
+    /// Key: adress of the conditional jump, (taken, not taken)
+    branches: HashMap<u64,(u64,u64)>,
+}
+
+/// the coverage of one source file
+#[derive(Default)]
+struct FileCoverage
+{
+    /// Key: line, executions of the line
+    lines: BTreeMap<u64,u64>,
+
+    /// Key: line, (taken, not taken) of the conditional jumps in the line.
+    /// `None` if the jump was never executed
+    branches: BTreeMap<u64,Vec<Option<(u64,u64)>>>,
+}
+
+impl FileCoverage
+{
+    fn lines_hit(&self) -> usize
+    {
+        self.lines.values().filter(|count| **count > 0).count()
+    }
+
+    /// every conditional jump has 2 branches
+    fn branches_found(&self) -> usize
+    {
+        self.branches.values().map(|jumps| jumps.len()*2).sum()
+    }
+
+    fn branches_hit(&self) -> usize
+    {
+        self.branches.values().flatten().flatten()
+            .map(|(taken,not_taken)| (*taken > 0) as usize + (*not_taken > 0) as usize)
+            .sum()
+    }
+}
+
+fn is_conditional_jump(instruction: InstructionEnum) -> bool
+{
+    matches!(instruction, InstructionEnum::je | InstructionEnum::jeg | InstructionEnum::jel | InstructionEnum::jg | InstructionEnum::jl)
+}
+
+impl Coverage
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// records an executed instruction. `jumped` is true if the instruction jumped
+    pub fn record(&mut self, pc: u64, instruction: InstructionEnum, jumped: bool)
+    {
+        *self.hits.entry(pc).or_insert(0) += 1;
+
+        if is_conditional_jump(instruction)
+        {
+            let (taken,not_taken) = self.branches.entry(pc).or_insert((0,0));
+            match jumped
+            {
+                true => *taken += 1,
+                false => *not_taken += 1,
+            }
+        }
+    }
+
+    /// maps the adresses to the source lines.
+    /// Key: path of the source file
+    fn files(&self, lines: &LineTable, mem: &MemModel) -> BTreeMap<String,FileCoverage>
+    {
+        let mut files:BTreeMap<String,FileCoverage> = BTreeMap::new();
+
+        for (addr,loc) in lines.instructions()
+        {
+            let file = files.entry(lines.source_path(loc).to_string_lossy().into_owned()).or_default();
+            let count = self.hits.get(&addr).copied().unwrap_or(0);
+
+            // a line with multiple instructions counts the executions of the most executed one
+            let line = file.lines.entry(loc.line).or_insert(0);
+            *line = (*line).max(count);
+
+            if matches!(deserialize_asm(mem, addr), Some((inst,_)) if is_conditional_jump(inst.instruction))
+            {
+                file.branches.entry(loc.line).or_default().push(self.branches.get(&addr).copied());
+            }
+        }
+        files
+    }
+
+    /// the coverage in the lcov tracefile format, e.g. for genhtml
+    pub fn lcov(&self, lines: &LineTable, mem: &MemModel) -> String
+    {
+        let mut s = String::new();
+
+        for (path,file) in self.files(lines, mem)
+        {
+            s.push_str(&format!("TN:\nSF:{}\n", path));
+
+            for (line,jumps) in &file.branches
+            {
+                for (block,jump) in jumps.iter().enumerate()
+                {
+                    let (taken,not_taken) = match jump
+                    {
+                        Some((taken,not_taken)) => (taken.to_string(), not_taken.to_string()),
+                        None => ("-".to_string(), "-".to_string()),
+                    };
+                    s.push_str(&format!("BRDA:{},{},0,{}\nBRDA:{},{},1,{}\n", line, block, taken, line, block, not_taken));
+                }
+            }
+            s.push_str(&format!("BRF:{}\nBRH:{}\n", file.branches_found(), file.branches_hit()));
+
+            for (line,count) in &file.lines
+            {
+                s.push_str(&format!("DA:{},{}\n", line, count));
+            }
+            s.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", file.lines.len(), file.lines_hit()));
+        }
+        s
+    }
+
+    /// the line and branch coverage per file and the lines that were never executed
+    pub fn summary(&self, lines: &LineTable, mem: &MemModel) -> String
+    {
+        let percent = |hit: usize, found: usize| if found == 0 {100.0} else {hit as f64 * 100.0 / found as f64};
+        let mut s = format!("{:>15} {:>8} {:>15} {:>8}  file\n", "lines", "%", "branches", "%");
+
+        let (mut lines_total, mut branches_total) = ((0,0), (0,0));
+        let mut missed = String::new();
+
+        for (path,file) in self.files(lines, mem)
+        {
+            let (lines_hit, lines_found) = (file.lines_hit(), file.lines.len());
+            let (branches_hit, branches_found) = (file.branches_hit(), file.branches_found());
+
+            s.push_str(&format!("{:>15} {:>7.2}% {:>15} {:>7.2}%  {}\n",
+                format!("{}/{}", lines_hit, lines_found), percent(lines_hit, lines_found),
+                format!("{}/{}", branches_hit, branches_found), percent(branches_hit, branches_found), path));
+
+            lines_total = (lines_total.0 + lines_hit, lines_total.1 + lines_found);
+            branches_total = (branches_total.0 + branches_hit, branches_total.1 + branches_found);
+
+            let ranges = missed_ranges(&file);
+            if !ranges.is_empty()
+            {
+                missed.push_str(&format!("{}: {}\n", path, ranges.join(", ")));
+            }
+        }
+
+        s.push_str(&format!("{:>15} {:>7.2}% {:>15} {:>7.2}%  total\n",
+            format!("{}/{}", lines_total.0, lines_total.1), percent(lines_total.0, lines_total.1),
+            format!("{}/{}", branches_total.0, branches_total.1), percent(branches_total.0, branches_total.1)));
+
+        if !missed.is_empty()
+        {
+            s.push_str("\nlines never executed:\n");
+            s.push_str(&missed);
+        }
+        s
+    }
+}
+
+/// the lines that were never executed, consecutive lines with instructions
+/// are combined, e.g. `12-15`
+fn missed_ranges(file: &FileCoverage) -> Vec<String>
+{
+    let mut ranges:Vec<(u64,u64)> = vec![];
+    let mut previous_missed = false;
+
+    for (line,count) in &file.lines
+    {
+        match (*count == 0, previous_missed, ranges.last_mut())
+        {
+            (true, true, Some(range)) => range.1 = *line,
+            (true, _, _) => ranges.push((*line,*line)),
+            _ => {},
+        }
+        previous_missed = *count == 0;
+    }
+
+    ranges.iter()
+        .map(|(start,end)| if start == end {format!("{}", start)} else {format!("{}-{}", start, end)})
+        .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::{MachineState,MachineInitInfo};
+    use crate::test_util::machine_with;
+
+    use std::path::PathBuf;
+
+    /// start:  mov a, 0                    (line 1)
+    /// loop:   add a, 1                    (line 2)
+    ///         cmp a, 3; jl loop           (line 3)
+    ///         cmp a, 10; jl end           (line 4)
+    ///         mov a, 1                    (line 5)
+    /// end:    push 9; sys (End)           (line 1 of lib.asm)
+    fn coverage() -> MachineState
+    {
+        let lines = "1\t1\t./in.asm\n3\t2\t./in.asm\n5\t3\t./in.asm\n7\t3\t./in.asm\n9\t4\t./in.asm\n11\t4\t./in.asm\n\
+                     13\t5\t./in.asm\n15\t1\tlib.asm\n17\t1\tlib.asm\n";
+
+        let code = [0x0F21_0000_0001_0000, 0, 0x0121_0000_0001_0000, 1, 0x0921_0000_0001_0000, 3, 0x0E10_0000_0000_0000, 3,
+                        0x0921_0000_0001_0000, 10, 0x0E10_0000_0000_0000, 15, 0x0F21_0000_0001_0000, 1,
+                        0x1010_0000_0000_0000, 9, 0x1600_0000_0000_0000];
+        let mut machine = machine_with(MachineInitInfo{
+            line_table: Some(LineTable::parse(lines, PathBuf::from("src"))),
+            ..Default::default()
+        }, &code);
+
+        machine.coverage = Some(Coverage::new());
+        machine.run().unwrap();
+        machine
+    }
+
+    #[test]
+    fn lcov_test()
+    {
+        let machine = coverage();
+        let lcov = machine.coverage.as_ref().unwrap().lcov(machine.debug.lines.as_ref().unwrap(), &machine.mem_state);
+
+        let expected = "TN:\nSF:src/in.asm\n\
+                        BRDA:3,0,0,2\nBRDA:3,0,1,1\nBRDA:4,0,0,1\nBRDA:4,0,1,0\nBRF:4\nBRH:3\n\
+                        DA:1,1\nDA:2,3\nDA:3,3\nDA:4,1\nDA:5,0\nLF:5\nLH:4\nend_of_record\n\
+                        TN:\nSF:src/lib.asm\nBRF:0\nBRH:0\nDA:1,1\nLF:1\nLH:1\nend_of_record\n";
+        assert_eq!(lcov, expected);
+    }
+
+    #[test]
+    fn summary_test()
+    {
+        let machine = coverage();
+        let summary = machine.coverage.as_ref().unwrap().summary(machine.debug.lines.as_ref().unwrap(), &machine.mem_state);
+
+        assert!(summary.contains("            4/5   80.00%             3/4   75.00%  src/in.asm\n"), "{}", summary);
+        assert!(summary.contains("            5/6   83.33%             3/4   75.00%  total\n"), "{}", summary);
+        assert!(summary.ends_with("lines never executed:\nsrc/in.asm: 5\n"), "{}", summary);
+    }
+}
@@ -0,0 +1,675 @@
+use crate::machine::MachineState;
+use crate::debug::{MachineDebug,DebugFrontend,ContinueAfterDebug,StopReason,DebugCommand,get_debug_command,parse_condition,parse_register};
+
+use j_system_definition::register::Register;
+
+use serde_json::{json,Value};
+
+use std::cell::RefCell;
+use std::collections::{HashMap,HashSet};
+use std::io::{self,BufRead,BufReader,Write};
+use std::net::TcpListener;
+use std::rc::Rc;
+
+/// the machine has only one thread
+const THREAD_ID: u64 = 1;
+
+const REGISTERS_REF: u64 = 1;
+const STACK_REF: u64 = 2;
+
+/// larger messages are rejected before their body is read
+const MAX_MESSAGE_SIZE: usize = 4 << 20;
+
+/// key of the breakpoints that were set by label
+const FUNCTION_BREAKPOINTS: &str = "<functions>";
+
+const REGISTERS: [Register;10] = [Register::a, Register::b, Register::c, Register::d, Register::e, Register::f,
+                                  Register::s, Register::pc, Register::tos, Register::bos];
+
+/// Debug Adapter Protocol server. Maps the requests of a client (e.g. an editor)
+/// onto the debugger of the machine.
+/// The output of the programm is sent as `output` events
+pub struct DapServer
+{
+    reader: Box<dyn BufRead>,
+    writer: Box<dyn Write>,
+
+    /// sequence number of the next message
+    seq: u64,
+
+    /// breakpoints set by the client.
+    /// Key: source path or `FUNCTION_BREAKPOINTS`
+    breakpoints: HashMap<String,Vec<u64>>,
+
+    /// `configurationDone` was received and the programm was started
+    configured: bool,
+
+    /// the programm has ended
+    terminated: bool,
+
+    /// the client has closed the session
+    disconnected: bool,
+}
+
+/// hands the stops of the machine to the server that is shared with `DapServer::run`
+struct DapFrontend(Rc<RefCell<DapServer>>);
+
+impl DebugFrontend for DapFrontend
+{
+    fn stopped(&mut self, machine: &mut MachineState, reason: StopReason, description: &str) -> ContinueAfterDebug
+    {
+        self.0.borrow_mut().stopped(machine, reason, description)
+    }
+}
+
+impl DapServer
+{
+    pub fn new(reader: Box<dyn BufRead>, writer: Box<dyn Write>) -> Self
+    {
+        Self{reader, writer, seq: 1, breakpoints: HashMap::new(), configured: false, terminated: false, disconnected: false}
+    }
+
+    /// communicates over stdin and stdout
+    pub fn stdio() -> Self
+    {
+        Self::new(Box::new(BufReader::new(io::stdin())), Box::new(io::stdout()))
+    }
+
+    /// waits for one client on `127.0.0.1:<port>`
+    pub fn tcp(port: u16) -> Result<Self,String>
+    {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("could not listen on port {}: {}", port, e))?;
+        eprintln!("waiting for a DAP client on 127.0.0.1:{}", port);
+
+        let (stream,_) = listener.accept().map_err(|e| format!("could not accept the client: {}", e))?;
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+
+        Ok(Self::new(Box::new(BufReader::new(reader)), Box::new(stream)))
+    }
+
+    /// runs the programm as debuggee of the client until the client disconnects.
+    /// Returns the error message if the programm failed
+    pub fn run(self, machine: &mut MachineState) -> Result<(),String>
+    {
+        machine.machine_information.buffer_output();
+        machine.debug.debug_mode.get_or_insert_with(HashSet::new);
+
+        let server = Rc::new(RefCell::new(self));
+
+        // initialize, launch and breakpoints until `configurationDone`
+        if let ContinueAfterDebug::Quit = server.borrow_mut().serve(machine)
+        {
+            return Ok(())
+        }
+
+        machine.debug.frontend = Some(Box::new(DapFrontend(server.clone())));
+        let result = machine.run();
+        machine.debug.frontend = None;
+
+        server.borrow_mut().exited(machine, &result);
+        result
+    }
+
+    fn stopped(&mut self, machine: &mut MachineState, reason: StopReason, description: &str) -> ContinueAfterDebug
+    {
+        if self.disconnected
+        {
+            return ContinueAfterDebug::Quit
+        }
+
+        let reason = match reason
+        {
+            StopReason::Step if machine.machine_information.get_cycles() == 0 => "entry",
+            StopReason::Step        => "step",
+            StopReason::Breakpoint  => "breakpoint",
+            StopReason::Watchpoint  => "data breakpoint",
+        };
+
+        self.send_output(machine);
+        self.event("stopped", json!({"reason": reason, "description": description.trim(), "threadId": THREAD_ID, "allThreadsStopped": true}));
+        self.serve(machine)
+    }
+
+    fn exited(&mut self, machine: &mut MachineState, result: &Result<(),String>)
+    {
+        self.terminated = true;
+
+        if self.disconnected
+        {
+            return
+        }
+
+        self.send_output(machine);
+        if let Err(msg) = result
+        {
+            self.event("output", json!({"category": "stderr", "output": format!("{}\n", msg)}));
+        }
+
+        self.event("exited", json!({"exitCode": if result.is_ok() {0} else {1}}));
+        self.event("terminated", json!({}));
+
+        // the client can still inspect the machine until it disconnects
+        self.serve(machine);
+    }
+
+    /// handles requests until the machine should continue
+    fn serve(&mut self, machine: &mut MachineState) -> ContinueAfterDebug
+    {
+        loop
+        {
+            let request = match self.read_message()
+            {
+                Ok(Some(request)) => request,
+                Ok(None) =>
+                {
+                    self.disconnected = true;
+                    return ContinueAfterDebug::Quit
+                },
+                Err(x) =>
+                {
+                    eprintln!("DAP: {}", x);
+                    self.disconnected = true;
+                    return ContinueAfterDebug::Quit
+                },
+            };
+
+            if let Some(cont) = self.handle(machine, &request)
+            {
+                return cont
+            }
+        }
+    }
+
+    /// handles one request, returns `Some` if the machine should leave the debugger
+    fn handle(&mut self, machine: &mut MachineState, request: &Value) -> Option<ContinueAfterDebug>
+    {
+        let args = &request["arguments"];
+        let command = request["command"].as_str().unwrap_or("");
+
+        let mut cont = None;
+        // events that are sent after the response
+        let mut events = vec![];
+
+        let result = match command
+        {
+            "initialize" =>
+            {
+                events.push(("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsFunctionBreakpoints": true,
+                    "supportsConditionalBreakpoints": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsStepBack": machine.journal.is_some(),
+                }))
+            },
+
+            // the programm was already loaded by the interpreter
+            "launch" | "attach" =>
+            {
+                if args["stopOnEntry"].as_bool().unwrap_or(false)
+                {
+                    machine.debug.debug_step = Some(0);
+                }
+                Ok(json!({}))
+            },
+
+            "setBreakpoints" => match args["source"]["path"].as_str().or(args["source"]["name"].as_str())
+            {
+                Some(path) =>
+                {
+                    let requested = args["breakpoints"].as_array().into_iter().flatten().map(|bp|
+                    {
+                        let line = bp["line"].as_u64().unwrap_or(0);
+                        let addr = machine.debug.lines.as_ref().ok_or("no line table loaded".to_string())
+                            .and_then(|lines| lines.adress_of(path, line).ok_or(format!("no instruction at or after line {}", line)));
+                        (addr, bp)
+                    }).collect();
+
+                    Ok(self.set_breakpoints(machine, path.into(), requested))
+                },
+                None => Err("the source has no path".into()),
+            },
+
+            "setFunctionBreakpoints" =>
+            {
+                let requested = args["breakpoints"].as_array().into_iter().flatten()
+                    .map(|bp| (machine.debug.resolve(bp["name"].as_str().unwrap_or("")), bp))
+                    .collect();
+
+                Ok(self.set_breakpoints(machine, FUNCTION_BREAKPOINTS.into(), requested))
+            },
+
+            "setExceptionBreakpoints" => Ok(json!({"breakpoints": []})),
+
+            "configurationDone" =>
+            {
+                if !self.configured
+                {
+                    self.configured = true;
+                    cont = Some(ContinueAfterDebug::Continue);
+                }
+                Ok(json!({}))
+            },
+
+            "threads"   => Ok(json!({"threads": [{"id": THREAD_ID, "name": "main"}]})),
+            "stackTrace"=> Ok(stack_trace(machine)),
+            "scopes"    => Ok(json!({"scopes": [
+                {"name": "Registers", "variablesReference": REGISTERS_REF, "expensive": false},
+                {"name": "Stack", "variablesReference": STACK_REF, "expensive": false},
+            ]})),
+            "variables" => variables(machine, args["variablesReference"].as_u64().unwrap_or(0)),
+            "readMemory"=> read_memory(machine, args),
+            "evaluate"  => evaluate(machine, args["expression"].as_str().unwrap_or("")),
+
+            "continue" | "next" | "stepIn" | "stepOut" | "stepBack" | "reverseContinue" if self.terminated =>
+                Err("the programm has ended".into()),
+
+            "continue" =>
+            {
+                cont = machine.run_debug_command(DebugCommand::Continue);
+                Ok(json!({"allThreadsContinued": true}))
+            },
+            "next"      => {cont = machine.run_debug_command(DebugCommand::Next); Ok(json!({}))},
+            "stepIn"    => {cont = machine.run_debug_command(DebugCommand::Step(0)); Ok(json!({}))},
+            "stepOut"   => {cont = machine.run_debug_command(DebugCommand::Finish); Ok(json!({}))},
+
+            "stepBack" | "reverseContinue" if machine.journal.is_none() =>
+                Err("no history recorded, start the interpreter with --history".into()),
+
+            "stepBack" | "reverseContinue" =>
+            {
+                let until_breakpoint = command == "reverseContinue";
+                machine.reverse(until_breakpoint, if until_breakpoint {u64::MAX} else {1});
+                // the client shows the new state itself
+                machine.machine_information.take_output();
+
+                let reason = if until_breakpoint && machine.check_breakpoint() {"breakpoint"} else {"step"};
+                events.push(("stopped", json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true})));
+                Ok(json!({}))
+            },
+
+            "disconnect" =>
+            {
+                cont = Some(ContinueAfterDebug::Quit);
+                Ok(json!({}))
+            },
+
+            _ => Err(format!("unsupported request: {}", command)),
+        };
+
+        self.respond(request, result);
+        for (event,body) in events
+        {
+            self.event(event, body);
+        }
+
+        if command == "disconnect"
+        {
+            self.disconnected = true;
+        }
+        cont
+    }
+
+    /// replaces the breakpoints of a source or of the labels.
+    /// `requested` contains the resolved adress and the breakpoint of the request
+    fn set_breakpoints(&mut self, machine: &mut MachineState, key: String, requested: Vec<(Result<u64,String>,&Value)>) -> Value
+    {
+        for addr in self.breakpoints.remove(&key).into_iter().flatten()
+        {
+            machine.debug.conditions.remove(&addr);
+            machine.debug.debug_mode.get_or_insert_with(HashSet::new).remove(&addr);
+        }
+
+        let mut addrs = vec![];
+        let mut breakpoints = vec![];
+
+        for (addr,bp) in requested
+        {
+            let cond = match bp["condition"].as_str()
+            {
+                Some(cond) => parse_condition(&cond.split_ascii_whitespace().collect::<Vec<_>>()).map(Some),
+                None => Ok(None),
+            };
+
+            match (addr,cond)
+            {
+                (Ok(addr),Ok(cond)) =>
+                {
+                    machine.debug.debug_mode.get_or_insert_with(HashSet::new).insert(addr);
+                    if let Some(cond) = cond
+                    {
+                        machine.debug.conditions.insert(addr, cond);
+                    }
+                    addrs.push(addr);
+
+                    let mut breakpoint = json!({"id": addr, "verified": true, "instructionReference": addr.to_string()});
+                    if let Some(loc) = machine.debug.lines.as_ref().and_then(|lines| lines.location(addr))
+                    {
+                        breakpoint["line"] = json!(loc.line);
+                    }
+                    breakpoints.push(breakpoint);
+                },
+                (Err(x),_) | (_,Err(x)) => breakpoints.push(json!({"verified": false, "message": x})),
+            }
+        }
+
+        self.breakpoints.insert(key, addrs);
+        json!({"breakpoints": breakpoints})
+    }
+
+    /// reads a message of the form `Content-Length: <n>\r\n\r\n<json>`.
+    /// Returns `None` when the client closed the connection
+    fn read_message(&mut self) -> Result<Option<Value>,String>
+    {
+        let mut length = None;
+
+        loop
+        {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line).map_err(|e| e.to_string())? == 0
+            {
+                return Ok(None)
+            }
+
+            let line = line.trim();
+
+            // an empty line ends the header
+            if line.is_empty() && length.is_some()
+            {
+                break
+            }
+
+            if let Some(len) = line.strip_prefix("Content-Length:")
+            {
+                length = Some(len.trim().parse::<usize>().map_err(|_| format!("invalid header: {}", line))?);
+            }
+
+            if let Some(len) = length.filter(|len| *len > MAX_MESSAGE_SIZE)
+            {
+                return Err(format!("message of {} bytes is larger than the maximum of {} bytes", len, MAX_MESSAGE_SIZE))
+            }
+        }
+
+        let mut body = vec![0; length.unwrap()];
+        self.reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+
+        serde_json::from_slice(&body).map(Some).map_err(|e| format!("invalid message: {}", e))
+    }
+
+    fn send(&mut self, mut message: Value)
+    {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+
+        let body = message.to_string();
+
+        // the client may already be gone, there is no one to report the error to
+        let _ = write!(self.writer, "Content-Length: {}\r\n\r\n{}", body.len(), body).and_then(|_| self.writer.flush());
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value,String>)
+    {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+
+        match result
+        {
+            Ok(body) => response["body"] = body,
+            Err(msg) => response["message"] = json!(msg),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value)
+    {
+        self.send(json!({"type": "event", "event": event, "body": body}))
+    }
+
+    /// sends the output the programm printed since the last stop
+    fn send_output(&mut self, machine: &mut MachineState)
+    {
+        let output:String = machine.machine_information.take_output().into_iter().map(|(_,s)| s).collect();
+
+        if !output.is_empty()
+        {
+            self.event("output", json!({"category": "stdout", "output": output}));
+        }
+    }
+}
+
+/// one frame for the current instruction and for every call site on the stack
+fn stack_trace(machine: &MachineState) -> Value
+{
+    let frames:Vec<Value> = machine.call_stack().iter().enumerate().map(|(id,addr)|
+    {
+        let mut frame = json!({
+            "id": id,
+            "name": machine.debug.location(*addr),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": addr.to_string(),
+        });
+
+        if let Some((lines,loc)) = machine.debug.lines.as_ref().and_then(|lines| Some((lines, lines.location(*addr)?)))
+        {
+            frame["line"] = json!(loc.line);
+            frame["column"] = json!(1);
+            frame["source"] = json!({"name": loc.file, "path": lines.source_path(loc)});
+        }
+        frame
+    }).collect();
+
+    json!({"totalFrames": frames.len(), "stackFrames": frames})
+}
+
+/// the registers or the values on the stack
+fn variables(machine: &MachineState, reference: u64) -> Result<Value,String>
+{
+    let variable = |name: String, val: u64| json!({"name": name, "value": format!("{} ({:#x})", val, val), "variablesReference": 0});
+
+    let variables:Vec<Value> = match reference
+    {
+        REGISTERS_REF => REGISTERS.iter().map(|reg| variable(format!("{:?}", reg), machine.reg_state.read(*reg))).collect(),
+        STACK_REF =>
+        {
+            let tos = machine.reg_state.read(Register::tos);
+            let stack = if tos == 0 {0..=0} else {tos..=machine.mem_state.get_stack_top()};
+
+            stack.filter(|_| tos != 0)
+                .filter_map(|addr| Some(variable(addr.to_string(), machine.mem_state.peek(addr).ok()?)))
+                .collect()
+        },
+        _ => return Err(format!("unknown variables reference: {}", reference)),
+    };
+
+    Ok(json!({"variables": variables}))
+}
+
+/// the memory consists of 64 bit values, so a memory reference is the adress of a value.
+/// `offset` and `count` are in bytes and get rounded to whole values (little endian)
+fn read_memory(machine: &MachineState, args: &Value) -> Result<Value,String>
+{
+    let base = machine.debug.resolve(args["memoryReference"].as_str().unwrap_or(""))?;
+    let offset = args["offset"].as_i64().unwrap_or(0);
+    let count = args["count"].as_u64().unwrap_or(0);
+
+    let first = if offset < 0 {base.saturating_sub(offset.unsigned_abs() / 8)} else {base.saturating_add(offset as u64 / 8)};
+    let values = count.div_ceil(8);
+
+    let mut bytes = vec![];
+    for addr in first..first.saturating_add(values)
+    {
+        match machine.mem_state.peek(addr)
+        {
+            Ok(val) => bytes.extend_from_slice(&val.to_le_bytes()),
+            Err(_) => break,
+        }
+    }
+
+    Ok(json!({
+        "address": first.to_string(),
+        "data": base64(&bytes),
+        "unreadableBytes": values*8 - bytes.len() as u64,
+    }))
+}
+
+/// a register name shows the register, everything else is run as debugger command
+fn evaluate(machine: &mut MachineState, expression: &str) -> Result<Value,String>
+{
+    if let Some(reg) = parse_register(expression.trim())
+    {
+        return Ok(json!({"result": machine.reg_state.read(reg).to_string(), "variablesReference": 0}))
+    }
+
+    let command = get_debug_command(expression, &machine.debug)
+        .map_err(|x| if x.is_empty() {"empty expression".into()} else {x})?;
+
+    if matches!(command, DebugCommand::Step(_) | DebugCommand::Next | DebugCommand::Finish | DebugCommand::Continue |
+                         DebugCommand::Exit | DebugCommand::ReverseStep(_) | DebugCommand::ReverseContinue)
+    {
+        return Err("use the requests of the client to control the execution".into())
+    }
+
+    machine.run_debug_command(command);
+    let output:String = machine.machine_information.take_output().into_iter().map(|(_,s)| s).collect();
+
+    Ok(json!({"result": output.trim_end(), "variablesReference": 0}))
+}
+
+fn base64(bytes: &[u8]) -> String
+{
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut s = String::new();
+    for chunk in bytes.chunks(3)
+    {
+        let n = chunk.iter().enumerate().fold(0u32, |n,(ii,b)| n | (*b as u32) << (16 - 8*ii));
+
+        for ii in 0..4
+        {
+            if ii <= chunk.len()
+            {
+                s.push(CHARS[(n >> (18 - 6*ii) & 0x3f) as usize] as char);
+            }
+            else
+            {
+                s.push('=');
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::MachineInitInfo;
+    use crate::test_util::{SharedBuf,assembled_machine};
+
+    fn requests(requests: &[Value]) -> Vec<u8>
+    {
+        requests.iter().enumerate().map(|(seq,req)|
+        {
+            let mut req = req.clone();
+            req["seq"] = json!(seq + 1);
+            req["type"] = json!("request");
+            let body = req.to_string();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        }).collect::<String>().into_bytes()
+    }
+
+    fn messages(output: &[u8]) -> Vec<Value>
+    {
+        let mut server = DapServer::new(Box::new(io::Cursor::new(output.to_vec())), Box::new(io::sink()));
+        let mut messages = vec![];
+
+        while let Some(msg) = server.read_message().unwrap()
+        {
+            messages.push(msg);
+        }
+        messages
+    }
+
+    fn machine() -> MachineState
+    {
+        assembled_machine(MachineInitInfo::default(), "
+_rom
+_code
+    .start:
+        mov a, 5
+        add a, 1
+        push 9
+        sys
+")
+    }
+
+    #[test]
+    fn scripted_session_test()
+    {
+        let script = requests(&[
+            json!({"command": "initialize", "arguments": {"adapterID": "j"}}),
+            json!({"command": "launch", "arguments": {}}),
+            json!({"command": "setBreakpoints", "arguments": {"source": {"path": "/src/t.asm"}, "breakpoints": [{"line": 6}, {"line": 12}]}}),
+            json!({"command": "configurationDone"}),
+            json!({"command": "stackTrace", "arguments": {"threadId": 1}}),
+            json!({"command": "variables", "arguments": {"variablesReference": REGISTERS_REF}}),
+            json!({"command": "next", "arguments": {"threadId": 1}}),
+            json!({"command": "evaluate", "arguments": {"expression": "a"}}),
+            json!({"command": "readMemory", "arguments": {"memoryReference": "1", "count": 16}}),
+            json!({"command": "continue", "arguments": {"threadId": 1}}),
+            json!({"command": "disconnect"}),
+        ]);
+
+        let out = SharedBuf::default();
+        let mut machine = machine();
+
+        DapServer::new(Box::new(io::Cursor::new(script)), Box::new(out.clone())).run(&mut machine).unwrap();
+
+        let messages = messages(&out.0.borrow());
+        let response = |command: &str| messages.iter().filter(|m| m["type"] == "response" && m["command"] == command).cloned().collect::<Vec<_>>();
+        let events:Vec<&str> = messages.iter().filter(|m| m["type"] == "event").map(|m| m["event"].as_str().unwrap()).collect();
+
+        assert!(messages.iter().all(|m| m["type"] != "response" || m["success"] == true), "{:?}", messages);
+        assert_eq!(events, vec!["initialized", "stopped", "stopped", "exited", "terminated"]);
+
+        let bps = &response("setBreakpoints")[0]["body"]["breakpoints"];
+        assert_eq!(bps[0]["verified"], true);
+        assert_eq!(bps[0]["instructionReference"], "3");
+        assert_eq!(bps[1]["verified"], false);
+
+        let frame = &response("stackTrace")[0]["body"]["stackFrames"][0];
+        assert_eq!((frame["line"].as_u64(), frame["instructionPointerReference"].as_str()), (Some(6), Some("3")));
+
+        let regs = &response("variables")[0]["body"]["variables"];
+        assert_eq!((regs[0]["name"].as_str(), regs[0]["value"].as_str()), (Some("a"), Some("5 (0x5)")));
+
+        // after `next` the add was executed
+        assert_eq!(response("evaluate")[0]["body"]["result"], "6");
+        assert_eq!(response("readMemory")[0]["body"]["data"], base64(&[0x00,0x00,0x01,0x00,0x00,0x00,0x21,0x0F, 5,0,0,0,0,0,0,0]));
+    }
+
+    #[test]
+    fn message_size_test()
+    {
+        let read = |header: &str| DapServer::new(Box::new(io::Cursor::new(header.as_bytes().to_vec())), Box::new(io::sink())).read_message();
+
+        assert_eq!(read("Content-Length: 2\r\n\r\n{}"), Ok(Some(json!({}))));
+        assert!(read(&format!("Content-Length: {}\r\n\r\n{{}}", MAX_MESSAGE_SIZE + 1)).unwrap_err().contains("larger than the maximum"));
+        assert!(read("Content-Length: 99999999999999999999\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn base64_test()
+    {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
@@ -1,5 +1,7 @@
 use crate::machine::MachineState;
 use crate::deserialization::{self, deserialize_asm};
+use crate::line_table::LineTable;
+use crate::snapshot;
 
 use j_system_definition::register::Register;
 use j_system_definition::instructions::InstructionEnum;
@@ -11,6 +13,31 @@ use std::io::{self, Write};
 
 const ASM_DISPLAY_SIZE:u64 = 4;
 const STACK_DISPLAY_SIZE:u64 = 4;
+const SOURCE_DISPLAY_RADIUS:u64 = 2;
+
+const HELP: &str = "\
+b <loc> [if <reg> <op> <val>]   set a (conditional) breakpoint, <op>: == != < > <= >=
+d <loc>                         remove a breakpoint
+list                            list breakpoints and watchpoints
+w <loc> / uw <loc>              watch/unwatch writes to an adress
+r [reg] [x|d|a]                 print registers as hex, decimal or ascii
+m <loc> [count] [x|d|a]         print memory
+s [n]                           step n+1 instructions
+n                               step over a call
+finish                          run until the current function returns
+rs [n]                          step n instructions backwards (needs --history)
+rc                              continue backwards to the previous breakpoint
+lw <loc>                        show the last write to an adress
+assert <reg> <op> <val>         check a register, e.g. `assert a == 3`
+assert m <loc> <op> <val>       check a value in memory
+c                               continue
+ps / ins                        print the machine state / current instruction
+src                             print the source around the current instruction
+bt                              print the call stack
+dump [file]                     write a snapshot to continue with --resume (default: snapshot.json)
+exit                            stop the machine
+<loc> is an adress (decimal or 0x hex), a label name or file:line
+";
 
 /// how the Interpreter should continue after leaving
 /// the debugger
@@ -20,23 +47,219 @@ pub enum ContinueAfterDebug
     Continue,
 }
 
+/// why the machine stopped in the debugger
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum StopReason
+{
+    Breakpoint,
+    Step,
+    Watchpoint,
+}
+
+/// replaces the command line of the debugger, e.g. with the DAP server
+pub trait DebugFrontend
+{
+    /// called when the machine stops before an instruction,
+    /// `description` is the text the command line would show
+    fn stopped(&mut self, machine: &mut MachineState, reason: StopReason, description: &str) -> ContinueAfterDebug;
+}
+
 pub struct DebugInformation
 {
     /// is `None` when not in Debug mode.
-    /// Contains all pointers that hava a breakpoint (and symbols?)
+    /// Contains all pointers that hava a breakpoint
     pub debug_mode: Option<HashSet<u64>>,
 
     /// contains the labels with the label name(s)
     /// multiple lables can refer to the same address
     pub symbols: Option<HashMap<u64,Vec<String>>>,
+
+    /// maps the instructions to the source lines
+    pub lines: Option<LineTable>,
     
     /// is `None` when the machine is not stepping in the debugger.
     /// is `Some(<val>)` when the debugger is stepping val-amount of
     /// instructions until it re-hits the debugger
     pub debug_step: Option<u64>,
 
-    // ???
-    //pub debug_output: String,
+    /// conditions of the conditional breakpoints.
+    /// Key: adress of the breakpoint
+    pub conditions: HashMap<u64,Condition>,
+
+    /// is `Some(tos)` while running until the current function returns (`finish`).
+    /// `tos` is the top of stack when `finish` was called
+    pub finish: Option<u64>,
+
+    /// is `Some((adress, tos))` while stepping over a call (`next`).
+    /// the debugger stops at the adress after the call once the call returned
+    pub step_over: Option<(u64,u64)>,
+
+    /// handles the stops instead of the command line if set
+    pub frontend: Option<Box<dyn DebugFrontend>>,
+
+    /// amount of `assert` commands that failed
+    pub failed_asserts: u64,
+}
+
+impl DebugInformation
+{
+    pub fn new(debug_mode: Option<HashSet<u64>>, symbols: Option<HashMap<u64,Vec<String>>>, lines: Option<LineTable>) -> Self
+    {
+        Self{debug_mode, symbols, lines, debug_step: None, conditions: HashMap::new(), finish: None, step_over: None, frontend: None, failed_asserts: 0}
+    }
+
+    /// the last label at or before the adress and its adress
+    pub fn enclosing_label(&self, addr: u64) -> Option<(u64,&str)>
+    {
+        self.symbols.as_ref()
+            .and_then(|map| map.iter().filter(|(ptr,_)| **ptr <= addr).max_by_key(|(ptr,_)| **ptr))
+            .map(|(ptr,names)| (*ptr, names[0].as_str()))
+    }
+
+
+    /// describes an adress with the closest label in front of it 
+    /// and the source line, e.g. `12 (loop+3) in.asm:14`
+    pub fn location(&self, addr: u64) -> String
+    {
+        let mut s = match self.enclosing_label(addr)
+        {
+            Some((ptr,name)) if ptr == addr => format!("{} ({})", addr, name),
+            Some((ptr,name)) => format!("{} ({}+{})", addr, name, addr-ptr),
+            None => format!("{}", addr),
+        };
+
+        if let Some(loc) = self.lines.as_ref().and_then(|lines| lines.location(addr))
+        {
+            s.push_str(&format!(" {}:{}", loc.file, loc.line));
+        }
+        s
+    }
+
+    /// returns the adress of a location. 
+    /// A location is an adress (decimal or 0x hex) or a label name (with or without `.`)
+    pub fn resolve(&self, loc: &str) -> Result<u64,String>
+    {
+        if let Some(addr) = parse_number(loc)
+        {
+            return Ok(addr)
+        }
+
+        // file:line
+        if let Some((file,line)) = loc.rsplit_once(':')
+        {
+            let line = line.parse::<u64>().map_err(|_| format!("invalid line: {}", line))?;
+
+            return self.lines.as_ref().ok_or("no line table loaded")?
+                .adress_of(file, line).ok_or(format!("no instruction at or after {}", loc))
+        }
+
+        let name = loc.strip_prefix('.').unwrap_or(loc);
+
+        self.symbols.as_ref()
+            .and_then(|map| map.iter().find(|(_,names)| names.iter().any(|n| n == name)))
+            .map(|(addr,_)| *addr)
+            .ok_or(format!("unknown label: {}", loc))
+    }
+}
+
+impl DebugInformation
+{
+    /// the adresses `start..end` from the label to the next label,
+    /// e.g. the instructions of a function
+    pub fn label_range(&self, name: &str) -> Result<(u64,u64),String>
+    {
+        let start = self.resolve(name.strip_prefix('.').unwrap_or(name))
+            .ok().filter(|addr| self.symbols.as_ref().is_some_and(|map| map.contains_key(addr)))
+            .ok_or(format!("unknown label: {}", name))?;
+
+        let end = self.symbols.iter().flat_map(|map| map.keys())
+            .filter(|addr| **addr > start).min().copied().unwrap_or(u64::MAX);
+
+        Ok((start, end))
+    }
+}
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum CompareOp
+{
+    Eq,
+    NotEq,
+    Less,
+    Greater,
+    LessEq,
+    GreaterEq,
+}
+
+impl CompareOp
+{
+    pub fn as_str(&self) -> &'static str
+    {
+        match self
+        {
+            CompareOp::Eq           => "==",
+            CompareOp::NotEq        => "!=",
+            CompareOp::Less         => "<",
+            CompareOp::Greater      => ">",
+            CompareOp::LessEq       => "<=",
+            CompareOp::GreaterEq    => ">=",
+        }
+    }
+
+    pub fn compare(&self, left: u64, right: u64) -> bool
+    {
+        match self
+        {
+            CompareOp::Eq           => left == right,
+            CompareOp::NotEq        => left != right,
+            CompareOp::Less         => left <  right,
+            CompareOp::Greater      => left >  right,
+            CompareOp::LessEq       => left <= right,
+            CompareOp::GreaterEq    => left >= right,
+        }
+    }
+}
+
+/// condition of a conditional breakpoint: `<reg> <op> <val>`
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub struct Condition
+{
+    pub reg: Register,
+    pub op: CompareOp,
+    pub val: u64,
+}
+
+impl Condition
+{
+    pub fn holds(&self, reg_val: u64) -> bool
+    {
+        self.op.compare(reg_val, self.val)
+    }
+}
+
+/// how values are displayed by the debugger
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum NumberFormat
+{
+    Dec,
+    Hex,
+    Ascii,
+}
+
+impl NumberFormat
+{
+    pub fn format(&self, val: u64) -> String
+    {
+        match self
+        {
+            NumberFormat::Dec   => format!("{}", val),
+            NumberFormat::Hex   => format!("{:#x}", val),
+            NumberFormat::Ascii => match char::from_u32(val as u32)
+            {
+                Some(c) if val < 128 && !c.is_ascii_control() => format!("'{}'", c),
+                _ => format!("{:#x}", val),
+            },
+        }
+    }
 }
 
 pub trait MachineDebug
@@ -45,35 +268,73 @@ pub trait MachineDebug
     fn print_state(&mut self);
     fn print_current_asm(&mut self);
     fn get_regs(&mut self);
+    fn print_registers(&mut self, reg: Option<Register>, format: NumberFormat);
     fn peek_stack(&mut self);
     fn peek_instructions(&mut self);
-    fn read_mem(&mut self, mem: u64);
+    fn read_mem(&mut self, ptr: u64, count: u64, format: NumberFormat);
+    fn list_breakpoints(&mut self);
+    fn print_source(&mut self);
+    fn backtrace(&mut self);
+
+    /// the adress of the current instruction followed by the call sites on the stack
+    fn call_stack(&self) -> Vec<u64>;
+
+    /// reverts the last executed instruction, returns false if the history is empty
+    fn step_back(&mut self) -> Result<bool,String>;
+    fn reverse(&mut self, until_breakpoint: bool, steps: u64);
+    fn last_write(&mut self, addr: u64);
+
+    /// prints a message and counts the failure if the value does not fullfill the comparison
+    fn check_assert(&mut self, name: String, val: Result<u64,String>, op: CompareOp, expected: u64);
+
+    /// returns the adress of the call instruction if the adress is a return adress
+    fn call_site(&self, addr: u64) -> Option<u64>;
+    fn dump(&mut self, file: &str) -> Result<(),String>;
 
     /// returns true if `PC` points to a adress that has a breakpoint
+    /// and the condition of the breakpoint holds
     fn check_breakpoint(&mut self) -> bool;
     
+    /// stops the machine and hands it to the frontend or the command line
+    fn stop(&mut self, reason: StopReason, description: String) -> ContinueAfterDebug;
+
     /// if a breakpoint was hit, this function provides a CI to the user 
     /// in which the user can request information about the machine state
-    fn breakpoint_cli(&mut self, rehit: bool) -> ContinueAfterDebug;
+    fn breakpoint_cli(&mut self, reason: String) -> ContinueAfterDebug;
+
+    /// executes a debugger command, returns `Some` if the machine should leave the debugger
+    fn run_debug_command(&mut self, command: DebugCommand) -> Option<ContinueAfterDebug>;
 }
 
 impl MachineDebug for MachineState
 {
     fn check_breakpoint(&mut self) -> bool
     {
-        self.debug.debug_mode.as_ref().unwrap().contains(&self.reg_state.read(Register::pc))
-    }
+        let pc = self.reg_state.read(Register::pc);
 
-    fn read_mem(&mut self, ptr:u64) 
-    {
-        let s;
-        if let Ok(val) = self.mem_state.read(ptr)
+        if !self.debug.debug_mode.as_ref().unwrap().contains(&pc)
         {
-            s = format!("{}\n",val);
+            return false
         }
-        else 
+
+        match self.debug.conditions.get(&pc)
         {
-            s = format!("could not read address: {}\n", ptr);
+            Some(cond) => cond.holds(self.reg_state.read(cond.reg)),
+            None => true,
+        }
+    }
+
+    fn read_mem(&mut self, ptr:u64, count: u64, format: NumberFormat) 
+    {
+        let mut s = String::new();
+
+        for addr in ptr..ptr.saturating_add(count)
+        {
+            match self.mem_state.peek(addr)
+            {
+                Ok(val) => s.push_str(&format!("{}:\t{}\n", addr, format.format(val))),
+                Err(x) => {s.push_str(&format!("could not read address {}: {}\n", addr, x)); break},
+            }
         }
         self.machine_information.push_str(s)
     }
@@ -81,12 +342,23 @@ impl MachineDebug for MachineState
     /// displays the breakpoint CLI when a breakpoint is set for this adress
     fn breakpoint(&mut self) -> ContinueAfterDebug
     {
+        let pc = self.reg_state.read(Register::pc);
+        let tos = self.reg_state.read(Register::tos);
+
+        // writes of the last instruction to watched adresses
+        let hits = self.mem_state.take_watch_hits();
+        if !hits.is_empty()
+        {
+            let reason = hits.iter().map(|(addr,old,new)| format!("watchpoint {}: {} -> {}\n", addr, old, new)).collect();
+            return self.stop(StopReason::Watchpoint, reason);
+        }
+
         if let Some(steps) = self.debug.debug_step
         {
             if steps == 0
             {
                 self.debug.debug_step = None;
-                return self.breakpoint_cli(true); 
+                return self.stop(StopReason::Step, format!("resume after step at {}\n", self.debug.location(pc)));
             }
             else 
             {
@@ -94,26 +366,57 @@ impl MachineDebug for MachineState
             }
         }
 
+        // the called function returned
+        if let Some((addr,call_tos)) = self.debug.step_over
+        {
+            if pc == addr && tos >= call_tos
+            {
+                return self.stop(StopReason::Step, format!("stepped over call to {}\n", self.debug.location(pc)));
+            }
+        }
+
+        // stop after the `ret` of the current function
+        if let Some(finish_tos) = self.debug.finish
+        {
+            let is_ret = matches!(deserialize_asm(&self.mem_state, pc), Some((inst,_)) if inst.instruction == InstructionEnum::ret);
+
+            if is_ret && tos >= finish_tos
+            {
+                self.debug.finish = None;
+                self.debug.debug_step = Some(0);
+            }
+        }
+
         if !self.check_breakpoint()
         {
             return ContinueAfterDebug::Continue
         }
 
-        self.breakpoint_cli(false)
+        self.stop(StopReason::Breakpoint, format!("hit breakpoint at: {}\n", self.debug.location(pc)))
     }
 
-    fn breakpoint_cli(&mut self, rehit: bool) -> ContinueAfterDebug
+    fn stop(&mut self, reason: StopReason, description: String) -> ContinueAfterDebug
     {
-        // pointer to instruction that is about to be executed
-        let addr = self.reg_state.read(Register::pc);
+        // a breakpoint inside of the function cancels `next` and `finish`
+        self.debug.step_over = None;
+        self.debug.finish = None;
 
-        if rehit
-        {   
-            print!("resume after step at {}\n",addr);
-        }
-        else{
-            print!("hit breakpoint at: {}\n", addr);
+        // the frontend is taken out while it has access to the machine
+        match self.debug.frontend.take()
+        {
+            Some(mut frontend) =>
+            {
+                let cont = frontend.stopped(self, reason, &description);
+                self.debug.frontend = Some(frontend);
+                cont
+            },
+            None => self.breakpoint_cli(description),
         }
+    }
+
+    fn breakpoint_cli(&mut self, reason: String) -> ContinueAfterDebug
+    {
+        print!("{}", reason);
 
         // initial breakpoint print
         self.print_state();
@@ -127,26 +430,297 @@ impl MachineDebug for MachineState
             io::stdout().flush().unwrap();
 
             let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            if let Some(command) = get_debug_command(&input)
+
+            // stop the machine when the input is closed
+            if io::stdin().read_line(&mut input).unwrap_or(0) == 0
+            {
+                return ContinueAfterDebug::Quit
+            }
+
+            match get_debug_command(&input, &self.debug)
+            {
+                Ok(command) => if let Some(cont) = self.run_debug_command(command)
+                {
+                    return cont
+                },
+                // empty line
+                Err(x) if x.is_empty() => {},
+                Err(x) => self.machine_information.push_str(format!("{}\n", x)),
+            }
+        }
+    }
+
+    fn run_debug_command(&mut self, command: DebugCommand) -> Option<ContinueAfterDebug>
+    {
+        match command
+        {
+            DebugCommand::PrintRegisters(reg,format)    => self.print_registers(reg, format),
+            DebugCommand::MemRead(ptr,count,format)     => self.read_mem(ptr, count, format),
+            DebugCommand::PrintState                    => self.print_state(),
+            DebugCommand::PrintCurrentAsm               => self.print_current_asm(),
+            DebugCommand::Help                          => self.machine_information.push_str(HELP.into()),
+            DebugCommand::ListBreakpoints               => self.list_breakpoints(),
+            DebugCommand::PrintSource                   => self.print_source(),
+            DebugCommand::Backtrace                     => self.backtrace(),
+            DebugCommand::ReverseStep(steps)            => self.reverse(false, steps),
+            DebugCommand::ReverseContinue               => self.reverse(true, u64::MAX),
+            DebugCommand::LastWrite(addr)               => self.last_write(addr),
+            DebugCommand::Assert(cond)                  => 
+                self.check_assert(format!("{:?}", cond.reg), Ok(self.reg_state.read(cond.reg)), cond.op, cond.val),
+            DebugCommand::AssertMem(addr,op,expected)   => 
+                self.check_assert(format!("[{}]", addr), self.mem_state.peek(addr), op, expected),
+
+            DebugCommand::SetBreakpoint(addr,cond) =>
+            {
+                self.debug.debug_mode.get_or_insert_with(HashSet::new).insert(addr);
+                match cond
+                {
+                    Some(cond) => {self.debug.conditions.insert(addr, cond);},
+                    None => {self.debug.conditions.remove(&addr);},
+                }
+                self.machine_information.push_str(format!("breakpoint at {}\n", self.debug.location(addr)));
+            },
+
+            DebugCommand::UnsetBreakpoint(addr) =>
+            {
+                self.debug.conditions.remove(&addr);
+                if !self.debug.debug_mode.as_mut().map(|set| set.remove(&addr)).unwrap_or(false)
+                {
+                    self.machine_information.push_str(format!("no breakpoint at {}\n", addr));
+                }
+            },
+
+            DebugCommand::Watch(addr)   => self.mem_state.watch(addr),
+            DebugCommand::Unwatch(addr) => if !self.mem_state.unwatch(addr)
+            {
+                self.machine_information.push_str(format!("no watchpoint at {}\n", addr));
+            },
+
+            DebugCommand::Dump(file) => if let Err(x) = self.dump(&file)
+            {
+                self.machine_information.push_str(format!("{}\n", x));
+            },
+
+            DebugCommand::Exit              => return Some(ContinueAfterDebug::Quit),
+            DebugCommand::Step(steps)       => {self.debug.debug_step = Some(steps); return Some(ContinueAfterDebug::Continue)},
+            DebugCommand::Continue          => return Some(ContinueAfterDebug::Continue),
+
+            DebugCommand::Next =>
             {
-                match command
+                let pc = self.reg_state.read(Register::pc);
+
+                match deserialize_asm(&self.mem_state, pc)
                 {
-                    DebugCommand::PrintRegisters    => {},
-                    DebugCommand::MemRead(ptr)      => self.read_mem(ptr),
-                    DebugCommand::PrintState        => self.print_state(),
-                    DebugCommand::PrintCurrentAsm   => self.print_current_asm(),
-                    DebugCommand::Exit              => return ContinueAfterDebug::Quit,
-                    DebugCommand::Dump              => {todo!()},
-                    DebugCommand::Step(steps)       => {self.debug.debug_step = Some(steps); return ContinueAfterDebug::Continue},
-                    DebugCommand::Continue          => return ContinueAfterDebug::Continue,
+                    Some((inst,next)) if inst.instruction == InstructionEnum::call => 
+                        self.debug.step_over = Some((next, self.reg_state.read(Register::tos))),
+                    _ => self.debug.debug_step = Some(0),
                 }
+                return Some(ContinueAfterDebug::Continue)
+            },
+
+            DebugCommand::Finish =>
+            {
+                self.debug.finish = Some(self.reg_state.read(Register::tos));
+                return Some(ContinueAfterDebug::Continue)
+            },
+        }
+        None
+    }
+
+    fn print_registers(&mut self, reg: Option<Register>, format: NumberFormat)
+    {
+        let regs = match reg
+        {
+            Some(reg) => vec![reg],
+            None => vec![Register::a, Register::b, Register::c, Register::d, Register::e, Register::f, 
+                         Register::s, Register::pc, Register::tos, Register::bos],
+        };
+
+        let s:String = regs.iter().map(|reg| format!("{:?}: {}\n", reg, format.format(self.reg_state.read(*reg)))).collect();
+        self.machine_information.push_str(s);
+    }
+
+    fn list_breakpoints(&mut self)
+    {
+        let mut breakpoints:Vec<_> = self.debug.debug_mode.iter().flatten().copied().collect();
+        breakpoints.sort();
+
+        let mut s = String::new();
+        for addr in breakpoints
+        {
+            s.push_str(&format!("breakpoint {}", self.debug.location(addr)));
+            if let Some(cond) = self.debug.conditions.get(&addr)
+            {
+                s.push_str(&format!(" if {:?} {} {}", cond.reg, cond.op.as_str(), cond.val));
             }
-            else 
+            s.push('\n');
+        }
+
+        let mut watchpoints:Vec<_> = self.mem_state.get_watchpoints().iter().copied().collect();
+        watchpoints.sort();
+        watchpoints.iter().for_each(|addr| s.push_str(&format!("watchpoint {}\n", addr)));
+
+        self.machine_information.push_str(s);
+    }
+
+    fn print_source(&mut self)
+    {
+        let pc = self.reg_state.read(Register::pc);
+
+        let context = self.debug.lines.as_ref().and_then(|lines| 
+            lines.location(pc).and_then(|loc| Some((loc.clone(), lines.source_context(loc, SOURCE_DISPLAY_RADIUS)?))));
+
+        let s = match context
+        {
+            Some((loc,context)) => 
+            {
+                let mut s = format!("Source ({}):\n", loc.file);
+                for (line,content) in context
+                {
+                    let marker = if line == loc.line {"->"} else {""};
+                    s.push_str(&format!("{}\t{}\t{}\n", marker, line, content));
+                }
+                s
+            },
+            None => "no source available\n".into(),
+        };
+
+        self.machine_information.push_str(s);
+    }
+
+    /// the return adresses on the stack resolved to labels and source lines.
+    /// A value on the stack counts as return adress if the instruction in front of it is a `call`
+    fn backtrace(&mut self)
+    {
+        let s:String = self.call_stack().iter().enumerate()
+            .map(|(frame,addr)| format!("#{}\t{}\n", frame, self.debug.location(*addr)))
+            .collect();
+
+        self.machine_information.push_str(s);
+    }
+
+    fn call_stack(&self) -> Vec<u64>
+    {
+        let tos = self.reg_state.read(Register::tos);
+        let mut frames = vec![self.reg_state.read(Register::pc)];
+
+        if tos != 0
+        {
+            frames.extend((tos..=self.mem_state.get_stack_top())
+                .filter_map(|addr| self.mem_state.peek(addr).ok().and_then(|val| self.call_site(val))));
+        }
+        frames
+    }
+
+    fn call_site(&self, addr: u64) -> Option<u64>
+    {
+        let code_start = self.mem_state.get_code_base_ptr();
+        let code_end = code_start + self.mem_state.get_code_size();
+
+        if addr <= code_start || addr > code_end
+        {
+            return None
+        }
+
+        // a call with a constant has a size of 2, a call with a register a size of 1
+        (1..=2).filter(|size| addr - size >= code_start).map(|size| addr - size).find(|call| 
+            matches!(deserialize_asm(&self.mem_state, *call), 
+                Some((inst,next)) if inst.instruction == InstructionEnum::call && next == addr))
+    }
+
+    fn step_back(&mut self) -> Result<bool,String>
+    {
+        let entry = match self.journal.as_mut().map(|j| j.pop())
+        {
+            None => return Err("no history recorded, start the interpreter with --history".into()),
+            Some(None) => return Ok(false),
+            Some(Some(entry)) => entry,
+        };
+
+        // revert the writes in reverse order in case an adress was written multiple times
+        for (addr,old,_) in entry.mem_writes.iter().rev()
+        {
+            self.mem_state.restore(*addr, *old)?;
+        }
+
+        self.reg_state = entry.regs;
+        self.machine_information.set_cycles(entry.cycle);
+
+        Ok(true)
+    }
+
+    /// steps backwards `steps` instructions or until a breakpoint is reached
+    fn reverse(&mut self, until_breakpoint: bool, steps: u64)
+    {
+        let mut done = 0;
+
+        while done < steps
+        {
+            match self.step_back()
             {
-                self.machine_information.push_str(format!("unknown command: {}", input));    
+                Ok(true) => done += 1,
+                Ok(false) => 
+                {
+                    self.machine_information.push_str("reached the start of the history\n".into());
+                    break
+                },
+                Err(x) => 
+                {
+                    self.machine_information.push_str(format!("{}\n", x));
+                    return
+                },
+            }
+
+            if until_breakpoint && self.check_breakpoint()
+            {
+                break
             }
         }
+
+        // the machine does not stop at the watchpoints of the reverted writes
+        self.mem_state.take_watch_hits();
+
+        let pc = self.reg_state.read(Register::pc);
+        self.machine_information.push_str(format!("stepped back {} instructions to {}\n", done, self.debug.location(pc)));
+        self.print_state();
+    }
+
+    fn last_write(&mut self, addr: u64)
+    {
+        let write = self.journal.as_ref().map(|j| j.last_write(addr).map(|(e,old,new)| (e.regs.read(Register::pc), e.cycle, old, new)));
+
+        let s = match write
+        {
+            None => "no history recorded, start the interpreter with --history\n".into(),
+            Some(None) => format!("{} was not written in the recorded history\n", addr),
+            Some(Some((pc,cycle,old,new))) => 
+                format!("{} was written by {} in cycle {}: {} -> {}\n", addr, self.debug.location(pc), cycle, old, new),
+        };
+        self.machine_information.push_str(s);
+    }
+
+    fn check_assert(&mut self, name: String, val: Result<u64,String>, op: CompareOp, expected: u64)
+    {
+        let pc = self.reg_state.read(Register::pc);
+
+        let failure = match val
+        {
+            Ok(val) if op.compare(val, expected) => return,
+            Ok(val) => format!("{} is {}", name, val),
+            Err(x) => format!("could not read {}: {}", name, x),
+        };
+
+        self.debug.failed_asserts += 1;
+        self.machine_information.push_str(format!("assertion failed at {}: {} {} {} ({})\n", 
+            self.debug.location(pc), name, op.as_str(), expected, failure));
+    }
+
+    /// writes a snapshot of the machine that can be continued with `--resume`
+    fn dump(&mut self, file: &str) -> Result<(),String>
+    {
+        snapshot::save_file(self, file)?;
+        self.machine_information.push_str(format!("snapshot written to {}\n", file));
+        Ok(())
     }
 
     fn print_current_asm(&mut self)
@@ -156,11 +730,11 @@ impl MachineDebug for MachineState
         // the instruction that is about to be executed
         if let Some((inst,_)) = deserialization::deserialize_asm(&mut self.mem_state, pc)
         {
-            self.machine_information.push_str(format!("{}\t{}",pc,inst.as_string()))
+            self.machine_information.push_str(format!("{}\t{}\n",pc,inst.as_string()))
         }
         else 
         {
-            self.machine_information.push_str(format!("cannot read instruction at: {}",pc))    
+            self.machine_information.push_str(format!("cannot read instruction at: {}\n",pc))    
         }
     }
 
@@ -168,6 +742,10 @@ impl MachineDebug for MachineState
     {
         self.machine_information.push_str("----------------------------------\n".into());
         self.get_regs();
+        if self.debug.lines.is_some()
+        {
+            self.print_source();
+        }
         self.peek_instructions();
         self.peek_stack();
     }
@@ -248,7 +826,7 @@ impl MachineDebug for MachineState
 
         if !found_next_instriction
         {
-            self.machine_information.push_str(format!("can not fetch next instruction from: {}\t{:#?}\n",addr,self.mem_state.read(addr)))
+            self.machine_information.push_str(format!("can not fetch next instruction from: {}\t{:#?}\n",addr,self.mem_state.peek(addr)))
         } 
     }
 
@@ -257,7 +835,7 @@ impl MachineDebug for MachineState
     {
         self.machine_information.push_str("Stack:\n".into());
         let tos = self.reg_state.read(Register::tos);
-        let stack_size = self.mem_state.get_mem_size() -tos;  // use highest adress for this
+        let stack_size = (self.mem_state.get_stack_top()+1).saturating_sub(tos);  // use highest adress for this
 
         if tos==0 || stack_size==0 
         {
@@ -278,57 +856,229 @@ impl MachineDebug for MachineState
             // TODO: -1 until tos fix
             let addr = self.reg_state.read(Register::tos)+ ii;
             // TODO: handle fail 
-            let val = self.mem_state.read(addr).unwrap();
+            let val = self.mem_state.peek(addr).unwrap();
 
             self.machine_information.push_str(format!("\t{:#x}\t{}\n",addr,val));
 
         }
     }
-    
 }
 
 pub enum DebugCommand
 {
-    MemRead(u64),
-    PrintRegisters,
+    MemRead(u64,u64,NumberFormat),
+    PrintRegisters(Option<Register>,NumberFormat),
     PrintCurrentAsm,
     PrintState,
+    PrintSource,
+    Backtrace,
+    ReverseStep(u64),
+    ReverseContinue,
+    LastWrite(u64),
+    Assert(Condition),
+    AssertMem(u64,CompareOp,u64),
     Step(u64),
+    Next,
+    Finish,
     Continue,
-    Dump,
+    SetBreakpoint(u64,Option<Condition>),
+    UnsetBreakpoint(u64),
+    ListBreakpoints,
+    Watch(u64),
+    Unwatch(u64),
+    Dump(String),
+    Help,
     Exit,
 }
 
-fn get_debug_command(command: &str) -> Option<DebugCommand>
+fn parse_number(s: &str) -> Option<u64>
+{
+    match s.strip_prefix("0x")
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+pub(crate) fn parse_register(s: &str) -> Option<Register>
+{
+    match s
+    {
+        "a"     => Some(Register::a),
+        "b"     => Some(Register::b),
+        "c"     => Some(Register::c),
+        "d"     => Some(Register::d),
+        "e"     => Some(Register::e),
+        "f"     => Some(Register::f),
+        "s"     => Some(Register::s),
+        "pc"    => Some(Register::pc),
+        "tos"   => Some(Register::tos),
+        "bos"   => Some(Register::bos),
+        _       => None,
+    }
+}
+
+fn parse_format(s: &str) -> Result<NumberFormat,String>
+{
+    match s
+    {
+        "d" => Ok(NumberFormat::Dec),
+        "x" => Ok(NumberFormat::Hex),
+        "a" => Ok(NumberFormat::Ascii),
+        _   => Err(format!("unknown format: {} (use x, d or a)", s)),
+    }
+}
+
+/// parses `<reg> <op> <val>`
+pub(crate) fn parse_condition(parts: &[&str]) -> Result<Condition,String>
+{
+    if parts.len() != 3
+    {
+        return Err("a condition needs the form: <reg> <op> <val>".into())
+    }
+
+    let reg = parse_register(parts[0]).ok_or(format!("unknown register: {}", parts[0]))?;
+    let op = parse_compare_op(parts[1])?;
+    let val = parse_number(parts[2]).ok_or(format!("invalid value: {}", parts[2]))?;
+
+    Ok(Condition{reg, op, val})
+}
+
+fn parse_compare_op(s: &str) -> Result<CompareOp,String>
+{
+    match s
+    {
+        "==" => Ok(CompareOp::Eq),
+        "!=" => Ok(CompareOp::NotEq),
+        "<"  => Ok(CompareOp::Less),
+        ">"  => Ok(CompareOp::Greater),
+        "<=" => Ok(CompareOp::LessEq),
+        ">=" => Ok(CompareOp::GreaterEq),
+        x    => Err(format!("unknown comparison: {}", x)),
+    }
+}
+
+pub(crate) fn get_debug_command(command: &str, debug: &DebugInformation) -> Result<DebugCommand,String>
 {
     let parts:Vec<_> = command.trim().split_ascii_whitespace().filter(|x| !x.is_empty()).collect();
 
     // check if the input only contained whitespaces
     if parts.len() == 0
     {
-        return None
+        return Err("".into())
     }
 
+    let unknown = || format!("unknown command: {} (see help)", command.trim());
+
     match (parts[0], parts.len())
     {
-        ("exit",1)  => Some(DebugCommand::Exit),
-        ("ps",1)    => Some(DebugCommand::PrintState),
-        ("ins",1)   => Some(DebugCommand::PrintCurrentAsm),
+        ("exit",1)  => Ok(DebugCommand::Exit),
+        ("help",1)  => Ok(DebugCommand::Help),
+        ("ps",1)    => Ok(DebugCommand::PrintState),
+        ("ins",1)   => Ok(DebugCommand::PrintCurrentAsm),
+        ("src",1)   => Ok(DebugCommand::PrintSource),
+        ("bt",1)    => Ok(DebugCommand::Backtrace),
         
-        ("s",1)     => Some(DebugCommand::Step(0)), 
-        ("s",2)     => Some(DebugCommand::Step(parts[1].parse::<u64>().ok()?)),
+        ("s",1)     => Ok(DebugCommand::Step(0)), 
+        ("s",2)     => Ok(DebugCommand::Step(parse_number(parts[1]).ok_or(unknown())?)),
+        ("n",1) | ("next",1)    => Ok(DebugCommand::Next),
+        ("finish",1)            => Ok(DebugCommand::Finish),
+        ("rs",1)    => Ok(DebugCommand::ReverseStep(1)),
+        ("rs",2)    => Ok(DebugCommand::ReverseStep(parse_number(parts[1]).ok_or(unknown())?)),
+        ("rc",1)    => Ok(DebugCommand::ReverseContinue),
+        ("lw",2)    => Ok(DebugCommand::LastWrite(debug.resolve(parts[1])?)),
+        ("assert",4)=> Ok(DebugCommand::Assert(parse_condition(&parts[1..])?)),
+        ("assert",5) if parts[1] == "m" => Ok(DebugCommand::AssertMem(debug.resolve(parts[2])?, 
+            parse_compare_op(parts[3])?, parse_number(parts[4]).ok_or(format!("invalid value: {}", parts[4]))?)),
+
+        ("c",1)     => Ok(DebugCommand::Continue),
+        ("dump",1)  => Ok(DebugCommand::Dump("snapshot.json".into())),
+        ("dump",2)  => Ok(DebugCommand::Dump(parts[1].into())),
+
+        ("r",1)     => Ok(DebugCommand::PrintRegisters(None, NumberFormat::Dec)),
+        ("r",2)     => match parse_register(parts[1])
+        {
+            Some(reg) => Ok(DebugCommand::PrintRegisters(Some(reg), NumberFormat::Dec)),
+            None => Ok(DebugCommand::PrintRegisters(None, parse_format(parts[1])?)),
+        },
+        ("r",3)     => Ok(DebugCommand::PrintRegisters(
+            Some(parse_register(parts[1]).ok_or(format!("unknown register: {}", parts[1]))?), parse_format(parts[2])?)),
 
-        ("c",1)     => Some(DebugCommand::Continue),
-        ("dump",1)  => Some(DebugCommand::Dump),
-        ("m",2)     => Some(DebugCommand::MemRead(parts[1].parse::<u64>().ok()?)),
+        ("m",2)     => Ok(DebugCommand::MemRead(debug.resolve(parts[1])?, 1, NumberFormat::Dec)),
+        ("m",3)     => Ok(DebugCommand::MemRead(debug.resolve(parts[1])?, parse_number(parts[2]).ok_or(unknown())?, NumberFormat::Dec)),
+        ("m",4)     => Ok(DebugCommand::MemRead(debug.resolve(parts[1])?, parse_number(parts[2]).ok_or(unknown())?, parse_format(parts[3])?)),
 
         // set/unset breakpoints
-        ("set",2)   => todo!(),
-        ("unset",2) => todo!(),
+        ("b",2) | ("set",2)         => Ok(DebugCommand::SetBreakpoint(debug.resolve(parts[1])?, None)),
+        ("b",_) | ("set",_) if parts.len() > 2 && parts[2] == "if" 
+                                    => Ok(DebugCommand::SetBreakpoint(debug.resolve(parts[1])?, Some(parse_condition(&parts[3..])?))),
+        ("d",2) | ("unset",2)       => Ok(DebugCommand::UnsetBreakpoint(debug.resolve(parts[1])?)),
 
         // list active breakpoints
-        ("list",2)  => todo!(),
+        ("list",1)  => Ok(DebugCommand::ListBreakpoints),
 
-        _ => None 
+        ("w",2)     => Ok(DebugCommand::Watch(debug.resolve(parts[1])?)),
+        ("uw",2)    => Ok(DebugCommand::Unwatch(debug.resolve(parts[1])?)),
+
+        _ => Err(unknown()),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn debug_info() -> DebugInformation
+    {
+        let mut symbols = HashMap::new();
+        symbols.insert(1, vec!["start".to_string()]);
+        symbols.insert(10, vec!["loop".to_string(), "inner".to_string()]);
+        let lines = LineTable::parse("1\t5\tin.asm\n10\t9\tin.asm\n", Default::default());
+        DebugInformation::new(Some(HashSet::new()), Some(symbols), Some(lines))
+    }
+
+    #[test]
+    fn resolve_location_test()
+    {
+        let debug = debug_info();
+
+        assert_eq!(debug.resolve("0x10"), Ok(16));
+        assert_eq!(debug.resolve("inner"), Ok(10));
+        assert_eq!(debug.resolve(".start"), Ok(1));
+        assert!(debug.resolve("missing").is_err());
+        assert_eq!(debug.resolve("in.asm:7"), Ok(10));
+        assert!(debug.resolve("in.asm:10").is_err());
+        assert_eq!(debug.location(13), "13 (loop+3) in.asm:9");
+
+        assert_eq!(debug.label_range("start"), Ok((1,10)));
+        assert_eq!(debug.label_range(".loop"), Ok((10,u64::MAX)));
+        assert!(debug.label_range("0x10").is_err());
+    }
+
+    #[test]
+    fn parse_breakpoint_command_test()
+    {
+        let debug = debug_info();
+
+        match get_debug_command("b loop if a >= 0x20\n", &debug)
+        {
+            Ok(DebugCommand::SetBreakpoint(10, Some(cond))) => 
+            {
+                assert_eq!(cond, Condition{reg: Register::a, op: CompareOp::GreaterEq, val: 32});
+                assert!(cond.holds(32) && !cond.holds(31));
+            },
+            _ => panic!("could not parse conditional breakpoint"),
+        }
+
+        assert!(matches!(get_debug_command("m start 4 x", &debug), Ok(DebugCommand::MemRead(1, 4, NumberFormat::Hex))));
+        assert!(matches!(get_debug_command("r c a", &debug), Ok(DebugCommand::PrintRegisters(Some(Register::c), NumberFormat::Ascii))));
+        assert!(get_debug_command("b loop if q == 1", &debug).is_err());
+        assert!(get_debug_command("set", &debug).is_err());
+
+        assert!(matches!(get_debug_command("assert a != 3", &debug), 
+            Ok(DebugCommand::Assert(Condition{reg: Register::a, op: CompareOp::NotEq, val: 3}))));
+        assert!(matches!(get_debug_command("assert m loop < 0x10", &debug), Ok(DebugCommand::AssertMem(10, CompareOp::Less, 16))));
+        assert!(get_debug_command("assert m loop 3", &debug).is_err());
+    }
+}
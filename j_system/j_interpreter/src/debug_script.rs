@@ -0,0 +1,215 @@
+use crate::machine::MachineState;
+use crate::debug::{MachineDebug,DebugFrontend,ContinueAfterDebug,StopReason,get_debug_command};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Write;
+use std::rc::Rc;
+
+/// runs the debugger commands of a file instead of reading them from stdin.
+///
+/// Every stop of the machine takes commands from the script until a command
+/// continues the machine. When the script has ended the command line takes over,
+/// in batch mode the machine just keeps running
+pub struct DebugScript
+{
+    /// the remaining commands with their line number
+    commands: VecDeque<(usize,String)>,
+
+    /// never read from stdin
+    batch: bool,
+
+    /// gets the commands, the output of the debugger and the output of the programm
+    log: Box<dyn Write>,
+
+    /// a command of the script could not be parsed, the machine was stopped
+    error: Option<String>,
+}
+
+/// hands the stops of the machine to the script that is shared with `DebugScript::run_batch`
+struct ScriptFrontend(Rc<RefCell<DebugScript>>);
+
+impl DebugFrontend for ScriptFrontend
+{
+    fn stopped(&mut self, machine: &mut MachineState, _reason: StopReason, description: &str) -> ContinueAfterDebug
+    {
+        self.0.borrow_mut().stopped(machine, description)
+    }
+}
+
+impl DebugScript
+{
+    /// empty lines and lines starting with `#` are ignored
+    pub fn parse(input: &str, batch: bool, log: Box<dyn Write>) -> Self
+    {
+        let commands = input.lines().enumerate()
+            .map(|(ii,line)| (ii+1, line.trim().to_string()))
+            .filter(|(_,line)| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+
+        Self{commands, batch, log, error: None}
+    }
+
+    pub fn load(file: &str, batch: bool, log: Box<dyn Write>) -> Result<Self,String>
+    {
+        let input = fs::read_to_string(file).map_err(|e| format!("could not read the debug script {}: {}", file, e))?;
+        Ok(Self::parse(&input, batch, log))
+    }
+
+    /// lets the script handle the stops of the machine, the machine is run as usual
+    pub fn attach(self, machine: &mut MachineState)
+    {
+        machine.debug.frontend = Some(Box::new(ScriptFrontend(Rc::new(RefCell::new(self)))));
+    }
+
+    /// runs the programm without reading from stdin.
+    /// Fails if the programm failed or an assertion did not hold
+    pub fn run_batch(mut self, machine: &mut MachineState) -> Result<(),String>
+    {
+        self.batch = true;
+        machine.machine_information.buffer_output();
+
+        let script = Rc::new(RefCell::new(self));
+
+        machine.debug.frontend = Some(Box::new(ScriptFrontend(script.clone())));
+        let result = machine.run();
+        machine.debug.frontend = None;
+
+        let mut script = script.borrow_mut();
+        script.flush(machine);
+
+        if let Err(x) = &result
+        {
+            script.write(&format!("programm failed: {}\n", x));
+        }
+
+        let failed = machine.debug.failed_asserts;
+        script.write(&format!("cycles used: {}, failed assertions: {}\n", machine.machine_information.get_cycles(), failed));
+
+        result?;
+        if let Some(x) = script.error.take()
+        {
+            return Err(x)
+        }
+
+        match failed
+        {
+            0 => Ok(()),
+            _ => Err(format!("{} assertion(s) failed", failed)),
+        }
+    }
+
+    fn stopped(&mut self, machine: &mut MachineState, description: &str) -> ContinueAfterDebug
+    {
+        if self.commands.is_empty() && !self.batch
+        {
+            return machine.breakpoint_cli(description.into())
+        }
+
+        self.flush(machine);
+        self.write(description);
+
+        while let Some((line,command)) = self.commands.pop_front()
+        {
+            self.write(&format!("(dbg) {}\n", command));
+
+            let cont = match get_debug_command(&command, &machine.debug)
+            {
+                Ok(command) => machine.run_debug_command(command),
+                Err(x) =>
+                {
+                    let error = format!("error in line {} of the debug script: {}", line, x);
+                    self.write(&format!("{}\n", error));
+                    self.error = Some(error);
+                    Some(ContinueAfterDebug::Quit)
+                },
+            };
+
+            self.flush(machine);
+
+            if let Some(cont) = cont
+            {
+                return cont
+            }
+        }
+
+        match self.batch
+        {
+            true => ContinueAfterDebug::Continue,
+            false => machine.breakpoint_cli(String::new()),
+        }
+    }
+
+    /// writes the buffered output of the machine to the log
+    fn flush(&mut self, machine: &mut MachineState)
+    {
+        let output:String = machine.machine_information.take_output().into_iter().map(|(_,s)| s).collect();
+        self.write(&output);
+    }
+
+    fn write(&mut self, s: &str)
+    {
+        if let Err(x) = self.log.write_all(s.as_bytes()).and_then(|_| self.log.flush())
+        {
+            eprintln!("could not write the debug log: {}", x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::MachineInitInfo;
+    use crate::test_util::{SharedBuf,assembled_machine};
+
+    use std::collections::HashSet;
+
+    fn machine() -> MachineState
+    {
+        let mut machine = assembled_machine(MachineInitInfo{debug_mode: Some(HashSet::new()), ..Default::default()}, "
+_rom
+_code
+    .start:
+        mov a, 0
+    .loop:
+        add a, 1
+        cmp a, 5
+        jl .loop
+        push 9
+        sys
+");
+
+        // stop before the first instruction
+        machine.debug.debug_step = Some(0);
+        machine
+    }
+
+    fn run(script: &str) -> (Result<(),String>, String)
+    {
+        let log = SharedBuf::default();
+        let result = DebugScript::parse(script, true, Box::new(log.clone())).run_batch(&mut machine());
+        (result, log.text())
+    }
+
+    #[test]
+    fn batch_script_test()
+    {
+        let (result,log) = run("# stop in the loop\nb loop\nc\n\nc\nassert a == 1\nassert m 1 != 0\nd loop\nc\n");
+        assert_eq!(result, Ok(()), "{}", log);
+        assert!(log.contains("(dbg) assert a == 1\n(dbg) assert m 1 != 0\n"));
+        assert!(log.contains("failed assertions: 0"));
+
+        let (result,log) = run("b loop\nc\ns 2\nassert a == 3\n");
+        assert!(result.is_err());
+        assert!(log.contains("assertion failed at 3 (loop) t.asm:7: a == 3 (a is 1)"), "{}", log);
+
+        let (result,log) = run("c\nunknown\n");
+        assert_eq!(result, Ok(()));
+        assert!(!log.contains("unknown command"));
+
+        let (result,_) = run("b missing\n");
+        assert_eq!(result, Err("error in line 1 of the debug script: unknown label: missing".into()));
+    }
+}
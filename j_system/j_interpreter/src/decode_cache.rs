@@ -0,0 +1,110 @@
+use crate::deserialization::deserialize_asm;
+use crate::memory::MemModel;
+
+use j_system_definition::instructions::AsmLine;
+
+/// the decoded instructions of the code section, so every instruction
+/// only gets decoded the first time it is executed.
+///
+/// Writes to the code section invalidate the instructions that contain the
+/// written adress. Instructions outside of the code section are always decoded
+pub struct DecodeCache
+{
+    enabled: bool,
+
+    /// adress of the first entry
+    base: u64,
+
+    /// the instruction and the adress of the next instruction.
+    /// Key: adress - base
+    entries: Vec<Option<(AsmLine,u64)>>,
+}
+
+/// an instruction is at most 3 values long (instruction and 2 parameters)
+const MAX_INSTRUCTION_SIZE: u64 = 3;
+
+impl DecodeCache
+{
+    pub fn new(enabled: bool) -> Self
+    {
+        Self{enabled, base: 0, entries: vec![]}
+    }
+
+    /// removes all instructions and adapts the cache to the code section of the memory.
+    /// Has to be called when new code is loaded
+    pub fn reset(&mut self, mem: &mut MemModel)
+    {
+        mem.take_code_writes();
+        self.base = mem.get_code_base_ptr();
+        self.entries = match self.enabled
+        {
+            true => vec![None; mem.get_code_size() as usize],
+            false => vec![],
+        };
+    }
+
+    /// the instruction at the adress and the adress of the next instruction
+    pub fn fetch(&mut self, mem: &mut MemModel, addr: u64) -> Option<(AsmLine,u64)>
+    {
+        if mem.has_code_writes()
+        {
+            self.invalidate(&mem.take_code_writes());
+        }
+
+        let entry = match addr.checked_sub(self.base).and_then(|index| self.entries.get_mut(index as usize))
+        {
+            Some(entry) => entry,
+            None => return deserialize_asm(mem, addr),
+        };
+
+        if entry.is_none()
+        {
+            *entry = deserialize_asm(mem, addr);
+        }
+        entry.clone()
+    }
+
+    /// removes the instructions that contain the written adresses
+    pub fn invalidate(&mut self, writes: &[u64])
+    {
+        for written in writes
+        {
+            let first = written.saturating_sub(MAX_INSTRUCTION_SIZE-1).max(self.base);
+            for entry in first..=*written
+            {
+                if let Some(entry) = self.entries.get_mut((entry - self.base) as usize)
+                {
+                    *entry = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::machine_with_code;
+
+    #[test]
+    fn self_modifying_code_test()
+    {
+        // mov a, 1; mov [6], 0x0121_0000_0001_0000; mov a, 5 (becomes add a, 5); push 9; sys (End)
+        let code = vec![0x0F21_0000_0001_0000, 1, 0x0F31_0000_0000_0000, 6, 0x0121_0000_0001_0000,
+                        0x0F21_0000_0001_0000, 5, 0x1010_0000_0000_0000, 9, 0x1600_0000_0000_0000];
+
+        for enabled in [true, false]
+        {
+            let mut machine = machine_with_code(&code);
+            machine.decode_cache = DecodeCache::new(enabled);
+            machine.decode_cache.reset(&mut machine.mem_state);
+
+            // decode the original instruction before it gets overwritten
+            assert_eq!(machine.decode_cache.fetch(&mut machine.mem_state, 6).unwrap().0.as_string(), "mov\t a, 5");
+
+            machine.run().unwrap();
+            assert_eq!(machine.reg_state.read(j_system_definition::register::Register::a), 6);
+        }
+    }
+}
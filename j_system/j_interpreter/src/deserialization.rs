@@ -17,7 +17,7 @@ use crate::type_cov_parse::*;
 /// Retruns None when the instruction cant be read
 pub fn deserialize_asm(mem_state: &MemModel, mut ptr:u64) -> Option<(AsmLine,u64)>
 {
-    let serialized_line = mem_state.read(ptr).ok()?;//.unwrap();
+    let serialized_line = mem_state.peek(ptr).ok()?;//.unwrap();
      
     // the first 8 bits contain the instruction.
     // get the masked bits of the instruction and
@@ -45,7 +45,7 @@ pub fn deserialize_asm(mem_state: &MemModel, mut ptr:u64) -> Option<(AsmLine,u64
     
     if parameter_amount >= 1 
     {
-        let preload_next_line = mem_state.read(ptr);
+        let preload_next_line = mem_state.peek(ptr);
         let (param,advance_ptr) = get_param_and_insert_val(param_type1,param_ad1,preload_next_line.ok())?;
         par1 = Some(param);
 
@@ -57,7 +57,7 @@ pub fn deserialize_asm(mem_state: &MemModel, mut ptr:u64) -> Option<(AsmLine,u64
 
         if parameter_amount >= 2 
         {
-            let preload_next_line = mem_state.read(ptr);
+            let preload_next_line = mem_state.peek(ptr);
             let (param,advance_ptr) = get_param_and_insert_val(param_type2,param_ad2,preload_next_line.ok())?;
 
             par2 = Some(param);
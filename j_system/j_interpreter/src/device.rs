@@ -0,0 +1,365 @@
+use crate::input::InputSource;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// a device that is mapped into the adress space of the machine.
+/// All offsets are relative to the base adress of the mapping.
+pub trait Device
+{
+    fn read(&mut self, offset: u64) -> Result<u64,String>;
+    fn write(&mut self, offset: u64, val: u64) -> Result<(),String>;
+
+    /// the value a read would return, without changing the device.
+    /// Used by the debugger and the cost model
+    fn peek(&self, offset: u64) -> Result<u64,String>;
+
+    /// gets called after every instruction with the current cycle count
+    fn tick(&mut self, _cycle: u128) {}
+
+    /// gets called when the machine stops
+    fn finish(&mut self) -> Result<(),String> {Ok(())}
+
+    /// output that was written since the last call.
+    /// It gets added to the output of the machine after every instruction
+    fn take_output(&mut self) -> Option<String> {None}
+}
+
+/// character console
+///
+/// offset 0: write a byte to the output of the machine, chars are utf-8 encoded
+/// offset 1: read a char from the input (`u64::MAX` on EOF)
+pub struct ConsoleDevice
+{
+    /// the bytes that were not passed to the machine yet
+    output: Vec<u8>,
+    input: InputSource,
+}
+
+pub const CONSOLE_SIZE:u64 = 2;
+
+impl ConsoleDevice
+{
+    /// console that reads from stdin
+    pub fn new() -> Self
+    {
+        Self::with_input(InputSource::Stdin)
+    }
+
+    pub fn with_input(input: InputSource) -> Self
+    {
+        Self{output: vec![], input}
+    }
+}
+
+impl Default for ConsoleDevice
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Device for ConsoleDevice
+{
+    fn read(&mut self, offset: u64) -> Result<u64,String>
+    {
+        match offset
+        {
+            1 => Ok(self.input.read_byte().map(|b| b as u64).unwrap_or(u64::MAX)),
+            _ => self.peek(offset),
+        }
+    }
+
+    fn peek(&self, offset: u64) -> Result<u64,String>
+    {
+        match offset
+        {
+            0 => Ok(0),
+            1 => Err("the console input can only be read by the programm".into()),
+            _ => Err(format!("console has no register {}", offset)),
+        }
+    }
+
+    fn write(&mut self, offset: u64, val: u64) -> Result<(),String>
+    {
+        match offset
+        {
+            0 if val <= u8::MAX as u64 => {self.output.push(val as u8); Ok(())},
+            0 => Err(format!("console can not print {}", val)),
+            _ => Err(format!("console register {} is read only", offset)),
+        }
+    }
+
+    /// a char that is split over multiple writes is passed on when it is complete
+    fn take_output(&mut self) -> Option<String>
+    {
+        let rest = self.output.split_off(complete_len(&self.output));
+
+        match self.output.is_empty()
+        {
+            true => {self.output = rest; None},
+            false => Some(String::from_utf8_lossy(&std::mem::replace(&mut self.output, rest)).to_string()),
+        }
+    }
+
+    /// a char that was not completed is replaced, so it still shows up in the output
+    fn finish(&mut self) -> Result<(),String>
+    {
+        self.output = String::from_utf8_lossy(&self.output).into_owned().into_bytes();
+        Ok(())
+    }
+}
+
+/// the length of the bytes without a utf-8 char at the end that is not complete yet
+fn complete_len(bytes: &[u8]) -> usize
+{
+    for start in (bytes.len().saturating_sub(3)..bytes.len()).rev()
+    {
+        let char_len = match bytes[start]
+        {
+            0x80..=0xBF => continue,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+
+        return if bytes.len() - start < char_len {start} else {bytes.len()}
+    }
+    bytes.len()
+}
+
+/// counts the cycles of the machine
+///
+/// offset 0: cycles since the last reset (read only)
+/// offset 1: writing any value resets the timer
+pub struct TimerDevice
+{
+    cycle: u128,
+    reset_at: u128,
+}
+
+pub const TIMER_SIZE:u64 = 2;
+
+impl TimerDevice
+{
+    pub fn new() -> Self
+    {
+        Self{cycle: 0, reset_at: 0}
+    }
+}
+
+impl Default for TimerDevice
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Device for TimerDevice
+{
+    fn read(&mut self, offset: u64) -> Result<u64,String>
+    {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u64) -> Result<u64,String>
+    {
+        match offset
+        {
+            0 => Ok((self.cycle - self.reset_at) as u64),
+            1 => Ok(0),
+            _ => Err(format!("timer has no register {}", offset)),
+        }
+    }
+
+    fn write(&mut self, offset: u64, _val: u64) -> Result<(),String>
+    {
+        match offset
+        {
+            1 => {self.reset_at = self.cycle; Ok(())},
+            _ => Err(format!("timer register {} is read only", offset)),
+        }
+    }
+
+    fn tick(&mut self, cycle: u128)
+    {
+        self.cycle = cycle;
+    }
+}
+
+/// text-mode framebuffer with one char per cell.
+/// The cells are stored row by row.
+pub struct FramebufferDevice
+{
+    width: u64,
+    height: u64,
+    cells: Vec<u64>,
+
+    /// file the framebuffer gets written to when the machine stops
+    dump_file: Option<PathBuf>,
+}
+
+/// the framebuffer can't have more cells than this
+pub const MAX_FRAMEBUFFER_CELLS:u64 = 1 << 20;
+
+impl FramebufferDevice
+{
+    /// fails if the framebuffer would have more than `MAX_FRAMEBUFFER_CELLS` cells
+    pub fn new(width: u64, height: u64, dump_file: Option<PathBuf>) -> Result<Self,String>
+    {
+        let size = width.checked_mul(height).filter(|size| *size <= MAX_FRAMEBUFFER_CELLS)
+            .ok_or(format!("the framebuffer {}x{} has more than {} cells", width, height, MAX_FRAMEBUFFER_CELLS))?;
+
+        Ok(Self{width, height, cells: vec![0; size as usize], dump_file})
+    }
+
+    pub fn size(&self) -> u64
+    {
+        self.width * self.height
+    }
+
+    /// the content of the framebuffer as text.
+    /// cells that do not contain a printable ascii char are shown as space
+    pub fn dump(&self) -> String
+    {
+        let mut s = String::new();
+
+        for row in self.cells.chunks(self.width.max(1) as usize)
+        {
+            let line:String = row.iter().map(|c| match *c
+            {
+                x if x < 128 && (x as u8 as char).is_ascii_graphic() => x as u8 as char,
+                _ => ' ',
+            }).collect();
+
+            s.push_str(line.trim_end());
+            s.push('\n');
+        }
+
+        s
+    }
+}
+
+impl Device for FramebufferDevice
+{
+    fn read(&mut self, offset: u64) -> Result<u64,String>
+    {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: u64) -> Result<u64,String>
+    {
+        self.cells.get(offset as usize).copied().ok_or(format!("framebuffer has no cell {}", offset))
+    }
+
+    fn write(&mut self, offset: u64, val: u64) -> Result<(),String>
+    {
+        let cell = self.cells.get_mut(offset as usize).ok_or(format!("framebuffer has no cell {}", offset))?;
+        *cell = val;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(),String>
+    {
+        if let Some(file) = &self.dump_file
+        {
+            fs::write(file, self.dump()).map_err(|e| format!("could not dump framebuffer: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn framebuffer_dump_test()
+    {
+        let mut fb = FramebufferDevice::new(3, 2, None).unwrap();
+
+        fb.write(0, b'a' as u64).unwrap();
+        fb.write(2, b'c' as u64).unwrap();
+        fb.write(4, b'e' as u64).unwrap();
+
+        assert_eq!(fb.dump(), "a c\n e\n");
+        assert!(fb.write(6, 1).is_err());
+
+        assert!(FramebufferDevice::new(u64::MAX, 2, None).is_err());
+        assert!(FramebufferDevice::new(MAX_FRAMEBUFFER_CELLS, 2, None).is_err());
+    }
+
+    #[test]
+    fn framebuffer_dump_after_fault_test()
+    {
+        use crate::machine::{MachineInitInfo,MachineState};
+        use crate::load_bin::Binary;
+
+        let dump = std::env::temp_dir().join("j_interpreter_framebuffer_fault_test.txt");
+        let _ = fs::remove_file(&dump);
+
+        let source = "_rom\n_code\n.start:\nmov [500], 104\nmov a, [0]\n";
+        let assembly = j_assembler::assembler::assemble(source.lines().map(|l| l.to_string()).collect(), "t.asm".into()).unwrap();
+
+        let mut machine = MachineState::init(MachineInitInfo{write_to_file: true, ..Default::default()});
+        machine.laod_into_state(Binary::from_assembly(&assembly)).unwrap();
+        machine.mem_state.map_device(500, 2, Box::new(FramebufferDevice::new(2, 1, Some(dump.clone())).unwrap())).unwrap();
+
+        assert!(machine.run().is_err());
+        assert_eq!(fs::read_to_string(&dump).ok(), Some("h\n".to_string()));
+        let _ = fs::remove_file(dump);
+    }
+
+    #[test]
+    fn console_test()
+    {
+        let mut console = ConsoleDevice::with_input(InputSource::from_bytes(b"x"));
+
+        console.write(0, b'h' as u64).unwrap();
+        console.write(0, b'i' as u64).unwrap();
+        assert!(console.write(0, 256).is_err());
+        assert_eq!(console.take_output(), Some("hi".to_string()));
+        assert_eq!(console.take_output(), None);
+
+        // peeking does not consume the input
+        assert!(console.peek(1).is_err());
+        assert_eq!(console.read(1), Ok(b'x' as u64));
+        assert_eq!(console.read(1), Ok(u64::MAX));
+    }
+
+    #[test]
+    fn console_utf8_test()
+    {
+        let mut console = ConsoleDevice::with_input(InputSource::from_bytes(b""));
+
+        // the chars are only passed on when all of their bytes were written
+        for (bytes,expected) in [(&b"a\xC3"[..], Some("a")), (b"\xA9", Some("\u{e9}")), (b"\xE2\x82", None), (b"\xAC!", Some("\u{20ac}!")), (b"\xFF", Some("\u{fffd}"))]
+        {
+            bytes.iter().for_each(|b| console.write(0, *b as u64).unwrap());
+            assert_eq!(console.take_output().as_deref(), expected);
+        }
+
+        // an incomplete char is replaced when the machine stops
+        console.write(0, 0xF0).unwrap();
+        assert_eq!(console.take_output(), None);
+        console.finish().unwrap();
+        assert_eq!(console.take_output().as_deref(), Some("\u{fffd}"));
+    }
+
+    #[test]
+    fn timer_test()
+    {
+        let mut timer = TimerDevice::new();
+
+        timer.tick(10);
+        assert_eq!(timer.read(0), Ok(10));
+        timer.write(1, 0).unwrap();
+        timer.tick(15);
+        assert_eq!(timer.read(0), Ok(5));
+        assert!(timer.write(0, 0).is_err());
+    }
+}
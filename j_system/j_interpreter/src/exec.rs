@@ -5,9 +5,16 @@ use crate::check_instruction::*;
 use crate::syscall::*;
 use crate::machine::{MachineState, InstructionReturn, Exec};
 
+use std::convert::TryFrom;
+
 impl Exec for MachineState{
 fn run_instruction(&mut self, inst: AsmLine) -> InstructionReturn
 {
+    if let Err(msg) = check_destination(&inst)
+    {
+        return InstructionReturn::Err(msg);
+    }
+
     match inst.instruction
     {
         InstructionEnum::add    => self.add(inst.get_param1(),inst.get_param2()),
@@ -27,8 +34,8 @@ fn run_instruction(&mut self, inst: AsmLine) -> InstructionReturn
         InstructionEnum::ret    => self.ret(inst.get_param1(),inst.get_param2()),
         InstructionEnum::call   => self.call(inst.get_param1(),inst.get_param2()),
         InstructionEnum::or     => self.or(inst.get_param1(),inst.get_param2()),
-        InstructionEnum::popa   => unimplemented!("TODO: popa"),
-        InstructionEnum::pusha  => unimplemented!("TODO: pusha"),
+        InstructionEnum::popa   => InstructionReturn::Err("TODO: popa is not implemented".to_string()),
+        InstructionEnum::pusha  => InstructionReturn::Err("TODO: pusha is not implemented".to_string()),
         InstructionEnum::jeg    => self.jeg(inst.get_param1(),inst.get_param2()),
         InstructionEnum::jg     => self.jg(inst.get_param1(),inst.get_param2()),
         InstructionEnum::xor    => self.xor(inst.get_param1(),inst.get_param2()),
@@ -243,8 +250,10 @@ fn push(&mut self, param1: Option<Param>, param2: Option<Param>) -> InstructionR
 
     let val = self.get_param_value(&param1);
     
-    // TODO: handle result
-    self.push_stack(val).unwrap();
+    if let Err(x) = self.push_stack(val)
+    {
+        return InstructionReturn::Err(x);
+    }
     
     InstructionReturn::Next
 }
@@ -328,13 +337,20 @@ fn ret(&mut self, param1: Option<Param>, param2: Option<Param>) -> InstructionRe
 
     // get the retrun adress
     // add 1 to not jump to the call again but the instruction after that
-    // TODO: handle empty stack
-    let jmp_adress = self.pop_stack().unwrap();
+    let jmp_adress = match self.pop_stack()
+    {
+        Ok(adress) => adress,
+        Err(msg) => return InstructionReturn::Err(msg),
+    };
 
     // remove arguments on stack
     for _ in 0..stack_clean_amount //as usize
     { 
-        // add the ammount 
+        // the stack is empty
+        if self.reg_state.read(Register::tos) == 0
+        {
+            break;
+        }
         let _ = self.pop_stack();
     }
 
@@ -383,7 +399,8 @@ fn shr(&mut self, param1: Option<Param>, param2: Option<Param>) -> InstructionRe
 
     let mut val = self.get_param_value(&param1);
 
-    val = val >> shift_amount;
+    // shifting by 64 bits clears the register
+    val = u32::try_from(shift_amount).ok().and_then(|s| val.checked_shr(s)).unwrap_or(0);
 
     self.store_in_dest(val, &param1);
 
@@ -411,7 +428,8 @@ fn shl(&mut self, param1: Option<Param>, param2: Option<Param>) -> InstructionRe
 
     let mut val = self.get_param_value(&param1);
 
-    val = val << shift_amount;
+    // shifting by 64 bits clears the register
+    val = u32::try_from(shift_amount).ok().and_then(|s| val.checked_shl(s)).unwrap_or(0);
 
     self.store_in_dest(val, &param1);
 
@@ -428,7 +446,10 @@ fn call(&mut self, param1: Option<Param>, param2: Option<Param>) -> InstructionR
     // TODO: save registers?, stackframe?
     
     // save return adress
-    self.push_stack(self.next_ptr).unwrap();
+    if let Err(x) = self.push_stack(self.next_ptr)
+    {
+        return InstructionReturn::Err(x);
+    }
 
     InstructionReturn::JumpTo(self.get_param_value(&param1))
 }
@@ -449,7 +470,40 @@ fn sys(&mut self, param1: Option<Param>, param2: Option<Param>) -> InstructionRe
         SysCallAction::Err(x)   => InstructionReturn::Err(x),
         SysCallAction::Ok       => InstructionReturn::Next,
         SysCallAction::Ptr(x)   => { self.reg_state.store(Register::f, x); InstructionReturn::Next}
+        SysCallAction::Value(x) => { self.reg_state.store(Register::f, x); InstructionReturn::Next}
+    }
+
+}
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::machine::MachineInitInfo;
+    use crate::test_util::assembled_machine;
+
+    use j_system_definition::register::Register;
+
+    fn shift(inst: &str, val: u64, amount: u64) -> Result<u64,String>
+    {
+        let mut machine = assembled_machine(MachineInitInfo::default(), &format!("_rom\n_code\n    .start:\n        {} a, b\n        push 9\n        sys\n", inst));
+        machine.reg_state.store(Register::a, val);
+        machine.reg_state.store(Register::b, amount);
+        machine.run().map(|_| machine.reg_state.read(Register::a))
     }
 
+    #[test]
+    fn shift_test()
+    {
+        assert_eq!(shift("shl", 1, 3), Ok(8));
+        assert_eq!(shift("shr", 8, 3), Ok(1));
+
+        // shifting by 64 bits clears the register
+        assert_eq!(shift("shl", 1, 64), Ok(0));
+        assert_eq!(shift("shr", u64::MAX, 64), Ok(0));
+
+        // the amount is not truncated to its lower 32 bits
+        assert!(shift("shl", 1, (1 << 32) + 1).is_err());
+        assert!(shift("shr", 2, (1 << 32) + 1).is_err());
+    }
 }
-}
\ No newline at end of file
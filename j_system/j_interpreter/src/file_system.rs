@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// the first file descriptor that is handed out by `open`.
+/// 0 and 1 are reserved for stdin and stdout
+pub const FIRST_FD:u64 = 3;
+
+/// files of the virtual file system can't grow larger than this.
+/// protects the host from programms that seek far behind the end and write
+pub const MAX_VIRTUAL_FILE_SIZE:u64 = 1 << 24;
+
+/// the file system the guest programm can access through the file syscalls
+pub enum FileSystem
+{
+    /// no file access is allowed
+    None,
+
+    /// all paths are relative to this directory.
+    /// paths that would leave the directory are rejected
+    Sandbox(PathBuf),
+
+    /// in memory file system that is mainly used for tests.
+    /// Key: file name, Value: content of the file
+    Virtual(HashMap<String,Vec<u8>>),
+}
+
+/// the mode a file gets opened with
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum OpenMode
+{
+    /// read only, the file must exist
+    Read,
+    /// write only, the file gets created or truncated
+    Write,
+    /// write only at the end of the file, the file gets created if it does not exist
+    Append,
+    /// read and write, the file gets created if it does not exist
+    ReadWrite,
+}
+
+impl OpenMode
+{
+    pub fn from_u64(mode: u64) -> Option<Self>
+    {
+        match mode
+        {
+            1 => Some(Self::Read),
+            2 => Some(Self::Write),
+            3 => Some(Self::Append),
+            4 => Some(Self::ReadWrite),
+            _ => None,
+        }
+    }
+
+    fn readable(&self) -> bool
+    {
+        matches!(self, Self::Read | Self::ReadWrite)
+    }
+
+    fn writable(&self) -> bool
+    {
+        !matches!(self, Self::Read)
+    }
+}
+
+enum OpenFile
+{
+    Host(File),
+    Virtual{name: String, pos: u64, mode: OpenMode},
+}
+
+/// holds the file system and all files that were opened by the guest
+pub struct FileTable
+{
+    fs: FileSystem,
+    open_files: HashMap<u64,OpenFile>,
+    next_fd: u64,
+}
+
+impl FileTable
+{
+    pub fn new(fs: FileSystem) -> Self
+    {
+        Self{fs, open_files: HashMap::new(), next_fd: FIRST_FD}
+    }
+
+    pub fn has_open_files(&self) -> bool
+    {
+        !self.open_files.is_empty()
+    }
+
+    /// returns the content of a file in the virtual file system
+    pub fn get_virtual_file(&self, name: &str) -> Option<&Vec<u8>>
+    {
+        match &self.fs
+        {
+            FileSystem::Virtual(files) => files.get(name),
+            _ => None,
+        }
+    }
+
+    pub fn open(&mut self, name: &str, mode: OpenMode) -> Result<u64,String>
+    {
+        let file = match &mut self.fs
+        {
+            FileSystem::None => return Err("file access is not enabled".into()),
+
+            FileSystem::Sandbox(root) =>
+            {
+                let path = sandboxed_path(root, name)?;
+
+                let mut options = OpenOptions::new();
+                match mode
+                {
+                    OpenMode::Read      => options.read(true),
+                    OpenMode::Write     => options.write(true).create(true).truncate(true),
+                    OpenMode::Append    => options.append(true).create(true),
+                    OpenMode::ReadWrite => options.read(true).write(true).create(true),
+                };
+
+                OpenFile::Host(options.open(path).map_err(|e| format!("could not open {}: {}", name, e))?)
+            },
+
+            FileSystem::Virtual(files) =>
+            {
+                check_file_name(name)?;
+
+                match mode
+                {
+                    OpenMode::Read => if !files.contains_key(name)
+                    {
+                        return Err(format!("could not open {}: file does not exist", name))
+                    },
+                    OpenMode::Write => {files.insert(name.into(), vec![]);},
+                    OpenMode::Append | OpenMode::ReadWrite => {files.entry(name.into()).or_default();},
+                }
+
+                let pos = if mode == OpenMode::Append {files[name].len() as u64} else {0};
+                OpenFile::Virtual{name: name.into(), pos, mode}
+            },
+        };
+
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.open_files.insert(fd, file);
+
+        Ok(fd)
+    }
+
+    pub fn close(&mut self, fd: u64) -> Result<(),String>
+    {
+        self.open_files.remove(&fd).map(|_| ()).ok_or(format!("invalid file descriptor: {}", fd))
+    }
+
+    /// reads up to `max` bytes. returns less bytes if the end of the file was reached
+    pub fn read(&mut self, fd: u64, max: u64) -> Result<Vec<u8>,String>
+    {
+        let (file, fs) = (self.open_files.get_mut(&fd).ok_or(format!("invalid file descriptor: {}", fd))?, &self.fs);
+
+        match file
+        {
+            OpenFile::Host(f) =>
+            {
+                let mut buf = vec![];
+                f.take(max).read_to_end(&mut buf).map_err(|e| e.to_string())?;
+                Ok(buf)
+            },
+
+            OpenFile::Virtual{name, pos, mode} =>
+            {
+                if !mode.readable()
+                {
+                    return Err(format!("file descriptor {} is not readable", fd))
+                }
+
+                let content = match fs
+                {
+                    FileSystem::Virtual(files) => files.get(name.as_str()).ok_or("file was removed")?,
+                    _ => unreachable!(),
+                };
+
+                let start = (*pos as usize).min(content.len());
+                let end = start.saturating_add(max as usize).min(content.len());
+                *pos = end as u64;
+
+                Ok(content[start..end].to_vec())
+            },
+        }
+    }
+
+    /// writes the bytes and returns the amount of written bytes
+    pub fn write(&mut self, fd: u64, bytes: &[u8]) -> Result<u64,String>
+    {
+        let (file, fs) = (self.open_files.get_mut(&fd).ok_or(format!("invalid file descriptor: {}", fd))?, &mut self.fs);
+
+        match file
+        {
+            OpenFile::Host(f) =>
+            {
+                f.write_all(bytes).map_err(|e| e.to_string())?;
+                Ok(bytes.len() as u64)
+            },
+
+            OpenFile::Virtual{name, pos, mode} =>
+            {
+                if !mode.writable()
+                {
+                    return Err(format!("file descriptor {} is not writable", fd))
+                }
+
+                let content = match fs
+                {
+                    FileSystem::Virtual(files) => files.get_mut(name.as_str()).ok_or("file was removed")?,
+                    _ => unreachable!(),
+                };
+
+                if *mode == OpenMode::Append
+                {
+                    *pos = content.len() as u64;
+                }
+
+                let end = pos.checked_add(bytes.len() as u64).filter(|end| *end <= MAX_VIRTUAL_FILE_SIZE)
+                    .ok_or(format!("files can't be larger than {} bytes", MAX_VIRTUAL_FILE_SIZE))?;
+                let (start, end) = (*pos as usize, end as usize);
+
+                // fill gaps that were created by seeking behind the end
+                if content.len() < end
+                {
+                    content.resize(end, 0);
+                }
+                content[start..end].copy_from_slice(bytes);
+                *pos = end as u64;
+
+                Ok(bytes.len() as u64)
+            },
+        }
+    }
+
+    /// changes the position in the file and returns the new position.
+    /// whence: 0 = from the start, 1 = from the current position, 2 = from the end
+    pub fn seek(&mut self, fd: u64, whence: u64, offset: i64) -> Result<u64,String>
+    {
+        let (file, fs) = (self.open_files.get_mut(&fd).ok_or(format!("invalid file descriptor: {}", fd))?, &self.fs);
+
+        match file
+        {
+            OpenFile::Host(f) =>
+            {
+                let from = match whence
+                {
+                    0 if offset >= 0 => SeekFrom::Start(offset as u64),
+                    1 => SeekFrom::Current(offset),
+                    2 => SeekFrom::End(offset),
+                    _ => return Err(format!("invalid seek: {} {}", whence, offset)),
+                };
+                f.seek(from).map_err(|e| e.to_string())
+            },
+
+            OpenFile::Virtual{name, pos, ..} =>
+            {
+                let len = match fs
+                {
+                    FileSystem::Virtual(files) => files.get(name.as_str()).ok_or("file was removed")?.len(),
+                    _ => unreachable!(),
+                };
+
+                let base = match whence
+                {
+                    0 => 0,
+                    1 => *pos as i128,
+                    2 => len as i128,
+                    _ => return Err(format!("invalid seek: {} {}", whence, offset)),
+                };
+
+                let new_pos = base + offset as i128;
+                if new_pos < 0 || new_pos > u64::MAX as i128
+                {
+                    return Err(format!("invalid seek: {} {}", whence, offset))
+                }
+
+                *pos = new_pos as u64;
+                Ok(*pos)
+            },
+        }
+    }
+}
+
+/// only relative paths that stay inside of the directory are allowed
+fn check_file_name(name: &str) -> Result<(),String>
+{
+    let path = Path::new(name);
+
+    if name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(format!("invalid file name: {}", name))
+    }
+
+    Ok(())
+}
+
+/// returns the path of the file inside of the sandbox directory
+fn sandboxed_path(root: &Path, name: &str) -> Result<PathBuf,String>
+{
+    check_file_name(name)?;
+
+    let path = root.join(name);
+
+    // a dangling symlink would be followed when the file gets created
+    if path.symlink_metadata().is_ok_and(|meta| meta.file_type().is_symlink())
+    {
+        return Err(format!("{} is a symlink", name))
+    }
+
+    // make sure symlinks do not lead outside of the sandbox
+    let root = root.canonicalize().map_err(|e| format!("invalid sandbox directory: {}", e))?;
+    let parent = path.parent().unwrap_or(&root).canonicalize().map_err(|e| format!("could not open {}: {}", name, e))?;
+
+    let resolved = match path.canonicalize()
+    {
+        Ok(p) => p,
+        // the file does not exist (yet), the parent is checked instead
+        Err(_) => parent,
+    };
+
+    if !resolved.starts_with(&root)
+    {
+        return Err(format!("{} is outside of the sandbox", name))
+    }
+
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn virtual_table() -> FileTable
+    {
+        let mut files = HashMap::new();
+        files.insert("in.txt".to_string(), b"hello world".to_vec());
+        FileTable::new(FileSystem::Virtual(files))
+    }
+
+    #[test]
+    fn virtual_read_seek_test()
+    {
+        let mut ft = virtual_table();
+        let fd = ft.open("in.txt", OpenMode::Read).unwrap();
+
+        assert_eq!(ft.read(fd, 5), Ok(b"hello".to_vec()));
+        assert_eq!(ft.seek(fd, 2, -5), Ok(6));
+        assert_eq!(ft.read(fd, 100), Ok(b"world".to_vec()));
+        assert_eq!(ft.read(fd, 100), Ok(vec![]));
+        assert!(ft.write(fd, b"x").is_err());
+        assert_eq!(ft.close(fd), Ok(()));
+        assert!(ft.close(fd).is_err());
+    }
+
+    #[test]
+    fn virtual_write_append_test()
+    {
+        let mut ft = virtual_table();
+
+        let fd = ft.open("out.txt", OpenMode::Write).unwrap();
+        assert_eq!(ft.write(fd, b"abc"), Ok(3));
+        ft.close(fd).unwrap();
+
+        let fd = ft.open("out.txt", OpenMode::Append).unwrap();
+        assert_eq!(ft.write(fd, b"def"), Ok(3));
+
+        assert_eq!(ft.get_virtual_file("out.txt"), Some(&b"abcdef".to_vec()));
+        assert!(ft.open("missing.txt", OpenMode::Read).is_err());
+
+        // writing far behind the end fails instead of allocating the gap
+        let fd = ft.open("out.txt", OpenMode::ReadWrite).unwrap();
+        assert_eq!(ft.seek(fd, 0, MAX_VIRTUAL_FILE_SIZE as i64), Ok(MAX_VIRTUAL_FILE_SIZE));
+        assert!(ft.write(fd, b"x").is_err());
+        assert_eq!(ft.seek(fd, 0, i64::MAX), Ok(i64::MAX as u64));
+        assert!(ft.write(fd, &[0; 4096]).is_err());
+        assert_eq!(ft.get_virtual_file("out.txt").map(|f| f.len()), Some(6));
+    }
+
+    #[test]
+    fn sandbox_escape_test()
+    {
+        let dir = std::env::temp_dir().join("j_interpreter_sandbox_escape_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut ft = FileTable::new(FileSystem::Sandbox(dir.clone()));
+
+        assert!(ft.open("../x.txt", OpenMode::Write).is_err());
+        assert!(ft.open("/etc/passwd", OpenMode::Read).is_err());
+
+        let fd = ft.open("x.txt", OpenMode::Write).unwrap();
+        assert_eq!(ft.write(fd, b"abc"), Ok(3));
+        ft.close(fd).unwrap();
+
+        let fd = ft.open("x.txt", OpenMode::Read).unwrap();
+        assert_eq!(ft.read(fd, 10), Ok(b"abc".to_vec()));
+
+        // a dangling symlink must not be used to create a file outside of the sandbox
+        #[cfg(unix)]
+        {
+            let outside = std::env::temp_dir().join("j_interpreter_sandbox_escape_target.txt");
+            let _ = std::fs::remove_file(&outside);
+            let _ = std::fs::remove_file(dir.join("link.txt"));
+            std::os::unix::fs::symlink(&outside, dir.join("link.txt")).unwrap();
+
+            assert!(ft.open("link.txt", OpenMode::Write).is_err());
+            assert!(!outside.exists());
+        }
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
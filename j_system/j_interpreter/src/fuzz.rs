@@ -0,0 +1,390 @@
+use crate::block_engine::BlockEngine;
+use crate::deserialization::deserialize_asm;
+use crate::input::InputSource;
+use crate::load_bin::Binary;
+use crate::machine::{MachineInitInfo,MachineState};
+use crate::rng::Rng;
+
+use j_assembler::assembler::{assemble,Assembly};
+use j_system_definition::instructions::AsmLine;
+
+use std::panic::{self,AssertUnwindSafe};
+
+const REGISTERS: [&str;10] = ["a", "b", "c", "d", "e", "f", "tos", "bos", "pc", "s"];
+
+/// instructions and the amount of parameters they take
+const INSTRUCTIONS: [(&str,usize);22] = [
+    ("add",2), ("sub",2), ("xor",2), ("or",2), ("and",2), ("shr",2), ("shl",2), ("cmp",2), ("mov",2),
+    ("jmp",1), ("je",1), ("jeg",1), ("jel",1), ("jg",1), ("jl",1), ("call",1),
+    ("push",1), ("pop",1), ("ret",1), ("pusha",0), ("popa",0), ("sys",0),
+];
+
+/// chars that are inserted into the source by `mutate`
+const SOURCE_CHARS: [char;24] = ['[', ']', '+', '-', '.', ',', ':', '$', '#', '"', '_', ' ', '\t',
+                                 '0', '1', '9', 'a', 's', 'x', ';', 'é', '\0', '\n', '\\'];
+
+/// cycles a fuzzed programm may run
+const MAX_CYCLES: u128 = 10_000;
+
+/// generates random programms for the assembler and the VM.
+/// The same seed always generates the same programms
+pub struct Fuzzer
+{
+    rng: Rng,
+
+    /// amount of labels in the code and in the rom of the current programm
+    code_labels: u64,
+    rom_labels: u64,
+}
+
+impl Fuzzer
+{
+    pub fn new(seed: u64) -> Self
+    {
+        Self{rng: Rng::new(seed), code_labels: 0, rom_labels: 0}
+    }
+
+    fn next(&mut self) -> u64
+    {
+        self.rng.next_u64()
+    }
+
+    /// a random number from 0 to `max-1`
+    fn below(&mut self, max: u64) -> u64
+    {
+        self.rng.below(max)
+    }
+
+    /// true with a chance of 1 in `n`
+    fn one_in(&mut self, n: u64) -> bool
+    {
+        self.below(n) == 0
+    }
+
+    fn register(&mut self) -> &'static str
+    {
+        REGISTERS[self.below(REGISTERS.len() as u64) as usize]
+    }
+
+    /// small numbers are used as adresses, syscall parameters and shift amounts,
+    /// large ones test the edge cases
+    fn number(&mut self) -> u64
+    {
+        match self.below(4)
+        {
+            0 => self.next(),
+            1 => self.below(1100),
+            _ => self.below(16),
+        }
+    }
+
+    fn offset(&mut self) -> String
+    {
+        let offset = if self.one_in(4) {self.next() >> 1} else {self.below(16)};
+        format!("{}{}", if self.one_in(2) {"+"} else {"-"}, offset)
+    }
+
+    fn param(&mut self) -> String
+    {
+        match self.below(9)
+        {
+            0 | 1 => self.register().to_string(),
+            2 | 3 => self.number().to_string(),
+            4 => format!("[{}]", self.number()),
+            5 if self.one_in(3) => format!("[{}]", self.register()),
+            5 => format!("[{}{}]", self.register(), self.offset()),
+            6 if self.code_labels > 0 => format!(".l{}", self.below(self.code_labels)),
+            7 if self.rom_labels > 0 => format!("[.d{}]", self.below(self.rom_labels)),
+            8 if self.rom_labels > 0 => format!("[.d{}+{}]", self.below(self.rom_labels), self.below(4)),
+            _ => format!("{}", self.below(64)),
+        }
+    }
+
+    /// the parameter an instruction writes to. Mostly a register that can be written
+    fn destination(&mut self) -> String
+    {
+        if self.one_in(8)
+        {
+            self.param()
+        }
+        else
+        {
+            REGISTERS[self.below(6) as usize].to_string()
+        }
+    }
+
+    fn jump_target(&mut self) -> String
+    {
+        if self.code_labels > 0 && !self.one_in(8)
+        {
+            format!(".l{}", self.below(self.code_labels))
+        }
+        else
+        {
+            self.param()
+        }
+    }
+
+    /// one instruction with the right amount of parameters.
+    /// A syscall gets its number and parameters pushed before it
+    fn instruction(&mut self) -> Vec<String>
+    {
+        let mut pick = || INSTRUCTIONS[self.below(INSTRUCTIONS.len() as u64) as usize];
+        let mut picked = pick();
+
+        // pusha and popa are not implemented and stop the programm
+        if matches!(picked.0, "pusha" | "popa")
+        {
+            picked = pick();
+        }
+
+        let (name,params) = picked;
+        match (name,params)
+        {
+            ("sys",_) =>
+            {
+                let mut lines:Vec<String> = (0..self.below(4)).map(|_| format!("push {}", self.number())).collect();
+                lines.push(format!("push {}", self.below(16)));
+                lines.push("sys".to_string());
+                lines
+            },
+            ("cmp",_) => vec![format!("cmp {}, {}", self.param(), self.param())],
+            ("pop",_) => vec![format!("pop {}", self.destination())],
+            ("ret",_) => vec![format!("ret {}", self.below(4))],
+            ("push",_) => vec![format!("push {}", self.param())],
+            (_,2) => vec![format!("{} {}, {}", name, self.destination(), self.param())],
+            (_,1) => vec![format!("{} {}", name, self.jump_target())],
+            _ => vec![name.to_string()],
+        }
+    }
+
+    fn rom_entry(&mut self, nr: u64) -> String
+    {
+        match self.below(4)
+        {
+            0 => format!("d{}: i {}", nr, self.number()),
+            1 => format!("d{}: s \"fuzz {}\"", nr, self.below(100)),
+            2 => format!("d{}: ai [{}, {}]", nr, self.below(128), self.below(128)),
+            _ => format!("d{}: as [\"a\", \"{}\"]", nr, self.below(100)),
+        }
+    }
+
+    /// the source of a random programm with labels and rom data that the assembler accepts.
+    /// It ends with the End syscall if it doesn't jump away
+    pub fn programm(&mut self, length: u64) -> Vec<String>
+    {
+        self.code_labels = 1 + self.below(4);
+        self.rom_labels = self.below(4);
+
+        // the instructions the labels are placed before
+        let mut label_positions:Vec<u64> = (0..self.code_labels).map(|_| self.below(length+1)).collect();
+        label_positions.sort_unstable();
+
+        let mut source = vec!["_code".to_string(), ".start:".to_string()];
+        for ii in 0..=length
+        {
+            for (nr,_) in label_positions.iter().enumerate().filter(|(_,pos)| **pos == ii)
+            {
+                source.push(format!(".l{}:", nr));
+            }
+
+            if ii < length
+            {
+                source.append(&mut self.instruction());
+            }
+        }
+        source.extend(["push 9".to_string(), "sys".to_string(), "_rom".to_string()]);
+
+        for nr in 0..self.rom_labels
+        {
+            source.push(self.rom_entry(nr));
+        }
+        source
+    }
+
+    /// random changes to the source: chars and lines get inserted, removed and duplicated
+    pub fn mutate(&mut self, mut source: Vec<String>) -> Vec<String>
+    {
+        for _ in 0..1 + self.below(4)
+        {
+            if source.is_empty()
+            {
+                source.push(String::new());
+            }
+            let line = self.below(source.len() as u64) as usize;
+            let chars:Vec<char> = source[line].chars().collect();
+            let pos = self.below(chars.len() as u64 + 1) as usize;
+
+            match self.below(5)
+            {
+                0 =>
+                {
+                    let c = SOURCE_CHARS[self.below(SOURCE_CHARS.len() as u64) as usize];
+                    source[line] = chars[..pos].iter().chain([c].iter()).chain(chars[pos..].iter()).collect();
+                },
+                1 if pos < chars.len() => source[line] = chars[..pos].iter().chain(chars[pos+1..].iter()).collect(),
+                2 => {source.remove(line);},
+                3 => source.insert(line, source[line].clone()),
+                _ => source[line] = chars[..pos].iter().collect(),
+            }
+        }
+        source
+    }
+
+    /// random bytes for the input syscalls
+    fn input(&mut self) -> Vec<u8>
+    {
+        (0..self.below(32)).map(|_| match self.below(3)
+        {
+            0 => b'0' + self.below(10) as u8,
+            1 => b'\n',
+            _ => self.below(256) as u8,
+        }).collect()
+    }
+}
+
+/// assembles the source and returns an error if the assembler panicked
+pub fn assemble_without_panic(source: &[String]) -> Result<Result<Assembly,String>,String>
+{
+    let source = source.to_vec();
+    panic::catch_unwind(AssertUnwindSafe(|| assemble(source, "fuzz.asm".to_string())))
+        .map_err(|err| format!("the assembler panicked: {}", panic_message(&err)))
+}
+
+fn panic_message(err: &Box<dyn std::any::Any + Send>) -> String
+{
+    match (err.downcast_ref::<&str>(), err.downcast_ref::<String>())
+    {
+        (Some(msg),_) => msg.to_string(),
+        (_,Some(msg)) => msg.clone(),
+        _ => "unknown panic".to_string(),
+    }
+}
+
+/// the instructions without the source line, the same as they are decoded from memory
+fn without_lines(code: &[AsmLine]) -> Vec<AsmLine>
+{
+    code.iter().map(|inst| AsmLine{line: 0, ..inst.clone()}).collect()
+}
+
+/// checks that the decoded instructions and the reassembled text of the instructions
+/// are the same as the instructions of the assembler
+fn check_round_trip(assembly: &Assembly, machine: &MachineState) -> Result<(),String>
+{
+    let code = without_lines(&assembly.code);
+
+    for (ii,(inst,pos)) in code.iter().zip(&assembly.instruction_position).enumerate()
+    {
+        let next = assembly.instruction_position.get(ii+1).copied().unwrap_or(assembly.code_base + assembly.code_len);
+        let decoded = deserialize_asm(&machine.mem_state, *pos);
+        if decoded != Some((inst.clone(), next))
+        {
+            return Err(format!("{} at {} was decoded as {:?}", inst.as_string(), pos, decoded));
+        }
+    }
+
+    let text:Vec<String> = vec!["_code".to_string(), ".start:".to_string()].into_iter()
+        .chain(code.iter().map(|inst| inst.as_string()))
+        .chain(vec!["_rom".to_string()])
+        .collect();
+
+    let reassembled = assemble_without_panic(&text)?.map_err(|err| format!("the disassembly could not be assembled: {}", err))?;
+    match without_lines(&reassembled.code).iter().zip(&code).find(|(reassembled,inst)| reassembled != inst)
+    {
+        Some((reassembled,inst)) => Err(format!("{} was assembled as {:?} from its text", inst.as_string(), reassembled)),
+        None => Ok(()),
+    }
+}
+
+/// runs the programm with a cycle limit and returns how it ended
+/// or an error if the interpreter panicked
+fn run(assembly: &Assembly, input: &[u8], blocks: bool) -> Result<Result<(),String>,String>
+{
+    let mut machine = MachineState::init(MachineInitInfo{seed: Some(1), max_cycles: MAX_CYCLES, ..Default::default()});
+    machine.machine_information.set_input(InputSource::from_bytes(input));
+    machine.machine_information.buffer_output();
+    if blocks
+    {
+        machine.block_engine = Some(BlockEngine::new());
+    }
+
+    panic::catch_unwind(AssertUnwindSafe(||
+    {
+        machine.laod_into_state(Binary::from_assembly(assembly))?;
+        machine.run()
+    })).map_err(|err| format!("the interpreter panicked{}: {}", if blocks {" (blocks)"} else {""}, panic_message(&err)))
+}
+
+/// generates the programm of the seed, checks the round trip through the serialization
+/// and runs it with both engines.
+/// Returns the error the programm stopped with or a report if a check failed
+pub fn fuzz_programm(seed: u64) -> Result<Option<String>,String>
+{
+    let mut fuzzer = Fuzzer::new(seed);
+    let length = 1 + fuzzer.below(40);
+    let source = fuzzer.programm(length);
+    let input = fuzzer.input();
+
+    let report = |msg: String| format!("seed {}: {}\n\n{}\n", seed, msg, source.join("\n"));
+
+    let assembly = assemble_without_panic(&source).map_err(report)?
+        .map_err(|err| report(format!("the assembler rejected the programm: {}", err)))?;
+
+    let mut machine = MachineState::init(MachineInitInfo::default());
+    machine.laod_into_state(Binary::from_assembly(&assembly)).map_err(report)?;
+    check_round_trip(&assembly, &machine).map_err(report)?;
+
+    let interpreted = run(&assembly, &input, false).map_err(report)?;
+    let blocks = run(&assembly, &input, true).map_err(report)?;
+    if interpreted != blocks
+    {
+        return Err(report(format!("the engines ended differently: {:?} (interpreter) != {:?} (blocks)", interpreted, blocks)));
+    }
+    Ok(interpreted.err())
+}
+
+/// assembles a mutated programm of the seed. Only fails if the assembler panicked
+pub fn fuzz_assembler(seed: u64) -> Result<(),String>
+{
+    let mut fuzzer = Fuzzer::new(seed);
+    let length = 1 + fuzzer.below(20);
+    let programm = fuzzer.programm(length);
+    let source = fuzzer.mutate(programm);
+
+    assemble_without_panic(&source).map(|_| ()).map_err(|err| format!("seed {}: {}\n\n{}\n", seed, err, source.join("\n")))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn programms_test()
+    {
+        let mut ended = 0;
+        for seed in 0..200
+        {
+            match fuzz_programm(seed)
+            {
+                Ok(None) => ended += 1,
+                Ok(Some(_)) => {},
+                Err(report) => panic!("{}", report),
+            }
+        }
+        // most programms stop with an error, some have to reach the end
+        assert!(ended > 10, "{}", ended);
+    }
+
+    #[test]
+    fn assembler_test()
+    {
+        for seed in 0..1000
+        {
+            if let Err(report) = fuzz_assembler(seed)
+            {
+                panic!("{}", report);
+            }
+        }
+    }
+}
@@ -0,0 +1,585 @@
+use crate::machine::MachineState;
+use crate::debug::{MachineDebug,DebugFrontend,ContinueAfterDebug,StopReason,DebugCommand};
+
+use j_system_definition::register::Register;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::io::{Read,Write};
+use std::net::TcpListener;
+use std::rc::Rc;
+
+/// register numbers of gdb, the order of the target description
+const REGISTERS: [Register;10] = [Register::a, Register::b, Register::c, Register::d, Register::e, Register::f,
+                                  Register::tos, Register::bos, Register::pc, Register::s];
+
+/// the memory of gdb is byte adressed, the value at adress `n` is at byte `n*8`.
+/// These registers always contain adresses and are scaled the same way
+const ADRESS_REGISTERS: [Register;3] = [Register::tos, Register::bos, Register::pc];
+
+const WORD_SIZE: u64 = 8;
+
+/// maximum size of a packet, sent to gdb in `qSupported`
+const PACKET_SIZE: u64 = 0x4000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.j.core">
+    <reg name="a" bitsize="64" type="int64" regnum="0"/>
+    <reg name="b" bitsize="64" type="int64"/>
+    <reg name="c" bitsize="64" type="int64"/>
+    <reg name="d" bitsize="64" type="int64"/>
+    <reg name="e" bitsize="64" type="int64"/>
+    <reg name="f" bitsize="64" type="int64"/>
+    <reg name="tos" bitsize="64" type="data_ptr"/>
+    <reg name="bos" bitsize="64" type="data_ptr"/>
+    <reg name="pc" bitsize="64" type="code_ptr"/>
+    <reg name="s" bitsize="64" type="int64"/>
+  </feature>
+</target>
+"#;
+
+/// stub for the GDB remote serial protocol, so gdb or lldb can debug the machine.
+///
+/// The machine stops before the first instruction and waits for the client.
+/// Interrupting a running machine (Ctrl-C) is not supported, use breakpoints instead
+pub struct GdbStub
+{
+    reader: Box<dyn Read>,
+    writer: Box<dyn Write>,
+
+    /// the client waits for a stop reply after `c` or `s`
+    running: bool,
+
+    /// the acknowledgments were turned off with `QStartNoAckMode`
+    no_ack: bool,
+
+    /// the client detached or the connection was closed, the machine runs without debugger
+    detached: bool,
+}
+
+/// what the stub does after a packet was handled
+enum Action
+{
+    Reply(String),
+    Resume(ContinueAfterDebug),
+}
+
+/// hands the stops of the machine to the stub that is shared with `GdbStub::run`
+struct GdbFrontend(Rc<RefCell<GdbStub>>);
+
+impl DebugFrontend for GdbFrontend
+{
+    fn stopped(&mut self, machine: &mut MachineState, _reason: StopReason, _description: &str) -> ContinueAfterDebug
+    {
+        self.0.borrow_mut().stopped(machine)
+    }
+}
+
+impl GdbStub
+{
+    pub fn new(reader: Box<dyn Read>, writer: Box<dyn Write>) -> Self
+    {
+        Self{reader, writer, running: false, no_ack: false, detached: false}
+    }
+
+    /// waits for one client on `127.0.0.1:<port>`
+    pub fn tcp(port: u16) -> Result<Self,String>
+    {
+        let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("could not listen on port {}: {}", port, e))?;
+        eprintln!("waiting for gdb on 127.0.0.1:{} (target remote :{})", port, port);
+
+        let (stream,_) = listener.accept().map_err(|e| format!("could not accept the client: {}", e))?;
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+
+        Ok(Self::new(Box::new(reader), Box::new(stream)))
+    }
+
+    /// runs the programm with the client attached.
+    /// Returns the error message if the programm failed
+    pub fn run(self, machine: &mut MachineState) -> Result<(),String>
+    {
+        machine.machine_information.buffer_output();
+        machine.debug.debug_mode.get_or_insert_with(HashSet::new);
+
+        // stop before the first instruction
+        machine.debug.debug_step = Some(0);
+
+        let stub = Rc::new(RefCell::new(self));
+
+        machine.debug.frontend = Some(Box::new(GdbFrontend(stub.clone())));
+        let result = machine.run();
+        machine.debug.frontend = None;
+
+        stub.borrow_mut().exited(machine, &result);
+        result
+    }
+
+    fn stopped(&mut self, machine: &mut MachineState) -> ContinueAfterDebug
+    {
+        if self.detached
+        {
+            return ContinueAfterDebug::Continue
+        }
+
+        if self.running
+        {
+            self.send_output(machine);
+            self.send_packet("S05");
+            self.running = false;
+        }
+
+        self.serve(machine)
+    }
+
+    fn exited(&mut self, machine: &mut MachineState, result: &Result<(),String>)
+    {
+        if self.detached
+        {
+            return
+        }
+
+        self.send_output(machine);
+        match result
+        {
+            Ok(()) => self.send_packet("W00"),
+            Err(msg) =>
+            {
+                self.send_packet(&format!("O{}", hex(format!("{}\n", msg).as_bytes())));
+                self.send_packet("W01")
+            },
+        }
+    }
+
+    /// handles packets until the machine should continue
+    fn serve(&mut self, machine: &mut MachineState) -> ContinueAfterDebug
+    {
+        loop
+        {
+            let packet = match self.read_packet()
+            {
+                Some(packet) => packet,
+                // the client is gone, the programm keeps running
+                None =>
+                {
+                    self.detach(machine);
+                    return ContinueAfterDebug::Continue
+                },
+            };
+
+            match self.handle(machine, &packet)
+            {
+                Action::Reply(reply) => self.send_packet(&reply),
+                Action::Resume(cont) => return cont,
+            }
+        }
+    }
+
+    fn handle(&mut self, machine: &mut MachineState, packet: &str) -> Action
+    {
+        let reply = |s: &str| Action::Reply(s.into());
+
+        match packet.as_bytes().first()
+        {
+            Some(b'?') => reply("S05"),
+            Some(b'g') => Action::Reply(REGISTERS.iter().map(|reg| hex(&read_register(machine, *reg).to_le_bytes())).collect()),
+            Some(b'G') =>
+            {
+                let values:Option<Vec<u64>> = (0..REGISTERS.len()).map(|ii| packet.get(1+ii*16..1+(ii+1)*16).and_then(parse_le_u64)).collect();
+                match values
+                {
+                    Some(values) =>
+                    {
+                        REGISTERS.iter().zip(values).for_each(|(reg,val)| write_register(machine, *reg, val));
+                        reply("OK")
+                    },
+                    None => reply("E01"),
+                }
+            },
+            Some(b'p') => match usize::from_str_radix(&packet[1..], 16).ok().and_then(|n| REGISTERS.get(n))
+            {
+                Some(reg) => Action::Reply(hex(&read_register(machine, *reg).to_le_bytes())),
+                None => reply("E01"),
+            },
+            Some(b'P') =>
+            {
+                let reg_val = packet[1..].split_once('=').and_then(|(n,val)|
+                    Some((*REGISTERS.get(usize::from_str_radix(n, 16).ok()?)?, parse_le_u64(val)?)));
+
+                match reg_val
+                {
+                    Some((reg,val)) => {write_register(machine, reg, val); reply("OK")},
+                    None => reply("E01"),
+                }
+            },
+            Some(b'm') => match parse_adress_length(&packet[1..])
+            {
+                Some((addr,len)) => read_memory(machine, addr, len).map_or(reply("E01"), |bytes| Action::Reply(hex(&bytes))),
+                None => reply("E01"),
+            },
+            Some(b'M') =>
+            {
+                let write = packet[1..].split_once(':').and_then(|(range,data)| Some((parse_adress_length(range)?, unhex(data)?)));
+
+                match write
+                {
+                    Some(((addr,len),data)) if data.len() as u64 == len && write_memory(machine, addr, &data).is_some() => reply("OK"),
+                    _ => reply("E01"),
+                }
+            },
+            Some(b'Z') | Some(b'z') => self.breakpoint(machine, packet),
+            // the adress to continue at is ignored
+            Some(b'c') => self.resume(machine, DebugCommand::Continue),
+            Some(b's') => self.resume(machine, DebugCommand::Step(0)),
+            // there is only one thread, so only the first action counts
+            Some(b'v') if packet.starts_with("vCont;c") => self.resume(machine, DebugCommand::Continue),
+            Some(b'v') if packet.starts_with("vCont;s") => self.resume(machine, DebugCommand::Step(0)),
+            Some(b'k') => Action::Resume(ContinueAfterDebug::Quit),
+            Some(b'D') =>
+            {
+                self.send_packet("OK");
+                self.detach(machine);
+                Action::Resume(ContinueAfterDebug::Continue)
+            },
+            Some(b'H') => reply("OK"),
+            _ => self.query(packet),
+        }
+    }
+
+    /// general queries and `v` packets
+    fn query(&mut self, packet: &str) -> Action
+    {
+        let reply = |s: &str| Action::Reply(s.into());
+
+        if let Some(annex) = packet.strip_prefix("qXfer:features:read:target.xml:")
+        {
+            return match parse_adress_length(annex)
+            {
+                Some((offset,len)) =>
+                {
+                    let start = (offset as usize).min(TARGET_XML.len());
+                    let end = start.saturating_add(len as usize).min(TARGET_XML.len());
+                    let prefix = if end == TARGET_XML.len() {"l"} else {"m"};
+                    Action::Reply(format!("{}{}", prefix, &TARGET_XML[start..end]))
+                },
+                None => reply("E01"),
+            }
+        }
+
+        match packet
+        {
+            p if p.starts_with("qSupported") => reply(&format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+", PACKET_SIZE)),
+            // the acknowledgment of this packet was already sent
+            "QStartNoAckMode" =>
+            {
+                self.no_ack = true;
+                reply("OK")
+            },
+            "qAttached"     => reply("1"),
+            "qC"            => reply("QC1"),
+            "qfThreadInfo"  => reply("m1"),
+            "qsThreadInfo"  => reply("l"),
+            "vCont?"        => reply("vCont;c;s"),
+            // not supported, the client falls back to other packets
+            _ => reply(""),
+        }
+    }
+
+    /// `Z<type>,<adress>,<kind>` inserts and `z...` removes a breakpoint or watchpoint.
+    /// type 0 and 1 are breakpoints, type 2 is a write watchpoint
+    fn breakpoint(&mut self, machine: &mut MachineState, packet: &str) -> Action
+    {
+        let insert = packet.starts_with('Z');
+        let parts:Vec<&str> = packet[1..].split(',').collect();
+
+        let (kind,addr) = match parts[..]
+        {
+            [kind, addr, _] => match u64::from_str_radix(addr, 16)
+            {
+                Ok(addr) => (kind, addr / WORD_SIZE),
+                Err(_) => return Action::Reply("E01".into()),
+            },
+            _ => return Action::Reply("E01".into()),
+        };
+
+        match (kind,insert)
+        {
+            ("0",true) | ("1",true) => {machine.debug.debug_mode.get_or_insert_with(HashSet::new).insert(addr);},
+            ("0",false) | ("1",false) => {machine.debug.debug_mode.get_or_insert_with(HashSet::new).remove(&addr);},
+            ("2",true) => machine.mem_state.watch(addr),
+            ("2",false) => {machine.mem_state.unwatch(addr);},
+            _ => return Action::Reply("".into()),
+        }
+        Action::Reply("OK".into())
+    }
+
+    /// the client waits for the stop reply after the machine stopped again
+    fn resume(&mut self, machine: &mut MachineState, command: DebugCommand) -> Action
+    {
+        self.running = true;
+        Action::Resume(machine.run_debug_command(command).unwrap_or(ContinueAfterDebug::Continue))
+    }
+
+    /// the machine runs without debugger after the client is gone
+    fn detach(&mut self, machine: &mut MachineState)
+    {
+        self.detached = true;
+        machine.debug.debug_mode = Some(HashSet::new());
+        machine.debug.debug_step = None;
+        for addr in machine.mem_state.get_watchpoints().clone()
+        {
+            machine.mem_state.unwatch(addr);
+        }
+    }
+
+    /// reads a packet of the form `$<data>#<checksum>`.
+    /// Returns `None` when the connection was closed
+    fn read_packet(&mut self) -> Option<String>
+    {
+        loop
+        {
+            // skip acknowledgments and interrupts until the start of the next packet
+            while self.read_byte()? != b'$' {}
+
+            let mut data = vec![];
+            loop
+            {
+                match self.read_byte()?
+                {
+                    b'#' => break,
+                    // escaped byte
+                    b'}' => data.push(self.read_byte()? ^ 0x20),
+                    b => data.push(b),
+                }
+            }
+
+            let checksum = [self.read_byte()?, self.read_byte()?];
+            let valid = std::str::from_utf8(&checksum).ok().and_then(|cs| u8::from_str_radix(cs, 16).ok())
+                == Some(checksum_of(&data));
+
+            if !self.no_ack
+            {
+                let _ = self.writer.write_all(if valid {b"+"} else {b"-"}).and_then(|_| self.writer.flush());
+            }
+
+            if valid
+            {
+                return Some(String::from_utf8_lossy(&data).into_owned())
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8>
+    {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)
+        {
+            Ok(1) => Some(byte[0]),
+            _ => None,
+        }
+    }
+
+    fn send_packet(&mut self, data: &str)
+    {
+        // the client may already be gone, there is no one to report the error to
+        let _ = write!(self.writer, "${}#{:02x}", data, checksum_of(data.as_bytes())).and_then(|_| self.writer.flush());
+    }
+
+    /// sends the output the programm printed since the last stop as console output of the client
+    fn send_output(&mut self, machine: &mut MachineState)
+    {
+        let output:String = machine.machine_information.take_output().into_iter().map(|(_,s)| s).collect();
+
+        if !output.is_empty()
+        {
+            self.send_packet(&format!("O{}", hex(output.as_bytes())));
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8
+{
+    data.iter().fold(0u8, |sum,b| sum.wrapping_add(*b))
+}
+
+fn hex(bytes: &[u8]) -> String
+{
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>>
+{
+    if !s.len().is_multiple_of(2)
+    {
+        return None
+    }
+
+    (0..s.len()).step_by(2).map(|ii| u8::from_str_radix(s.get(ii..ii+2)?, 16).ok()).collect()
+}
+
+/// registers are sent as little endian hex
+fn parse_le_u64(s: &str) -> Option<u64>
+{
+    let bytes:[u8;8] = unhex(s)?.try_into().ok()?;
+    Some(u64::from_le_bytes(bytes))
+}
+
+/// parses `<adress>,<length>` in hex
+fn parse_adress_length(s: &str) -> Option<(u64,u64)>
+{
+    let (addr,len) = s.split_once(',')?;
+    Some((u64::from_str_radix(addr, 16).ok()?, u64::from_str_radix(len, 16).ok()?))
+}
+
+fn read_register(machine: &MachineState, reg: Register) -> u64
+{
+    let val = machine.reg_state.read(reg);
+    if ADRESS_REGISTERS.contains(&reg) {val.wrapping_mul(WORD_SIZE)} else {val}
+}
+
+fn write_register(machine: &mut MachineState, reg: Register, val: u64)
+{
+    let val = if ADRESS_REGISTERS.contains(&reg) {val / WORD_SIZE} else {val};
+
+    match reg
+    {
+        Register::pc | Register::s => machine.reg_state.store_to_read_only(reg, val),
+        _ => machine.reg_state.store(reg, val),
+    }
+}
+
+/// reads `len` bytes starting at the byte adress.
+/// Fails if the first value can not be read, stops at the first value that can not be read
+fn read_memory(machine: &MachineState, addr: u64, len: u64) -> Option<Vec<u8>>
+{
+    let mut bytes = vec![];
+
+    // every byte is sent as two hex digits
+    let len = len.min(PACKET_SIZE / 2);
+
+    for byte in addr..addr.saturating_add(len)
+    {
+        match machine.mem_state.peek(byte / WORD_SIZE)
+        {
+            Ok(val) => bytes.push(val.to_le_bytes()[(byte % WORD_SIZE) as usize]),
+            Err(_) if bytes.is_empty() => return None,
+            Err(_) => break,
+        }
+    }
+    Some(bytes)
+}
+
+/// writes the bytes starting at the byte adress, the values are changed without triggering watchpoints.
+/// Fails without writing anything if a value is outside of the memory, in a guard page or in a device
+fn write_memory(machine: &mut MachineState, addr: u64, data: &[u8]) -> Option<()>
+{
+    let last = addr.checked_add(data.len().checked_sub(1)? as u64)?;
+    if (addr / WORD_SIZE..=last / WORD_SIZE).any(|word| machine.mem_state.check_debug_store(word).is_err())
+    {
+        return None
+    }
+
+    for (ii,byte) in data.iter().enumerate()
+    {
+        let byte_addr = addr + ii as u64;
+        let word = byte_addr / WORD_SIZE;
+
+        let mut bytes = machine.mem_state.peek(word).ok()?.to_le_bytes();
+        bytes[(byte_addr % WORD_SIZE) as usize] = *byte;
+        machine.mem_state.debug_store(word, u64::from_le_bytes(bytes)).ok()?;
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::MachineInitInfo;
+    use crate::memory::MemLayout;
+    use crate::test_util::{SharedBuf,assembled_machine};
+
+    fn packets(packets: &[&str]) -> Vec<u8>
+    {
+        packets.iter().map(|p| format!("+${}#{:02x}", p, checksum_of(p.as_bytes()))).collect::<String>().into_bytes()
+    }
+
+    /// the data of the packets the stub sent
+    fn replies(output: &[u8]) -> Vec<String>
+    {
+        let mut stub = GdbStub::new(Box::new(std::io::Cursor::new(output.to_vec())), Box::new(std::io::sink()));
+        stub.no_ack = true;
+
+        std::iter::from_fn(|| stub.read_packet()).collect()
+    }
+
+    #[test]
+    fn remote_session_test()
+    {
+        let mut machine = assembled_machine(MachineInitInfo::default(), "
+_rom
+_code
+    .start:
+        mov a, 5
+        add a, 1
+        push 9
+        sys
+");
+
+        let script = packets(&[
+            "qSupported:multiprocess+",
+            "?",
+            "qXfer:features:read:target.xml:0,20",
+            // breakpoint at adress 5 (byte 40)
+            "Z0,28,8",
+            "c",
+            "p0",
+            "p8",
+            // value 2 of the programm
+            "m10,8",
+            "P0=2a00000000000000",
+            "M258,8:0100000000000000",
+            "c",
+        ]);
+
+        let out = SharedBuf::default();
+        GdbStub::new(Box::new(std::io::Cursor::new(script)), Box::new(out.clone())).run(&mut machine).unwrap();
+
+        let replies = replies(&out.0.borrow());
+        assert_eq!(replies[0], "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+;swbreak+");
+        assert_eq!(replies[1], "S05");
+        assert_eq!(replies[2], format!("m{}", &TARGET_XML[..0x20]));
+        assert_eq!(replies[3..], ["OK", "S05", "0600000000000000", "2800000000000000", "0500000000000000", "OK", "OK", "W00"]);
+
+        assert_eq!(machine.reg_state.read(Register::a), 42);
+        assert_eq!(machine.mem_state.read(0x258 / 8).unwrap(), 1);
+    }
+
+    #[test]
+    fn memory_access_test()
+    {
+        let mut machine = assembled_machine(MachineInitInfo{layout: MemLayout::new(4096), ..Default::default()}, "
+_rom
+_code
+    .start:
+        push 9
+        sys
+");
+        machine.mem_state.add_guard(2000, 1).unwrap();
+
+        // reads are limited by the packet size
+        assert_eq!(read_memory(&machine, 8, u64::MAX).map(|bytes| bytes.len()), Some(PACKET_SIZE as usize / 2));
+
+        assert_eq!(write_memory(&mut machine, 8 * 50, &[1,2]), Some(()));
+        assert_eq!(machine.mem_state.read(50), Ok(0x0201));
+
+        // guard pages, adresses outside of the memory and overflowing ranges
+        assert_eq!(write_memory(&mut machine, 8 * 1999, &[1; 16]), None);
+        assert_eq!(machine.mem_state.read(1999), Ok(0));
+        assert_eq!(write_memory(&mut machine, 8 * 4096, &[1]), None);
+        assert_eq!(write_memory(&mut machine, u64::MAX, &[1,2]), None);
+        assert_eq!(write_memory(&mut machine, 8 * 50, &[]), None);
+    }
+}
@@ -0,0 +1,383 @@
+use crate::debug::parse_register;
+use crate::input::InputSource;
+use crate::load_bin::{self,Binary};
+use crate::machine::{MachineInitInfo,MachineState};
+
+use j_assembler::assembler::assemble_with_include_dirs;
+use j_system_definition::register::Register;
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path,PathBuf};
+
+/*
+    annotations in the comments of a test programm:
+
+    ; input: "100\n"            stdin of the programm
+    ; seed: 1                   seed of the `Rand` syscall (default 1)
+    ; cycle-limit: 100000       maximum cycles (default 10000000)
+
+    ; expect stdout: "42\n"     the whole output. Several lines get joined
+    ; expect exit: ok           ok (default) or error, with a part of the message: error "null pointer"
+    ; expect register a: 359    value of a register after the programm ended
+    ; expect cycles: 10..200    bounds of the executed cycles, both are optional: ..20000
+
+    Strings can contain \n, \t, \0, \" and \\.
+    Only files with at least one `expect` are tests
+*/
+
+const DEFAULT_CYCLE_LIMIT: u128 = 10_000_000;
+
+/// how the programm has to end
+#[derive(Debug,PartialEq)]
+pub enum Exit
+{
+    /// with the End syscall, the interpreter exits with 0
+    Ok,
+
+    /// with an error that contains the message, the interpreter exits with 1
+    Error(Option<String>),
+}
+
+/// a programm and what it has to do when it runs
+#[derive(Debug)]
+pub struct GoldenTest
+{
+    pub name: String,
+    source: Vec<String>,
+
+    /// the directory the programm was loaded from. Includes are searched here first
+    dir: PathBuf,
+
+    pub input: Vec<u8>,
+    pub seed: u64,
+    pub cycle_limit: u128,
+
+    pub stdout: Option<String>,
+    pub exit: Exit,
+    pub registers: Vec<(Register,u64)>,
+    pub cycles: (Option<u128>,Option<u128>),
+}
+
+impl GoldenTest
+{
+    /// reads the annotations of the source. Returns None if the source has no expectations
+    pub fn parse(name: &str, dir: PathBuf, source: &str) -> Result<Option<Self>,String>
+    {
+        let mut test = Self{
+            name: name.to_string(),
+            source: source.lines().map(|line| line.to_string()).collect(),
+            dir,
+            input: vec![],
+            seed: 1,
+            cycle_limit: DEFAULT_CYCLE_LIMIT,
+            stdout: None,
+            exit: Exit::Ok,
+            registers: vec![],
+            cycles: (None,None),
+        };
+        let mut expectations = 0;
+
+        for (ii,line) in source.lines().enumerate()
+        {
+            let annotation = match line.trim_start().strip_prefix(';').map(|comment| comment.trim()).and_then(|comment| comment.split_once(':'))
+            {
+                Some(annotation) => annotation,
+                None => continue,
+            };
+
+            let key:Vec<&str> = annotation.0.split_whitespace().collect();
+            let value = annotation.1.trim();
+            let err = |msg: String| format!("{}:{}: {}", name, ii+1, msg);
+
+            match key[..]
+            {
+                ["input"] => test.input.extend(parse_string(value).map_err(err)?.bytes()),
+                ["seed"] => test.seed = value.parse().map_err(|_| err(format!("invalid seed: {}", value)))?,
+                ["cycle-limit"] => test.cycle_limit = value.parse().map_err(|_| err(format!("invalid cycle limit: {}", value)))?,
+                ["expect", ..] => expectations += 1,
+                _ => continue,
+            }
+
+            match key[..]
+            {
+                ["expect", "stdout"] => test.stdout.get_or_insert(String::new()).push_str(&parse_string(value).map_err(err)?),
+                ["expect", "exit"] => test.exit = parse_exit(value).map_err(err)?,
+                ["expect", "register", reg] =>
+                {
+                    let reg = parse_register(reg).ok_or_else(|| err(format!("unknown register: {}", reg)))?;
+                    let val = value.parse().map_err(|_| err(format!("invalid register value: {}", value)))?;
+                    test.registers.push((reg,val));
+                },
+                ["expect", "cycles"] => test.cycles = parse_bounds(value).map_err(err)?,
+                ["expect", ..] => return Err(err(format!("unknown expectation: {}", annotation.0))),
+                _ => {},
+            }
+        }
+
+        Ok(if expectations > 0 {Some(test)} else {None})
+    }
+
+    pub fn load(path: &Path) -> Result<Option<Self>,String>
+    {
+        let source = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        let name = path.file_name().map_or(path.display().to_string(), |name| name.to_string_lossy().to_string());
+        Self::parse(&name, path.parent().map_or(PathBuf::new(), |dir| dir.to_path_buf()), &source)
+    }
+
+    /// assembles and runs the programm. Returns what did not match the expectations
+    pub fn run(&self, include_dirs: &[PathBuf]) -> Vec<String>
+    {
+        let dirs:Vec<PathBuf> = std::iter::once(self.dir.clone()).chain(include_dirs.iter().cloned()).collect();
+        let assembly = match assemble_with_include_dirs(self.source.clone(), self.name.clone(), &dirs)
+        {
+            Ok(assembly) => assembly,
+            Err(e) => return vec![format!("could not be assembled: {}", e)],
+        };
+
+        let mut machine = MachineState::init(MachineInitInfo{
+            max_cycles: self.cycle_limit,
+            seed: Some(self.seed),
+            symbols: Some(load_bin::symbols_of(&assembly)),
+            line_table: Some(load_bin::line_table_of(&assembly, self.dir.clone())),
+            ..Default::default()
+        });
+        machine.machine_information.set_input(InputSource::from_bytes(&self.input));
+        machine.machine_information.buffer_output();
+
+        let result = machine.laod_into_state(Binary::from_assembly(&assembly)).and_then(|_| machine.run());
+        let mut failures = vec![];
+
+        match (&self.exit, &result)
+        {
+            (Exit::Ok, Err(e)) => failures.push(format!("stopped with an error: {}", e)),
+            (Exit::Error(_), Ok(_)) => failures.push("ended without an error".to_string()),
+            (Exit::Error(Some(msg)), Err(e)) if !e.contains(msg.as_str()) => failures.push(format!("error {:?} does not contain {:?}", e, msg)),
+            _ => {},
+        }
+
+        if let Some(expected) = &self.stdout
+        {
+            let stdout:String = machine.machine_information.take_output().into_iter().map(|(_,s)| s).collect();
+            if &stdout != expected
+            {
+                failures.push(format!("stdout differs (- expected, + actual):\n{}", diff(expected, &stdout)));
+            }
+        }
+
+        for (reg,expected) in &self.registers
+        {
+            let val = machine.reg_state.read(*reg);
+            if val != *expected
+            {
+                failures.push(format!("register {:?}: expected {}, got {}", reg, expected, val));
+            }
+        }
+
+        let cycles = machine.machine_information.get_cycles();
+        let (min,max) = self.cycles;
+        if min.is_some_and(|min| cycles < min) || max.is_some_and(|max| cycles > max)
+        {
+            failures.push(format!("cycles: {} not in {}..{}", cycles, min.map_or(String::new(), |x| x.to_string()), max.map_or(String::new(), |x| x.to_string())));
+        }
+        failures
+    }
+}
+
+/// the tests of a file or of all `.asm` files in a directory, sorted by name
+pub fn discover(path: &Path) -> Result<Vec<GoldenTest>,String>
+{
+    if path.is_file()
+    {
+        return Ok(GoldenTest::load(path)?.into_iter().collect());
+    }
+
+    let mut files:Vec<PathBuf> = fs::read_dir(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "asm"))
+        .collect();
+    files.sort();
+
+    let mut tests = vec![];
+    for file in files
+    {
+        tests.extend(GoldenTest::load(&file)?);
+    }
+    Ok(tests)
+}
+
+/// runs the tests of the path and writes the results. Returns true if all passed
+pub fn run_tests(path: &Path, include_dirs: &[PathBuf], out: &mut dyn Write) -> Result<bool,String>
+{
+    let tests = discover(path)?;
+    let mut failed = 0;
+
+    for test in &tests
+    {
+        let failures = test.run(include_dirs);
+        if failures.is_empty()
+        {
+            writeln!(out, "PASS {}", test.name).map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        failed += 1;
+        writeln!(out, "FAIL {}", test.name).map_err(|e| e.to_string())?;
+        for failure in failures
+        {
+            writeln!(out, "    {}", failure.replace('\n', "\n    ")).map_err(|e| e.to_string())?;
+        }
+    }
+
+    writeln!(out, "{} passed, {} failed", tests.len() - failed, failed).map_err(|e| e.to_string())?;
+    Ok(failed == 0)
+}
+
+/// a string in quotes with escape sequences
+fn parse_string(s: &str) -> Result<String,String>
+{
+    let content = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')).ok_or(format!("expected a string in quotes: {}", s))?;
+    let mut res = String::new();
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next()
+    {
+        if c != '\\'
+        {
+            res.push(c);
+            continue;
+        }
+
+        res.push(match chars.next()
+        {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            other => return Err(format!("invalid escape sequence: \\{}", other.map_or(String::new(), |c| c.to_string()))),
+        });
+    }
+    Ok(res)
+}
+
+fn parse_exit(s: &str) -> Result<Exit,String>
+{
+    match s.split_once(char::is_whitespace)
+    {
+        None if s == "ok" => Ok(Exit::Ok),
+        None if s == "error" => Ok(Exit::Error(None)),
+        Some(("error", msg)) => Ok(Exit::Error(Some(parse_string(msg.trim())?))),
+        _ => Err(format!("expected ok or error: {}", s)),
+    }
+}
+
+/// `min..max`, both bounds are optional
+fn parse_bounds(s: &str) -> Result<(Option<u128>,Option<u128>),String>
+{
+    let (min,max) = s.split_once("..").ok_or(format!("expected min..max: {}", s))?;
+    let bound = |b: &str| match b.trim()
+    {
+        "" => Ok(None),
+        b => b.parse().map(Some).map_err(|_| format!("invalid bound: {}", b)),
+    };
+    Ok((bound(min)?, bound(max)?))
+}
+
+/// line diff of the expected and the actual text, lines only in the
+/// expected text start with `-`, lines only in the actual text with `+`
+fn diff(expected: &str, actual: &str) -> String
+{
+    let (a,b):(Vec<&str>,Vec<&str>) = (expected.split('\n').collect(), actual.split('\n').collect());
+
+    // length of the longest common subsequence of a[ii..] and b[jj..]
+    let mut lcs = vec![vec![0; b.len()+1]; a.len()+1];
+    for ii in (0..a.len()).rev()
+    {
+        for jj in (0..b.len()).rev()
+        {
+            lcs[ii][jj] = if a[ii] == b[jj] {lcs[ii+1][jj+1] + 1} else {lcs[ii+1][jj].max(lcs[ii][jj+1])};
+        }
+    }
+
+    let (mut ii, mut jj) = (0,0);
+    let mut lines = vec![];
+    while ii < a.len() || jj < b.len()
+    {
+        if ii < a.len() && jj < b.len() && a[ii] == b[jj]
+        {
+            lines.push(format!("  {}", a[ii]));
+            ii += 1;
+            jj += 1;
+        }
+        else if jj < b.len() && (ii == a.len() || lcs[ii][jj+1] >= lcs[ii+1][jj])
+        {
+            lines.push(format!("+ {}", b[jj]));
+            jj += 1;
+        }
+        else
+        {
+            lines.push(format!("- {}", a[ii]));
+            ii += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn asm_programms_test()
+    {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let mut out = vec![];
+
+        let passed = run_tests(&dir.join("../../asm"), &[dir.join("../j_assembler")], &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(passed, "{}", out);
+        assert!(out.ends_with("8 passed, 0 failed\n"), "{}", out);
+    }
+
+    #[test]
+    fn failure_report_test()
+    {
+        let source = r#"
+            ; input: "3\n"
+            ; expect stdout: "2\n1"
+            ; expect register a: 4
+            ; expect cycles: ..2
+            _rom
+            _code
+            .start:
+                mov a, 5
+                push 1
+                push 3
+                push 8
+                sys
+                push 9
+                sys
+        "#;
+        let test = GoldenTest::parse("t.asm", PathBuf::new(), source).unwrap().unwrap();
+        assert_eq!(test.input, b"3\n");
+
+        assert_eq!(test.run(&[]), vec![
+            "stdout differs (- expected, + actual):\n- 2\n  1".to_string(),
+            "register a: expected 4, got 5".to_string(),
+            "cycles: 6 not in ..2".to_string(),
+        ]);
+
+        let source = source.replace("push 9", "mov a, [0]").replace(": ..2", ": 2..");
+        let test = GoldenTest::parse("t.asm", PathBuf::new(), &format!("; expect exit: error \"null pointer\"\n{}", source)).unwrap().unwrap();
+        assert_eq!(test.exit, Exit::Error(Some("null pointer".to_string())));
+        assert_eq!(test.run(&[]).len(), 2);
+
+        assert_eq!(diff("a\nb", "a\nc\nb"), "  a\n+ c\n  b");
+
+        assert!(GoldenTest::parse("t.asm", PathBuf::new(), "; expect stdot: \"\"").is_err());
+        assert!(GoldenTest::parse("t.asm", PathBuf::new(), "; input: 3").is_err());
+        assert!(GoldenTest::parse("t.asm", PathBuf::new(), "; a comment: no test").unwrap().is_none());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::check_instruction::StateHelperFunctions;
+use crate::input::InputSource;
+use crate::load_bin::{self,Binary};
+use crate::machine::{MachineInitInfo,MachineState};
+use crate::syscall::read_string_terminated_uncompressed;
+
+use j_assembler::assembler::assemble_with_include_dirs;
+use j_system_definition::register::{Register,RegisterState};
+
+use std::fs;
+use std::path::{Path,PathBuf};
+
+/*
+    calls single functions of a programm from rust tests:
+
+    let mut test = GuestTest::load(Path::new("math.asm"), &[PathBuf::from("../j_assembler")])?;
+    test.set_register(Register::b, 3)?;
+    let res = test.call(".add", &[1, 2])?;
+    assert_eq!(res.registers.read(Register::a), 3);
+
+    The arguments are pushed in the given order like the `push` instructions in front
+    of a `call`. The function runs until the `ret` that removes its return adress.
+    `.start` is not executed, so everything the function needs has to be set up by the test.
+    Registers and memory stay as they are between the calls.
+
+    Inside of the programm the `Assert` syscall (15) stops the call with the source line:
+        push .msg       ; or 0 without a message
+        push a          ; fails if 0
+        push 15
+        sys
+*/
+
+const DEFAULT_CYCLE_LIMIT: u128 = 10_000_000;
+
+/// the state after a function returned
+pub struct CallResult
+{
+    pub registers: RegisterState,
+
+    /// cycles of the call
+    pub cycles: u128,
+
+    /// what the function printed
+    pub output: String,
+}
+
+/// a loaded programm whose functions can be called one by one
+pub struct GuestTest
+{
+    pub machine: MachineState,
+
+    /// the functions return to this adress, it is never executed
+    return_adress: u64,
+}
+
+impl GuestTest
+{
+    /// assembles the source. Includes are searched in `dir` and then in the include dirs
+    pub fn from_source(name: &str, dir: PathBuf, source: &str, include_dirs: &[PathBuf]) -> Result<Self,String>
+    {
+        let dirs:Vec<PathBuf> = std::iter::once(dir.clone()).chain(include_dirs.iter().cloned()).collect();
+        let lines = source.lines().map(|line| line.to_string()).collect();
+        let assembly = assemble_with_include_dirs(lines, name.to_string(), &dirs)?;
+
+        let mut machine = MachineState::init(MachineInitInfo{
+            max_cycles: DEFAULT_CYCLE_LIMIT,
+            seed: Some(1),
+            symbols: Some(load_bin::symbols_of(&assembly)),
+            line_table: Some(load_bin::line_table_of(&assembly, dir)),
+            ..Default::default()
+        });
+        machine.machine_information.set_input(InputSource::from_bytes(&[]));
+        machine.machine_information.buffer_output();
+        machine.laod_into_state(Binary::from_assembly(&assembly))?;
+
+        // behind the code, so it can't be NULL
+        let return_adress = machine.mem_state.get_code_base_ptr() + machine.mem_state.get_code_size();
+        Ok(Self{machine, return_adress})
+    }
+
+    pub fn load(path: &Path, include_dirs: &[PathBuf]) -> Result<Self,String>
+    {
+        let source = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        let name = path.file_name().map_or(path.display().to_string(), |name| name.to_string_lossy().to_string());
+        Self::from_source(&name, path.parent().map_or(PathBuf::new(), |dir| dir.to_path_buf()), &source, include_dirs)
+    }
+
+    /// the read only registers `s` and `pc` can not be set
+    pub fn set_register(&mut self, reg: Register, val: u64) -> Result<(),String>
+    {
+        match reg
+        {
+            Register::s | Register::pc => Err(format!("{:?} is a read only register", reg)),
+            _ => {self.machine.reg_state.store(reg, val); Ok(())},
+        }
+    }
+
+    pub fn read_register(&self, reg: Register) -> u64
+    {
+        self.machine.reg_state.read(reg)
+    }
+
+    /// the input syscalls read from this
+    pub fn set_input(&mut self, input: &[u8])
+    {
+        self.machine.machine_information.set_input(InputSource::from_bytes(input));
+    }
+
+    pub fn write_mem(&mut self, addr: u64, val: u64) -> Result<(),String>
+    {
+        self.machine.mem_state.store(val, addr)
+    }
+
+    pub fn read_mem(&self, addr: u64) -> Result<u64,String>
+    {
+        self.machine.mem_state.read(addr)
+    }
+
+    /// reads a null terminated string (one char per value)
+    pub fn read_string(&self, addr: u64) -> Result<String,String>
+    {
+        read_string_terminated_uncompressed(&self.machine, addr)
+    }
+
+    /// the adress of a label, with or without `.`
+    pub fn adress_of(&self, label: &str) -> Result<u64,String>
+    {
+        self.machine.debug.resolve(label)
+    }
+
+    /// pushes the arguments and the return adress and runs the function until it returns.
+    /// Fails if the function fails, an assertion does not hold or the programm ends
+    pub fn call(&mut self, label: &str, args: &[u64]) -> Result<CallResult,String>
+    {
+        let target = self.adress_of(label)?;
+
+        for arg in args
+        {
+            self.machine.push_stack(*arg)?;
+        }
+        self.machine.push_stack(self.return_adress)?;
+
+        // the function returned when its return adress was removed from the stack
+        let return_slot = self.machine.reg_state.read(Register::tos);
+        let cycles_before = self.machine.machine_information.get_cycles();
+        self.machine.reg_state.store_to_read_only(Register::pc, target);
+
+        loop
+        {
+            if self.machine.step()?
+            {
+                return Err(format!("the programm ended before {} returned", label))
+            }
+
+            let tos = self.machine.reg_state.read(Register::tos);
+            if tos == 0 || tos > return_slot
+            {
+                break
+            }
+        }
+
+        Ok(CallResult{
+            registers: self.machine.reg_state,
+            cycles: self.machine.machine_information.get_cycles() - cycles_before,
+            output: self.machine.machine_information.take_output().into_iter().map(|(_,s)| s).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const SOURCE: &str = "
+_rom
+    msg: s \"not positive\"
+_code
+    .start:
+        push 9
+        sys
+
+    ; a = first + second
+    .add:
+        mov a, [tos+2]
+        add a, [tos+1]
+        ret 2
+
+    ; writes b to the adress in the argument
+    .store:
+        mov c, [tos+1]
+        mov [c], b
+        ret 1
+
+    ; fails if the argument is 0
+    .check:
+        push .msg
+        push [tos+2]
+        push 15
+        sys
+        ret 1
+";
+
+    fn guest_test() -> GuestTest
+    {
+        GuestTest::from_source("t.asm", PathBuf::new(), SOURCE, &[]).unwrap()
+    }
+
+    #[test]
+    fn call_function_test()
+    {
+        let mut test = guest_test();
+        let tos = test.read_register(Register::tos);
+
+        let res = test.call(".add", &[40, 2]).unwrap();
+        assert_eq!(res.registers.read(Register::a), 42);
+        assert_eq!(res.cycles, 3);
+
+        // the arguments were removed by `ret 2`
+        assert_eq!(test.read_register(Register::tos), tos);
+
+        let addr = test.machine.mem_state.get_heap_base() + 5;
+        test.set_register(Register::b, 7).unwrap();
+        test.call("store", &[addr]).unwrap();
+        assert!(test.set_register(Register::pc, 1).is_err());
+        assert_eq!(test.read_mem(addr), Ok(7));
+
+        assert!(test.call("missing", &[]).is_err());
+        assert_eq!(test.call("start", &[]).err(), Some("the programm ended before start returned".to_string()));
+    }
+
+    #[test]
+    fn assert_syscall_test()
+    {
+        let mut test = guest_test();
+        assert!(test.call("check", &[1]).is_ok());
+
+        let err = test.call("check", &[0]).err().unwrap();
+        assert!(err.starts_with("assertion failed: not positive at pc"), "{}", err);
+        assert!(err.ends_with("(check+6) t.asm:26"), "{}", err);
+    }
+}
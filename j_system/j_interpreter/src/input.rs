@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+/// the source the machine reads its input from
+pub enum InputSource
+{
+    /// read from the stdin of the interpreter
+    Stdin,
+
+    /// read from a buffer. mainly used for tests
+    Buffer(VecDeque<u8>),
+}
+
+impl InputSource
+{
+    pub fn from_bytes(bytes: &[u8]) -> Self
+    {
+        Self::Buffer(bytes.iter().copied().collect())
+    }
+
+    /// returns the next byte without consuming it.
+    /// returns `None` on EOF or when stdin can not be read
+    pub fn peek_byte(&mut self) -> Option<u8>
+    {
+        match self
+        {
+            Self::Stdin =>
+            {
+                // the lock is only held for this call, so other reads of
+                // stdin (e.g. the debugger) still see the same buffer
+                let mut lock = io::stdin().lock();
+                lock.fill_buf().ok()?.first().copied()
+            },
+            Self::Buffer(buf) => buf.front().copied(),
+        }
+    }
+
+    /// returns and consumes the next byte.
+    /// returns `None` on EOF or when stdin can not be read
+    pub fn read_byte(&mut self) -> Option<u8>
+    {
+        match self
+        {
+            Self::Stdin =>
+            {
+                let mut lock = io::stdin().lock();
+                let b = lock.fill_buf().ok()?.first().copied()?;
+                lock.consume(1);
+                Some(b)
+            },
+            Self::Buffer(buf) => buf.pop_front(),
+        }
+    }
+
+    /// reads up to `max` bytes until a newline is reached.
+    /// The newline (and a preceding `\r`) is consumed but not returned.
+    /// If the line is longer than `max` the rest of the line stays in the input.
+    /// returns `None` if EOF was reached before anything could be read
+    pub fn read_line(&mut self, max: usize) -> Option<Vec<u8>>
+    {
+        let mut line = vec![];
+        let mut newline_found = false;
+
+        while line.len() < max
+        {
+            match self.read_byte()
+            {
+                Some(b'\n') => {newline_found = true; break},
+                Some(b) => line.push(b),
+                None => break,
+            }
+        }
+
+        // consume the newline if the line filled exactly the buffer
+        if !newline_found && line.len() == max && self.peek_byte() == Some(b'\n')
+        {
+            self.read_byte();
+            newline_found = true;
+        }
+
+        if line.last() == Some(&b'\r')
+        {
+            line.pop();
+        }
+
+        if line.is_empty() && !newline_found
+        {
+            return None;
+        }
+
+        Some(line)
+    }
+
+    /// reads up to `max` bytes. returns less bytes if EOF was reached
+    pub fn read_bytes(&mut self, max: usize) -> Vec<u8>
+    {
+        let mut bytes = vec![];
+
+        while bytes.len() < max
+        {
+            match self.read_byte()
+            {
+                Some(b) => bytes.push(b),
+                None => break,
+            }
+        }
+
+        bytes
+    }
+
+    /// skips leading whitespaces and reads the next whitespace separated token.
+    /// returns `None` if EOF was reached before a token was found
+    pub fn read_token(&mut self) -> Option<String>
+    {
+        while self.peek_byte()?.is_ascii_whitespace()
+        {
+            self.read_byte();
+        }
+
+        let mut token = vec![];
+
+        while let Some(b) = self.peek_byte()
+        {
+            if b.is_ascii_whitespace()
+            {
+                break;
+            }
+            token.push(b);
+            self.read_byte();
+        }
+
+        Some(String::from_utf8_lossy(&token).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn read_line_test()
+    {
+        let mut inp = InputSource::from_bytes(b"abc\r\ndefgh\n\n");
+
+        assert_eq!(inp.read_line(10), Some(b"abc".to_vec()));
+        assert_eq!(inp.read_line(3), Some(b"def".to_vec()));
+        assert_eq!(inp.read_line(2), Some(b"gh".to_vec()));
+        assert_eq!(inp.read_line(10), Some(vec![]));
+        assert_eq!(inp.read_line(10), None);
+    }
+
+    #[test]
+    fn read_token_test()
+    {
+        let mut inp = InputSource::from_bytes(b"  12\n 0x1f");
+
+        assert_eq!(inp.read_token(), Some("12".into()));
+        assert_eq!(inp.read_token(), Some("0x1f".into()));
+        assert_eq!(inp.read_token(), None);
+    }
+}
@@ -0,0 +1,106 @@
+use j_system_definition::register::RegisterState;
+
+use std::collections::VecDeque;
+
+/// the changes of one executed instruction
+pub struct JournalEntry
+{
+    /// cycle count before the instruction was executed
+    pub cycle: u128,
+
+    /// registers before the instruction was executed.
+    /// `pc` points to the instruction itself
+    pub regs: RegisterState,
+
+    /// memory writes of the instruction: (adress, old value, new value)
+    pub mem_writes: Vec<(u64,u64,u64)>,
+}
+
+/// bounded history of the last executed instructions.
+/// Only registers and memory are recorded, output, input
+/// and devices can not be reverted
+pub struct Journal
+{
+    entries: VecDeque<JournalEntry>,
+    capacity: usize,
+}
+
+impl Journal
+{
+    pub fn new(capacity: usize) -> Self
+    {
+        Self{entries: VecDeque::with_capacity(capacity.min(1 << 16)), capacity}
+    }
+
+    /// adds an entry and drops the oldest one if the journal is full
+    pub fn push(&mut self, entry: JournalEntry)
+    {
+        if self.capacity == 0
+        {
+            return
+        }
+
+        if self.entries.len() == self.capacity
+        {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// removes the entry of the last executed instruction
+    pub fn pop(&mut self) -> Option<JournalEntry>
+    {
+        self.entries.pop_back()
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.entries.is_empty()
+    }
+
+    /// the last recorded write to the adress: (entry, old value, new value)
+    pub fn last_write(&self, addr: u64) -> Option<(&JournalEntry,u64,u64)>
+    {
+        self.entries.iter().rev().find_map(|entry|
+            entry.mem_writes.iter().rev()
+                .find(|(a,_,_)| *a == addr)
+                .map(|(_,old,new)| (entry, *old, *new)))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn entry(cycle: u128, mem_writes: Vec<(u64,u64,u64)>) -> JournalEntry
+    {
+        JournalEntry{cycle, regs: RegisterState::new(), mem_writes}
+    }
+
+    #[test]
+    fn bounded_journal_test()
+    {
+        let mut journal = Journal::new(2);
+
+        journal.push(entry(0, vec![(10,0,1)]));
+        journal.push(entry(1, vec![(10,1,2), (11,0,5)]));
+        journal.push(entry(2, vec![]));
+
+        // the oldest entry was dropped
+        assert_eq!(journal.len(), 2);
+
+        let (e,old,new) = journal.last_write(10).unwrap();
+        assert_eq!((e.cycle,old,new), (1,1,2));
+
+        assert_eq!(journal.pop().map(|e| e.cycle), Some(2));
+        assert_eq!(journal.pop().map(|e| e.cycle), Some(1));
+        assert!(journal.pop().is_none());
+        assert!(journal.last_write(10).is_none());
+    }
+}
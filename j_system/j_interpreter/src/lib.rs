@@ -0,0 +1,35 @@
+mod check_instruction;
+mod deserialization;
+mod exec;
+mod type_cov_parse;
+mod output;
+
+pub mod memory;
+pub mod device;
+pub mod syscall;
+pub mod syscall_handler;
+pub mod input;
+pub mod file_system;
+pub mod load_bin;
+pub mod line_table;
+pub mod debug;
+pub mod journal;
+pub mod dap;
+pub mod gdb_stub;
+pub mod debug_script;
+pub mod snapshot;
+pub mod trace;
+pub mod profile;
+pub mod coverage;
+pub mod cost;
+pub mod decode_cache;
+pub mod block_engine;
+pub mod lockstep;
+pub mod fuzz;
+pub mod golden;
+pub mod guest_test;
+pub mod machine;
+pub mod rng;
+
+#[cfg(test)]
+mod test_util;
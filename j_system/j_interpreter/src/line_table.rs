@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// the place in the assembly source an instruction was assembled from
+#[derive(Clone,PartialEq,Debug)]
+pub struct SourceLocation
+{
+    pub file: String,
+    pub line: u64,
+}
+
+/// maps the adresses of the instructions to the source lines.
+/// Gets created from the `lines.dbg` file of the assembler
+pub struct LineTable
+{
+    /// Key: adress of the instruction
+    lines: BTreeMap<u64,SourceLocation>,
+
+    /// directory the source files are read from
+    source_dir: PathBuf,
+}
+
+impl LineTable
+{
+    /// parses the lines of the form `<adress>\t<line>\t<file>`.
+    /// Invalid lines are ignored
+    pub fn parse(input: &str, source_dir: PathBuf) -> Self
+    {
+        let mut lines = BTreeMap::new();
+
+        for entry in input.lines()
+        {
+            let parts:Vec<&str> = entry.splitn(3, '\t').collect();
+
+            if let [addr, line, file] = parts[..]
+            {
+                // the lines start at 1
+                if let (Ok(addr), Ok(line @ 1..)) = (addr.parse::<u64>(), line.parse::<u64>())
+                {
+                    lines.insert(addr, SourceLocation{file: file.into(), line});
+                }
+            }
+        }
+
+        Self{lines, source_dir}
+    }
+
+    /// the line table of a programm that was assembled in-process
+    pub fn from_locations(locations: impl Iterator<Item=(u64,SourceLocation)>, source_dir: PathBuf) -> Self
+    {
+        Self{lines: locations.collect(), source_dir}
+    }
+
+    /// the source location of the instruction that contains the adress
+    pub fn location(&self, addr: u64) -> Option<&SourceLocation>
+    {
+        self.lines.range(..=addr).next_back().map(|(_,loc)| loc)
+    }
+
+    /// adress of the first instruction at or after the line.
+    /// `file` can also be the end of the path, e.g. `in.asm` for `./in.asm`
+    pub fn adress_of(&self, file: &str, line: u64) -> Option<u64>
+    {
+        self.lines.iter()
+            .filter(|(_,loc)| loc.line >= line && same_file(&loc.file, file))
+            .min_by_key(|(addr,loc)| (loc.line, **addr))
+            .map(|(addr,_)| *addr)
+    }
+
+    /// the adresses of all instructions with their source location, sorted by adress
+    pub fn instructions(&self) -> impl Iterator<Item=(u64,&SourceLocation)>
+    {
+        self.lines.iter().map(|(addr,loc)| (*addr,loc))
+    }
+
+    /// path of the source file of the location
+    pub fn source_path(&self, loc: &SourceLocation) -> PathBuf
+    {
+        self.source_dir.join(loc.file.strip_prefix("./").unwrap_or(&loc.file))
+    }
+
+    /// the source lines from `line-radius` to `line+radius`
+    pub fn source_context(&self, loc: &SourceLocation, radius: u64) -> Option<Vec<(u64,String)>>
+    {
+        let content = fs::read_to_string(self.source_path(loc)).ok()?;
+        let first = loc.line.saturating_sub(radius).max(1);
+
+        Some(content.lines().enumerate()
+            .map(|(ii,line)| (ii as u64 +1, line.to_string()))
+            .skip(first as usize -1)
+            .take(loc.line.saturating_add(radius).saturating_add(1).saturating_sub(first) as usize)
+            .collect())
+    }
+}
+
+/// also matches if one path is the end of the other,
+/// e.g. `/home/user/in.asm` for `./in.asm`
+fn same_file(path: &str, name: &str) -> bool
+{
+    let path = path.strip_prefix("./").unwrap_or(path);
+    let name = name.strip_prefix("./").unwrap_or(name);
+
+    path == name || path.ends_with(&format!("/{}", name)) || name.ends_with(&format!("/{}", path))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn line_table_lookup_test()
+    {
+        let table = LineTable::parse("1\t7\t./in.asm\n3\t8\t./in.asm\ninvalid\n24\t4\tj_std_lib.asm\n", PathBuf::new());
+
+        assert_eq!(table.location(4), Some(&SourceLocation{file: "./in.asm".into(), line: 8}));
+        assert_eq!(table.location(30).map(|l| l.line), Some(4));
+        assert_eq!(table.location(0), None);
+
+        assert_eq!(table.adress_of("in.asm", 8), Some(3));
+        // the next line that contains an instruction
+        assert_eq!(table.adress_of("./in.asm", 2), Some(1));
+        assert_eq!(table.adress_of("j_std_lib.asm", 1), Some(24));
+        assert_eq!(table.adress_of("in.asm", 9), None);
+        // absolute path of an editor
+        assert_eq!(table.adress_of("/home/user/j_std_lib.asm", 4), Some(24));
+
+        // the lines start at 1
+        assert_eq!(LineTable::parse("1\t0\t./in.asm\n", PathBuf::new()).location(1), None);
+    }
+
+    #[test]
+    fn source_context_test()
+    {
+        let dir = std::env::temp_dir().join("j_interpreter_source_context_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("t.asm"), "a\nb\nc\nd\n").unwrap();
+
+        let table = LineTable::from_locations(std::iter::empty(), dir.clone());
+        let context = |line, radius| table.source_context(&SourceLocation{file: "t.asm".into(), line}, radius)
+            .map(|lines| lines.into_iter().map(|(nr,_)| nr).collect::<Vec<_>>());
+
+        assert_eq!(context(2, 1), Some(vec![1,2,3]));
+        assert_eq!(context(0, 1), Some(vec![1]));
+        assert_eq!(context(4, u64::MAX), Some(vec![1,2,3,4]));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -1,52 +1,123 @@
-use std::fs::{File, self};
-use std::io::prelude::*;
+use std::fs;
 use std::collections::HashMap;
+use std::path::PathBuf;
 
+use crate::line_table::{LineTable,SourceLocation};
+
+use j_assembler::assembler::{Assembly,BINARY_MAGIC,HEADER_SIZE};
+
+#[derive(Clone)]
 pub struct Binary
 {
     pub code:           Vec<u64>,
     pub rom:            Vec<u64>,
-    pub start_ptr:      u64
+    pub start_ptr:      u64,
+
+    /// adresses the sections are loaded to
+    pub rom_base:       u64,
+    pub code_base:      u64,
 }
 
 impl Binary{
 
     pub fn new() -> Self
     {
-        Self{
-            code:vec![],
-            rom:vec![],
-            start_ptr:0}
+        Self::from_sections(vec![], vec![], 0)
+    }
+
+    /// rom at 0 and the code directly after it
+    pub fn from_sections(rom: Vec<u64>, code: Vec<u64>, start_ptr: u64) -> Self
+    {
+        let code_base = rom.len() as u64;
+        Self{code, rom, start_ptr, rom_base: 0, code_base}
     }
 
-    pub fn load_file(&mut self,file_name: String)
+    pub fn load_file(&mut self,file_name: String) -> Result<(),String>
     {
-        let mut file = File::open(&file_name).unwrap();
-        let mut s = String::new();
-        file.read_to_string(&mut s).unwrap();
+        let s = fs::read_to_string(&file_name).map_err(|e| format!("couldn't read {}: {}", file_name, e))?;
 
-        let mut bin: Vec<u64> = vec![];
+        let bin = s.lines().map(|line| line.parse::<u64>().map_err(|_| format!("invalid value in {}: {}", file_name, line)))
+            .collect::<Result<Vec<u64>,String>>()?;
 
-        for line in s.lines()
+        *self = Self::parse(bin)?;
+        Ok(())
+    }
+
+    /// binaries of older assemblers have no header,
+    /// their rom starts at 0 and the code directly after it
+    fn parse(mut bin: Vec<u64>) -> Result<Self,String>
+    {
+        let (rom_base, code_base) = match bin.first()
         {
-            bin.push(line.parse::<u64>().unwrap())
-        }
+            Some(&BINARY_MAGIC) if bin.len() >= HEADER_SIZE => 
+            {
+                let bases = (bin[1], bin[2]);
+                bin.drain(..HEADER_SIZE);
+                (bases.0, Some(bases.1))
+            },
+            Some(&BINARY_MAGIC) => return Err("the header of the binary is incomplete".into()),
+            _ => (0, None),
+        };
 
         // move the whole binary into the sections and set
         // the start pointer
-        self.start_ptr = bin.pop().unwrap();
-
-        // get the split point
-        let split_point = bin.pop().unwrap();
+        let (start_ptr, split_point) = match (bin.pop(), bin.pop())
+        {
+            (Some(start_ptr), Some(split_point)) if split_point <= bin.len() as u64 => (start_ptr, split_point),
+            _ => return Err("the binary is too short".into()),
+        };
 
         // split vec into the sections
         let (rom,code) = bin.split_at(split_point as usize);
 
-        // set sections
-        self.rom = rom.to_vec();
-        self.code = code.to_vec();
+        Ok(Self{
+            code: code.to_vec(),
+            rom: rom.to_vec(),
+            start_ptr,
+            rom_base,
+            code_base: code_base.unwrap_or(rom_base + split_point),
+        })
     }
 
+    /// the sections of a programm that was assembled in-process
+    pub fn from_assembly(assembly: &Assembly) -> Self
+    {
+        let rom_end = HEADER_SIZE + assembly.rom_len as usize;
+        let code_end = rom_end + assembly.code_len as usize;
+        Self{
+            rom: assembly.binary[HEADER_SIZE..rom_end].to_vec(),
+            code: assembly.binary[rom_end..code_end].to_vec(),
+            start_ptr: assembly.start,
+            rom_base: assembly.rom_base,
+            code_base: assembly.code_base,
+        }
+    }
+}
+
+/// the labels of a programm that was assembled in-process, like `load_symbols`
+pub fn symbols_of(assembly: &Assembly) -> HashMap<u64,Vec<String>>
+{
+    let mut map:HashMap<u64,Vec<String>> = HashMap::new();
+    for (name,addr) in &assembly.labels
+    {
+        map.entry(*addr).or_default().push(name.clone());
+    }
+    map
+}
+
+/// the line table of a programm that was assembled in-process, like `load_line_table`
+pub fn line_table_of(assembly: &Assembly, source_dir: PathBuf) -> LineTable
+{
+    let locations = assembly.line_table.iter()
+        .map(|(addr,origin)| (*addr, SourceLocation{file: origin.file.clone(), line: origin.line}));
+    LineTable::from_locations(locations, source_dir)
+}
+
+/// loads the line table that was created by the assembler.
+/// The source files are read from `source_dir`
+pub fn load_line_table(source_dir: PathBuf) -> Option<LineTable>
+{
+    fs::read_to_string("lines.dbg").ok().map(|inp| LineTable::parse(&inp, source_dir))
 }
 
 pub fn load_symbols() -> Option<HashMap<u64,Vec<String>>>
@@ -97,4 +168,61 @@ fn parse_parts(inp: &str) -> Option<(u64,String)>
 
     return Some((addr,label_name.into()));
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::{MachineInitInfo,MachineState};
+
+    use j_assembler::assembler::{assemble_with_bases,SectionBases};
+    use j_system_definition::register::Register;
+
+    const SOURCE: &str = "
+_rom
+    val: i 42
+_code
+    .start:
+        mov a, [.val]
+        jmp .end
+    .end:
+        push 9
+        sys
+";
+
+    #[test]
+    fn parse_binary_test()
+    {
+        let with_header = Binary::parse(vec![BINARY_MAGIC, 10, 50, 0, 7, 1, 2, 2, 51]).unwrap();
+        assert_eq!((with_header.rom, with_header.code), (vec![0,7], vec![1,2]));
+        assert_eq!((with_header.rom_base, with_header.code_base, with_header.start_ptr), (10, 50, 51));
+
+        // older binaries start with the rom
+        let legacy = Binary::parse(vec![0, 7, 1, 2, 2, 3]).unwrap();
+        assert_eq!((legacy.rom, legacy.code), (vec![0,7], vec![1,2]));
+        assert_eq!((legacy.rom_base, legacy.code_base, legacy.start_ptr), (0, 2, 3));
+
+        assert!(Binary::parse(vec![BINARY_MAGIC, 10]).is_err());
+        assert!(Binary::parse(vec![0, 7, 5, 3]).is_err());
+        assert!(Binary::parse(vec![]).is_err());
+    }
+
+    #[test]
+    fn relocated_sections_test()
+    {
+        let lines = SOURCE.lines().map(|line| line.to_string()).collect();
+        let assembly = assemble_with_bases(lines, "t.asm".to_string(), &[], SectionBases{rom: 300, code: Some(100)}).unwrap();
+        assert_eq!(assembly.start, 100);
+        assert!(assembly.labels.contains(&("val".to_string(), 301)));
+
+        let mut machine = MachineState::init(MachineInitInfo::default());
+        machine.laod_into_state(Binary::from_assembly(&assembly)).unwrap();
+        machine.run().unwrap();
+        assert_eq!(machine.reg_state.read(Register::a), 42);
+        assert_eq!(machine.mem_state.get_heap_base(), 302);
+
+        // the code would overwrite the rom
+        let lines = SOURCE.lines().map(|line| line.to_string()).collect();
+        assert!(assemble_with_bases(lines, "t.asm".to_string(), &[], SectionBases{rom: 100, code: Some(99)}).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use crate::block_engine::BlockEngine;
+use crate::machine::MachineState;
+
+use j_system_definition::register::Register;
+
+use std::collections::VecDeque;
+
+const REGISTERS: [Register;10] = [Register::a, Register::b, Register::c, Register::d, Register::e, Register::f,
+                                  Register::tos, Register::bos, Register::pc, Register::s];
+
+/// number of executed instructions shown in the report of a divergence
+const HISTORY_SIZE: usize = 16;
+
+/// number of instructions shown from the pc of the divergence on
+const DISASSEMBLY_SIZE: u64 = 5;
+
+/// runs the same programm on two machines with different configurations
+/// (decode cache, block engine, ...) and compares the registers, cycles,
+/// written memory and output whenever both executed the same amount of instructions.
+///
+/// The interpreter is compared after every instruction. The block engine runs its
+/// blocks as usual, so it is compared at the end of every block and the other machine
+/// catches up with single instructions
+pub struct Lockstep
+{
+    machines: [Side;2],
+
+    /// the last executed instructions of the first machine: (number of the instruction, pc)
+    history: VecDeque<(u64,u64)>,
+}
+
+struct Side
+{
+    name: String,
+    machine: MachineState,
+    engine: Option<BlockEngine>,
+
+    /// amount of executed instructions
+    instructions: u64,
+}
+
+/// how both machines ended
+#[derive(Debug,PartialEq)]
+pub struct LockstepEnd
+{
+    pub instructions: u64,
+
+    /// the error both machines stopped with
+    pub error: Option<String>,
+}
+
+impl Side
+{
+    fn new(name: &str, mut machine: MachineState) -> Result<Self,String>
+    {
+        if machine.is_observed()
+        {
+            return Err(format!("{}: a machine in lockstep can't be debugged, traced, profiled or record history", name));
+        }
+
+        machine.reg_state.store_to_read_only(Register::pc, machine.next_ptr);
+        machine.mem_state.record_writes(true);
+        machine.machine_information.buffer_output();
+
+        let engine = machine.block_engine.take();
+        Ok(Self{name: name.to_string(), machine, engine, instructions: 0})
+    }
+
+    /// runs one instruction or one block
+    fn step(&mut self) -> Result<bool,String>
+    {
+        match &mut self.engine
+        {
+            Some(engine) =>
+            {
+                let before = engine.executed();
+                let res = engine.step_block(&mut self.machine);
+                self.instructions += engine.executed() - before;
+                res
+            },
+            None =>
+            {
+                self.instructions += 1;
+                self.machine.step()
+            },
+        }
+    }
+
+    fn disassemble(&mut self, addr: u64) -> Option<(String,u64)>
+    {
+        self.machine.decode_cache.fetch(&mut self.machine.mem_state, addr).map(|(inst,next)| (inst.as_string(), next))
+    }
+}
+
+impl Lockstep
+{
+    /// the machines have to be loaded with the same programm
+    pub fn new(left: (&str,MachineState), right: (&str,MachineState)) -> Result<Self,String>
+    {
+        Ok(Self{
+            machines: [Side::new(left.0, left.1)?, Side::new(right.0, right.1)?],
+            history: VecDeque::new(),
+        })
+    }
+
+    /// runs both machines until they end.
+    /// Returns the report of the first divergence as error
+    pub fn run(&mut self) -> Result<LockstepEnd,String>
+    {
+        loop
+        {
+            if let Some(end) = self.step()?
+            {
+                return Ok(end);
+            }
+        }
+    }
+
+    /// runs both machines until they executed the same amount of instructions,
+    /// returns how they ended if they did
+    pub fn step(&mut self) -> Result<Option<LockstepEnd>,String>
+    {
+        let pc = self.machines[0].machine.reg_state.read(Register::pc);
+        let mut results:[Option<Result<bool,String>>;2] = [None, None];
+
+        loop
+        {
+            let counts = [self.machines[0].instructions, self.machines[1].instructions];
+            let mut stepped = false;
+
+            for ii in 0..2
+            {
+                // a machine that stopped can't catch up
+                let stopped = matches!(results[ii], Some(Ok(true)) | Some(Err(_)));
+                if stopped || (results[ii].is_some() && counts[ii] >= counts[1-ii])
+                {
+                    continue;
+                }
+
+                let pc = self.machines[ii].machine.reg_state.read(Register::pc);
+                results[ii] = Some(self.machines[ii].step());
+                stepped = true;
+
+                if ii == 0
+                {
+                    self.history.push_back((self.machines[0].instructions, pc));
+                    if self.history.len() > HISTORY_SIZE
+                    {
+                        self.history.pop_front();
+                    }
+                }
+            }
+
+            if !stepped
+            {
+                break;
+            }
+        }
+
+        let results = results.map(|res| res.unwrap_or(Ok(false)));
+        let instructions = self.machines[0].instructions;
+
+        let differences = self.differences(&results);
+        if !differences.is_empty()
+        {
+            return Err(self.report(pc, &differences));
+        }
+
+        Ok(match &results[0]
+        {
+            Ok(false) => None,
+            Ok(true) => Some(LockstepEnd{instructions, error: None}),
+            Err(msg) => Some(LockstepEnd{instructions, error: Some(msg.clone())}),
+        })
+    }
+
+    /// what is different between the machines after an instruction
+    fn differences(&mut self, results: &[Result<bool,String>;2]) -> Vec<String>
+    {
+        let [left,right] = &mut self.machines;
+        let names = (&left.name, &right.name);
+        let (l,r) = (&mut left.machine, &mut right.machine);
+        let mut differences = vec![];
+
+        if left.instructions != right.instructions
+        {
+            differences.push(format!("instructions: {} ({}) != {} ({})", left.instructions, names.0, right.instructions, names.1));
+        }
+
+        if results[0] != results[1]
+        {
+            differences.push(format!("result: {:?} ({}) != {:?} ({})", results[0], names.0, results[1], names.1));
+        }
+
+        for reg in REGISTERS
+        {
+            let (vl,vr) = (l.reg_state.read(reg), r.reg_state.read(reg));
+            if vl != vr
+            {
+                differences.push(format!("register {:?}: {} ({}) != {} ({})", reg, vl, names.0, vr, names.1));
+            }
+        }
+
+        let (cl,cr) = (l.machine_information.get_cycles(), r.machine_information.get_cycles());
+        if cl != cr
+        {
+            differences.push(format!("cycles: {} ({}) != {} ({})", cl, names.0, cr, names.1));
+        }
+
+        let (wl,wr) = (l.mem_state.take_recorded_writes(), r.mem_state.take_recorded_writes());
+        if wl != wr
+        {
+            let writes = |writes: &Vec<(u64,u64,u64)>| writes.iter().map(|(addr,_,val)| format!("[{}]={}", addr, val)).collect::<Vec<_>>().join(" ");
+            differences.push(format!("memory writes: {} ({}) != {} ({})", writes(&wl), names.0, writes(&wr), names.1));
+        }
+
+        let (ol,or) = (l.machine_information.take_output(), r.machine_information.take_output());
+        let (ol,or):(Vec<String>,Vec<String>) = (ol.into_iter().map(|(_,s)| s).collect(), or.into_iter().map(|(_,s)| s).collect());
+        if ol != or
+        {
+            differences.push(format!("output: {:?} ({}) != {:?} ({})", ol.concat(), names.0, or.concat(), names.1));
+        }
+        differences
+    }
+
+    fn report(&mut self, pc: u64, differences: &[String]) -> String
+    {
+        let left = &mut self.machines[0];
+        let mut s = format!("divergence after {} instructions at pc {}\n", left.instructions, left.machine.debug.location(pc));
+
+        for difference in differences
+        {
+            s.push_str(&format!("    {}\n", difference));
+        }
+
+        s.push_str(&format!("\ndisassembly ({}):\n", left.name));
+        let mut addr = pc;
+        for ii in 0..DISASSEMBLY_SIZE
+        {
+            match left.disassemble(addr)
+            {
+                Some((asm,next)) =>
+                {
+                    s.push_str(&format!("{} {:<30} {}\n", if ii == 0 {">"} else {" "}, left.machine.debug.location(addr), asm));
+                    addr = next;
+                },
+                None => break,
+            }
+        }
+
+        // the instructions are decoded from the current memory
+        s.push_str("\nhistory:\n");
+        for (instruction,addr) in &self.history
+        {
+            let asm = left.disassemble(*addr).map_or("<invalid instruction>".to_string(), |(asm,_)| asm);
+            s.push_str(&format!("{:>8}  {:<30} {}\n", instruction, left.machine.debug.location(*addr), asm));
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::cost::CostModel;
+    use crate::decode_cache::DecodeCache;
+    use crate::machine::MachineInitInfo;
+    use crate::rng::Rng;
+    use crate::test_util::machine_with;
+
+    /// the interpreter without decode cache, the interpreter and the block engine
+    fn machines(code: &[u64]) -> Vec<MachineState>
+    {
+        (0..3).map(|ii|
+        {
+            let mut machine = machine_with(MachineInitInfo{seed: Some(1), max_cycles: 10_000, ..Default::default()}, code);
+            machine.decode_cache = DecodeCache::new(ii != 0);
+            machine.decode_cache.reset(&mut machine.mem_state);
+            machine.block_engine = if ii == 2 {Some(BlockEngine::new())} else {None};
+            machine
+        }).collect()
+    }
+
+    /// a random programm with arithmetic, stack operations and forward jumps
+    fn random_programm(seed: u64) -> Vec<u64>
+    {
+        let mut rng = Rng::new(seed);
+        let mut random = |max: u64| rng.below(max);
+
+        let mut code = vec![];
+        // adresses of the conditional jumps that still need a target
+        let mut jumps = vec![];
+        let mut pushed = 0;
+
+        for _ in 0..40
+        {
+            // patch the jumps that target this instruction
+            let addr = code.len() as u64 + 1;
+            jumps.retain(|jump: &usize| if random(3) == 0 {code[*jump] = addr; false} else {true});
+
+            let (reg1,reg2) = (random(6) + 1, random(6) + 1);
+            match random(7)
+            {
+                // add, sub, xor, or, and with a register
+                0 => code.push((random(5) + 1) << 56 | 0x0022_0000_0000_0000 | reg1 << 16 | reg2),
+                // add, sub, mov with a constant
+                1 => code.extend([[0x01, 0x02, 0x0F][random(3) as usize] << 56 | 0x0021_0000_0000_0000 | reg1 << 16, random(1000)]),
+                // shr, shl
+                2 => code.extend([(random(2) + 6) << 56 | 0x0021_0000_0000_0000 | reg1 << 16, random(8)]),
+                // cmp and a conditional jump
+                3 =>
+                {
+                    code.push(0x0922_0000_0000_0000 | reg1 << 16 | reg2);
+                    code.extend([(random(5) + 0x0A) << 56 | 0x0010_0000_0000_0000, 0]);
+                    jumps.push(code.len()-1);
+                },
+                4 => {code.push(0x1020_0000_0000_0000 | reg1 << 16); pushed += 1},
+                5 if pushed > 0 => {code.push(0x1120_0000_0000_0000 | reg1 << 16); pushed -= 1},
+                // mov reg, [tos]
+                _ if pushed > 0 => code.extend([0x0F24_0000_0000_0007 | reg1 << 16, 0]),
+                _ => code.push(0x0F22_0000_0000_0000 | reg1 << 16 | reg2),
+            }
+        }
+
+        let end = code.len() as u64 + 1;
+        for jump in jumps
+        {
+            code[jump] = end;
+        }
+        code.extend([0x1010_0000_0000_0000, 9, 0x1600_0000_0000_0000]);
+        code
+    }
+
+    #[test]
+    fn random_programms_test()
+    {
+        let mut ended = 0;
+        for seed in 0..200
+        {
+            let code = random_programm(seed);
+
+            for (ii,name) in [(1,"decode cache"), (2,"blocks")]
+            {
+                let mut machines = machines(&code);
+                let machine = machines.remove(ii);
+
+                // reading below the stack after a skipped push fails on both machines
+                let end = Lockstep::new(("reference", machines.remove(0)), (name, machine)).unwrap().run();
+                match end
+                {
+                    Ok(LockstepEnd{error: None, ..}) => ended += 1,
+                    Ok(_) => {},
+                    Err(report) => panic!("seed {}: {}", seed, report),
+                }
+            }
+        }
+        assert!(ended > 300, "{}", ended);
+    }
+
+    #[test]
+    fn divergence_report_test()
+    {
+        // loop: add a, 1; cmp a, 3; jl loop; push 9; sys (End)
+        let code = vec![0x0121_0000_0001_0000, 1, 0x0921_0000_0001_0000, 3, 0x0E10_0000_0000_0000, 1,
+                        0x1010_0000_0000_0000, 9, 0x1600_0000_0000_0000];
+        let mut machines = machines(&code);
+        let (mut blocks, mut interpreter, reference) = (machines.pop().unwrap(), machines.pop().unwrap(), machines.pop().unwrap());
+
+        // the same cycles with a different cost model don't diverge, different ones do
+        blocks.cost_model = CostModel::parse(r#"{"instructions": {"jl": 1}}"#).unwrap();
+        interpreter.cost_model = CostModel::parse(r#"{"instructions": {"jl": 2}}"#).unwrap();
+
+        assert_eq!(Lockstep::new(("reference", reference), ("blocks", blocks)).unwrap().run(),
+            Ok(LockstepEnd{instructions: 11, error: None}));
+
+        let reference = self::machines(&code).remove(0);
+        let report = Lockstep::new(("reference", reference), ("jl costs 2", interpreter)).unwrap().run().unwrap_err();
+        assert!(report.starts_with("divergence after 3 instructions at pc 5\n    cycles: 3 (reference) != 4 (jl costs 2)\n"), "{}", report);
+        assert!(report.contains("\n> 5"), "{}", report);
+        assert!(report.ends_with("       3  5                              jl\t 1\n"), "{}", report);
+
+        // the block engine is compared after the whole block of add, cmp and jl
+        let (mut blocks, reference) = (self::machines(&code).remove(2), self::machines(&code).remove(0));
+        blocks.cost_model = CostModel::parse(r#"{"instructions": {"add": 2}}"#).unwrap();
+
+        let report = Lockstep::new(("reference", reference), ("add costs 2", blocks)).unwrap().run().unwrap_err();
+        assert!(report.starts_with("divergence after 3 instructions at pc 1\n    cycles: 3 (reference) != 4 (add costs 2)\n"), "{}", report);
+    }
+}
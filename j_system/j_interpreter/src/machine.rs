@@ -1,13 +1,28 @@
 use j_system_definition::register::*;
 use j_system_definition::instructions::*;
 
-use crate::deserialization::*;
-use crate::memory::MemModel;
+use crate::memory::{MemModel,MemLayout};
 use crate::load_bin::Binary;
+use crate::input::InputSource;
+use crate::line_table::LineTable;
+use crate::journal::{Journal,JournalEntry};
+use crate::trace::Tracer;
+use crate::profile::Profiler;
+use crate::coverage::Coverage;
+use crate::cost::CostModel;
+use crate::decode_cache::DecodeCache;
+use crate::block_engine::BlockEngine;
+use crate::file_system::{FileSystem,FileTable};
+use crate::syscall_handler::SyscallRegistry;
 use crate::debug::{ContinueAfterDebug,MachineDebug,DebugInformation};
+use crate::snapshot::get_u64;
+use crate::rng::Rng;
+
+use serde_json::{json,Value};
 
 use std::fs;
-use std::time::Instant;
+use std::io::Write;
+use std::time::{Instant,SystemTime,UNIX_EPOCH};
 use std::collections::{HashSet,HashMap};
 
 pub trait Exec
@@ -40,7 +55,9 @@ pub trait Exec
 pub struct MachineInitInfo
 {
     pub max_cycles: u128,
-    pub mem_size: u64,
+
+    /// size of the memory and where stack and heap are placed
+    pub layout: MemLayout,
     /// is Some when machine is in debug mode,
     /// contains a list of all breakpoints
     /// and a list of "debug Symbols"?
@@ -49,7 +66,41 @@ pub struct MachineInitInfo
     /// contains the labels with the label name(s)
     /// multiple lables can refer to the same address
     pub symbols: Option<HashMap<u64,Vec<String>>>,
+
+    /// maps the instructions to the source lines
+    pub line_table: Option<LineTable>,
+
+    /// amount of instructions that are recorded for reverse debugging.
+    /// Nothing is recorded if `None`
+    pub history_size: Option<usize>,
+
     pub write_to_file: bool,
+
+    /// seed for the random number generator that can be used through
+    /// the `Rand` syscall. A seed based on the system time is used if `None`
+    pub seed: Option<u64>,
+
+    /// the file system that can be accessed by the file syscalls
+    pub file_system: FileSystem,
+}
+
+impl Default for MachineInitInfo
+{
+    /// same defaults as the command line of the interpreter
+    fn default() -> Self
+    {
+        Self{
+            max_cycles: 10_000_000_000,
+            layout: MemLayout::default(),
+            debug_mode: None,
+            symbols: None,
+            line_table: None,
+            history_size: None,
+            write_to_file: false,
+            seed: None,
+            file_system: FileSystem::None,
+        }
+    }
 }
 
 pub struct MachineState
@@ -59,6 +110,38 @@ pub struct MachineState
     pub reg_state: RegisterState,
     pub next_ptr: u64,
     pub debug: DebugInformation,
+
+    /// files that were opened by the programm
+    pub files: FileTable,
+
+    /// the handlers that are called by the `sys` instruction.
+    /// Key: syscall number
+    pub syscalls: SyscallRegistry,
+
+    /// memory fault of the current instruction (e.g. NULL dereference).
+    /// The machine stops after the instruction if this is set
+    pub(crate) fault: Option<String>,
+
+    /// history of the last instructions for reverse debugging
+    pub journal: Option<Journal>,
+
+    /// logs the executed instructions if set
+    tracer: Option<Tracer>,
+
+    /// counts the executed instructions and the cycles per function if set
+    pub profiler: Option<Profiler>,
+
+    /// records the executed instructions and conditional jumps if set
+    pub coverage: Option<Coverage>,
+
+    /// the cycles the instructions take
+    pub cost_model: CostModel,
+
+    /// the decoded instructions of the code section
+    pub decode_cache: DecodeCache,
+
+    /// runs the programm as translated basic blocks if set, see `run`
+    pub block_engine: Option<BlockEngine>,
 }
 
 
@@ -73,13 +156,38 @@ pub struct MachineInformation
 
     /// keep track of how many istructions have been run
     cycle_count:u128,
+
+    /// random number generator of the `Rand` syscall
+    rng: Rng,
+
+    /// point in time the machine was started.
+    /// used for the monotonic clock of the `Time` syscall
+    start_time: Instant,
+
+    /// the source the input syscalls read from
+    input: InputSource,
+
+    /// true if the input syscalls used `input`. Snapshots can't contain the position in the input
+    input_read: bool,
 }
 
 impl MachineInformation
 {
-    pub fn inc_cycle(&mut self)
+    pub fn add_cycles(&mut self, cycles: u64)
+    {
+        self.cycle_count += cycles as u128;
+    }
+
+    /// sets the cycle count back when stepping backwards
+    pub fn set_cycles(&mut self, cycles: u128)
     {
-        self.cycle_count +=1;
+        self.cycle_count = cycles;
+    }
+
+    /// true if the machine is not allowed to run another instruction
+    pub fn cycles_exhausted(&self) -> bool
+    {
+        self.cycle_count >= self.max_cycles
     }
 
     pub fn get_cycles(&self) -> u128
@@ -99,6 +207,72 @@ impl MachineInformation
         }
     }
 
+    /// returns the next value of the random number generator.
+    /// if `bound` is not 0 the value will be in the range `0..bound`
+    pub fn next_rand(&mut self, bound: u64) -> u64
+    {
+        self.rng.below(bound)
+    }
+
+    pub fn get_input(&mut self) -> &mut InputSource
+    {
+        self.input_read = true;
+        &mut self.input
+    }
+
+    /// replaces the source the input syscalls read from (stdin by default)
+    pub fn set_input(&mut self, input: InputSource)
+    {
+        self.input = input;
+        self.input_read = false;
+    }
+
+    /// true if something was read from the input since it was set
+    pub fn input_was_read(&self) -> bool
+    {
+        self.input_read
+    }
+
+    /// milliseconds since the machine was started
+    pub fn get_uptime_millis(&self) -> u64
+    {
+        self.start_time.elapsed().as_millis() as u64
+    }
+
+    /// output, cycles and the random number generator for a snapshot
+    pub(crate) fn save_state(&self) -> Value
+    {
+        json!({
+            "cycles": self.cycle_count.to_string(),
+            "rng_state": self.rng.state(),
+            "output": self.output.iter().map(|(cy,s)| json!([cy.to_string(), s])).collect::<Vec<_>>(),
+        })
+    }
+
+    pub(crate) fn load_state(&mut self, state: &Value) -> Result<(),String>
+    {
+        let parse_cycles = |val: &Value| val.as_str().and_then(|s| s.parse::<u128>().ok()).ok_or("invalid cycle count".to_string());
+
+        self.cycle_count = parse_cycles(&state["cycles"])?;
+        self.rng = Rng::new(get_u64(state, "rng_state")?);
+        self.output = state["output"].as_array().ok_or("output is missing")?.iter()
+            .map(|entry| Ok((parse_cycles(&entry[0])?, entry[1].as_str().ok_or("invalid output")?.to_string())))
+            .collect::<Result<_,String>>()?;
+        Ok(())
+    }
+
+    /// keeps the output in memory instead of printing it to stdout
+    pub fn buffer_output(&mut self)
+    {
+        self.write_to_file = true
+    }
+
+    /// removes the output that was buffered until now
+    pub fn take_output(&mut self) -> Vec<(u128,String)>
+    {
+        std::mem::take(&mut self.output)
+    }
+
     pub fn get_output(self) -> Vec<(u128,String)>
     {
         self.output
@@ -137,100 +311,73 @@ impl MachineState
 {
     pub fn init(config: MachineInitInfo) -> Self
     {
-        let MachineInitInfo{max_cycles, mem_size, debug_mode, write_to_file,symbols} = config;
+        let MachineInitInfo{max_cycles, layout, debug_mode, write_to_file,symbols,line_table,history_size,seed,file_system} = config;
+
+        // use the system time as seed if no seed was provided
+        let rng = Rng::new(seed.unwrap_or_else(|| 
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0)));
 
         // mem_size: u64,breakpoints: Option< HashSet<u64>>
 
         //crate a new machine state instance
-        let mem = MemModel::new(mem_size);
+        let mut mem = MemModel::with_layout(layout);
+        mem.record_writes(history_size.is_some());
         let reg: RegisterState = RegisterState::new();  //{a:0,b:0,c:0,d:0,e:0,f:0,s:0,pc:0,tos: u64::MAX,bos: u64::MAX};
         
         // return initial machine state
         Self{
-            machine_information: MachineInformation{output: vec![], cycle_count: 0,max_cycles,write_to_file},
+            machine_information: MachineInformation{output: vec![], cycle_count: 0,max_cycles,write_to_file,rng,start_time: Instant::now(),input: InputSource::Stdin,input_read: false},
             mem_state:mem, 
             reg_state:reg,
             // will be set when loading of binary
             next_ptr: 0,
 
             // TODO: add the breakpoints/parse from debug output file
-            debug: DebugInformation{debug_mode,debug_step: None, symbols}
+            debug: DebugInformation::new(debug_mode, symbols, line_table),
+            files: FileTable::new(file_system),
+            syscalls: SyscallRegistry::with_defaults(),
+            fault: None,
+            journal: history_size.map(Journal::new),
+            tracer: None,
+            profiler: None,
+            coverage: None,
+            cost_model: CostModel::default(),
+            decode_cache: DecodeCache::new(true),
+            block_engine: None,
         }
     }
 
-    pub fn laod_into_state(&mut self, input: Binary)
+    /// fails if the binary does not fit into the memory layout
+    pub fn laod_into_state(&mut self, input: Binary) -> Result<(),String>
     {   
-        let Binary{code,rom,start_ptr} = input;
+        let Binary{code,rom,start_ptr,rom_base,code_base} = input;
 
-        self.mem_state.prepare_mem(rom,code);
+        self.mem_state.prepare_sections(rom_base,rom,code_base,code)?;
+        self.decode_cache.reset(&mut self.mem_state);
+        if let Some(engine) = &mut self.block_engine
+        {
+            engine.reset();
+        }
         self.next_ptr = start_ptr;
+        Ok(())
     }
 
-    pub fn run_program(mut self)
+    /// logs every executed instruction from now on
+    pub fn set_tracer(&mut self, tracer: Tracer)
     {
+        self.mem_state.record_writes(true);
+        self.tracer = Some(tracer);
+    }
 
+    /// runs the programm and prints the output and in debug mode some statistics.
+    /// panics when the programm fails
+    pub fn run_program(&mut self)
+    {
         let now = Instant::now();
 
-        // set pc to the pointer of the start label
-        self.reg_state.store_to_read_only(Register::pc, self.next_ptr);
-        
-        loop
-        {   
-            // get the pointer of the next instruction
-            let inst_ptr = self.reg_state.read(Register::pc);
-
-            // TODO: should the breakpoint hit before the inst in executed?
-            // hit a breakpoint?
-            
-            if self.debug.debug_mode.is_some()
-            {
-                match self.breakpoint()
-                {
-                    ContinueAfterDebug::Continue    => {},
-                    ContinueAfterDebug::Quit        => break,
-                }
-            }
-
-            
-            // decode the instruction to run and get the pointer of 
-            // the next instruction to in line
-            // next_ptr may be altered by a jump or a call command
-            // since it does just increases the pointer by 1,2 or 3 
-            // depending on the size of the instruction
-            let (inst,next_ptr) = deserialize_asm(&mut self.mem_state, inst_ptr).unwrap();
-
-            self.next_ptr = next_ptr;
-
-            if self.machine_information.get_cycles() >= self.machine_information.max_cycles
-            {
-                panic!("cycle count surpassed");
-                //crate::output::dump_and_panic(format!("maximum amount of allowed cycles surpassed"), register_state, stack_state);
-            }
-
-            /*
-            if current_line >= code.len() as u64
-            {
-                crate::output::dump_and_panic(format!("No more programm code to execute and no end statement"), register_state, stack_state);
-            }
-            */
-
-            let ret = self.run_instruction(inst);
-            
-            match ret
-            {
-                InstructionReturn::Err(msg) => 
-                {
-                    panic!("{}",msg);
-                    //crate::output::dump_and_panic(format!("instruction returned with error in code line: {}",current_line), register_state, stack_state);
-                },
-
-                InstructionReturn::End => break,
-                InstructionReturn::Next => self.reg_state.store_to_read_only(Register::pc, self.next_ptr),
-                InstructionReturn::JumpTo(ptr) => self.reg_state.store_to_read_only(Register::pc, ptr),
-                //crate::instructions::InstructionReturn::AwaitInput => break // TODO: how should input work?
-            }
-            
-            self.machine_information.inc_cycle();
+        if let Err(msg) = self.run()
+        {
+            panic!("{}", msg);
         }
 
         let mut output = String::new();
@@ -261,6 +408,11 @@ impl MachineState
                 //output.push_str(&self.mem_state.stack.into_iter().map(|x| format!("{x}\n")).reduce(|a,b| a+&b).unwrap_or("".to_string()));
             }
         }
+        else
+        {
+            // only the output of the programm without debug information
+            output.push_str(&self.machine_information.print_output(false));
+        }
 
         if self.machine_information.write_to_file
         {
@@ -271,4 +423,231 @@ impl MachineState
             self.machine_information.push_str(output)
         }
     }
+
+    /// runs the programm until it ends or the debugger quits.
+    /// Returns the error message if an instruction failed.
+    ///
+    /// The block engine is only used if it is set and nothing has to
+    /// observe the single instructions (debugger, tracer, profiler, coverage, history)
+    pub fn run(&mut self) -> Result<(),String>
+    {
+        // set pc to the pointer of the start label
+        self.reg_state.store_to_read_only(Register::pc, self.next_ptr);
+
+        let result = match self.block_engine.take()
+        {
+            Some(mut engine) if !self.is_observed() =>
+            {
+                let result = engine.run(self);
+                self.block_engine = Some(engine);
+                result
+            },
+            engine =>
+            {
+                self.block_engine = engine;
+                self.run_interpreter()
+            },
+        };
+
+        // the devices and the tracer are also finished if the programm failed,
+        // e.g. the framebuffer dump is most useful after a fault
+        let finished = self.mem_state.finish_devices();
+        self.flush_device_output();
+        if let Err(x) = finished
+        {
+            eprintln!("{}", x);
+        }
+
+        if let Some(Err(x)) = self.tracer.as_mut().map(Tracer::finish)
+        {
+            eprintln!("{}", x);
+        }
+
+        result
+    }
+
+    /// true if something has to see every single instruction
+    /// (debugger, tracer, profiler, coverage, history)
+    pub fn is_observed(&self) -> bool
+    {
+        self.debug.debug_mode.is_some() || self.tracer.is_some() || self.profiler.is_some()
+            || self.coverage.is_some() || self.journal.is_some()
+    }
+
+    fn run_interpreter(&mut self) -> Result<(),String>
+    {
+        loop
+        {   
+            // TODO: should the breakpoint hit before the inst in executed?
+            // hit a breakpoint?
+            
+            if self.debug.debug_mode.is_some()
+            {
+                match self.breakpoint()
+                {
+                    ContinueAfterDebug::Continue    => {},
+                    ContinueAfterDebug::Quit        => return Ok(()),
+                }
+            }
+
+            if self.step()?
+            {
+                return Ok(())
+            }
+        }
+    }
+
+    /// runs the instruction `pc` points to, returns true if the machine ended
+    pub fn step(&mut self) -> Result<bool,String>
+    {
+        // get the pointer of the next instruction.
+        // read after the debugger since stepping backwards changes it
+        let inst_ptr = self.reg_state.read(Register::pc);
+        let regs_before = self.reg_state;
+
+        
+        // decode the instruction to run and get the pointer of 
+        // the next instruction to in line
+        // next_ptr may be altered by a jump or a call command
+        // since it does just increases the pointer by 1,2 or 3 
+        // depending on the size of the instruction
+        let (inst,next_ptr) = match self.decode_cache.fetch(&mut self.mem_state, inst_ptr)
+        {
+            Some(decoded) => decoded,
+            None => return Err(format!("could not decode the instruction at {}", inst_ptr)),
+        };
+
+        self.next_ptr = next_ptr;
+
+        // the instruction is consumed by the execution
+        let instruction = inst.instruction;
+        let cost = self.instruction_cost(&inst);
+        let trace_asm = match &self.tracer
+        {
+            Some(tracer) if tracer.contains(inst_ptr) => Some(inst.as_string()),
+            _ => None,
+        };
+
+        if self.machine_information.cycles_exhausted()
+        {
+            return Err("cycle count surpassed".into());
+            //crate::output::dump_and_panic(format!("maximum amount of allowed cycles surpassed"), register_state, stack_state);
+        }
+
+        /*
+        if current_line >= code.len() as u64
+        {
+            crate::output::dump_and_panic(format!("No more programm code to execute and no end statement"), register_state, stack_state);
+        }
+        */
+
+        let ret = self.run_instruction(inst);
+        let jumped = matches!(ret, InstructionReturn::JumpTo(_));
+
+        // the last instruction is still traced
+        let ended = self.apply_return(inst_ptr, ret)?;
+        
+        // the writes stay recorded for the caller if they are not needed here
+        let mem_writes = match self.tracer.is_some() || self.journal.is_some()
+        {
+            true => self.mem_state.take_recorded_writes(),
+            false => vec![],
+        };
+
+        if let Some(profiler) = &mut self.profiler
+        {
+            // the cycles of the instruction that ends the machine are not counted
+            profiler.record(inst_ptr, instruction, self.reg_state.read(Register::pc), if ended {0} else {cost});
+        }
+
+        if let Some(coverage) = &mut self.coverage
+        {
+            coverage.record(inst_ptr, instruction, jumped);
+        }
+
+        if let (Some(tracer),Some(asm)) = (&mut self.tracer, trace_asm)
+        {
+            tracer.record(self.machine_information.get_cycles(), inst_ptr, &asm, &regs_before, &self.reg_state, &mem_writes);
+        }
+
+        if let Some(journal) = &mut self.journal
+        {
+            journal.push(JournalEntry{
+                cycle: self.machine_information.get_cycles(),
+                regs: regs_before,
+                mem_writes,
+            });
+        }
+
+        if !ended
+        {
+            self.advance_cycles(cost);
+        }
+        Ok(ended)
+    }
+
+    /// the cycles of the instruction that is about to run
+    pub(crate) fn instruction_cost(&self, inst: &AsmLine) -> u64
+    {
+        // the syscall number is on top of the stack before `sys` runs
+        let syscall = match inst.instruction
+        {
+            InstructionEnum::sys => self.mem_state.peek(self.reg_state.read(Register::tos)).ok(),
+            _ => None,
+        };
+        self.cost_model.cost(inst, syscall)
+    }
+
+    /// moves `pc` to the next instruction or returns the error of the instruction.
+    /// Returns true if the instruction ended the machine
+    pub(crate) fn apply_return(&mut self, inst_ptr: u64, ret: InstructionReturn) -> Result<bool,String>
+    {
+        let ret = match (self.fault.take(), ret)
+        {
+            (Some(msg),_) => InstructionReturn::Err(msg),
+            // jumping to NULL or a guard page is a fault of the jump and not of the target
+            (None,InstructionReturn::JumpTo(ptr)) if self.mem_state.check_guard(ptr).is_err() => 
+                InstructionReturn::Err(format!("jump to {}: {}", ptr, self.mem_state.check_guard(ptr).unwrap_err())),
+            (None,ret) => ret,
+        };
+
+        match ret
+        {
+            InstructionReturn::Err(msg) => 
+            {
+                return Err(format!("{} at pc {}",msg, self.debug.location(inst_ptr)));
+                //crate::output::dump_and_panic(format!("instruction returned with error in code line: {}",current_line), register_state, stack_state);
+            },
+
+            InstructionReturn::End => return Ok(true),
+            InstructionReturn::Next => self.reg_state.store_to_read_only(Register::pc, self.next_ptr),
+            InstructionReturn::JumpTo(ptr) => self.reg_state.store_to_read_only(Register::pc, ptr),
+            //crate::instructions::InstructionReturn::AwaitInput => break // TODO: how should input work?
+        }
+        Ok(false)
+    }
+
+    /// counts the cycles of a finished instruction and passes them to the devices
+    pub(crate) fn advance_cycles(&mut self, cost: u64)
+    {
+        self.machine_information.add_cycles(cost);
+
+        if self.mem_state.has_devices()
+        {
+            self.mem_state.tick_devices(self.machine_information.get_cycles());
+            self.flush_device_output();
+        }
+    }
+
+    /// adds the output of the devices (e.g. the console) to the output of the machine
+    pub(crate) fn flush_device_output(&mut self)
+    {
+        let output = self.mem_state.take_device_output();
+        if !output.is_empty()
+        {
+            self.machine_information.push_str(output);
+            // the console is interactive, its output can't wait for a newline
+            let _ = std::io::stdout().flush();
+        }
+    }
 }
@@ -1,19 +1,27 @@
-mod check_instruction;
-mod deserialization;
-mod exec;
-mod memory;
-mod syscall;
-mod type_cov_parse;
-mod output;
-mod load_bin;
-mod debug;
-mod machine;
-
 use std::collections::{HashSet,HashMap};
-use std::fs;
+use std::fs::{self,File};
+use std::io::{self,Read,Write};
+use std::time::{Instant,SystemTime,UNIX_EPOCH};
 
-use crate::load_bin::Binary;
-use crate::machine::{MachineState,MachineInitInfo};
+use j_interpreter::load_bin::{self, Binary};
+use j_interpreter::machine::{MachineState,MachineInitInfo};
+use j_interpreter::file_system::FileSystem;
+use j_interpreter::memory::MemLayout;
+use j_interpreter::dap::DapServer;
+use j_interpreter::gdb_stub::GdbStub;
+use j_interpreter::debug_script::DebugScript;
+use j_interpreter::snapshot;
+use j_interpreter::trace::{Tracer,TraceFormat};
+use j_interpreter::profile::Profiler;
+use j_interpreter::coverage::Coverage;
+use j_interpreter::cost::CostModel;
+use j_interpreter::decode_cache::DecodeCache;
+use j_interpreter::block_engine::Engine;
+use j_interpreter::lockstep::Lockstep;
+use j_interpreter::fuzz;
+use j_interpreter::golden;
+use j_interpreter::input::InputSource;
+use j_interpreter::device::{ConsoleDevice, TimerDevice, FramebufferDevice, CONSOLE_SIZE, TIMER_SIZE};
 
 use clap::Parser;
 
@@ -26,6 +34,34 @@ struct Args {
     #[clap(short, long, action)]
     debug: bool,
 
+    /// breakpoint at an adress or label, can be used multiple times.
+    /// The debugger stops at the first instruction if no breakpoint is given
+    #[clap(short='b', long="break", value_parser)]
+    breakpoints: Vec<String>,
+
+    /// debug with a Debug Adapter Protocol client over `stdio` or a local TCP port.
+    /// use a port if the programm reads from stdin or uses the console device
+    #[clap(long, value_parser)]
+    dap: Option<String>,
+
+    /// wait for gdb or lldb on this local TCP port (`target remote :PORT`)
+    #[clap(long, value_parser)]
+    gdb: Option<u16>,
+
+    /// run the debugger commands of this file, one command per line.
+    /// enables the debug mode
+    #[clap(long, value_parser)]
+    debug_script: Option<String>,
+
+    /// do not read commands from stdin after the debug script ended.
+    /// exits with an error if an `assert` of the script failed
+    #[clap(long, action, requires = "debug-script")]
+    batch: bool,
+
+    /// write the output of the batch mode to this file instead of stdout
+    #[clap(long, value_parser, requires = "batch")]
+    debug_log: Option<String>,
+
     /// wirite the machine output to a file
     #[clap(short='f',long="out", action)]
     output_to_file: bool,
@@ -34,14 +70,116 @@ struct Args {
     #[clap(short, long,  value_parser)]
     input_file: Option<String>,
 
+    /// continue from a snapshot that was written with `dump` in the debugger
+    /// instead of loading the binary. devices and open files are not restored
+    #[clap(long, value_parser)]
+    resume: Option<String>,
+
+    /// log every executed instruction to this file
+    #[clap(long, value_parser)]
+    trace: Option<String>,
+
+    /// format of the trace: text or json (one object per line)
+    #[clap(long, value_parser = TraceFormat::parse, default_value = "text")]
+    trace_format: TraceFormat,
+
+    /// only trace the instructions in START..END (adresses, labels or file:line), can be used multiple times
+    #[clap(long, value_parser, requires = "trace")]
+    trace_range: Vec<String>,
+
+    /// only trace the instructions from this label to the next label, can be used multiple times
+    #[clap(long, value_parser, requires = "trace")]
+    trace_label: Vec<String>,
+
+    /// count the executed instructions and cycles per function and write the report to this file
+    #[clap(long, value_parser)]
+    profile: Option<String>,
+
+    /// write the cycles per call stack to this file, for flamegraph.pl or inferno
+    #[clap(long, value_parser)]
+    profile_folded: Option<String>,
+
+    /// write the line and branch coverage in the lcov format to this file and print a summary.
+    /// Needs the line table of the assembler
+    #[clap(long, value_parser)]
+    coverage: Option<String>,
+
+    /// json file with the cycles per instruction, memory parameter and syscall.
+    /// Every instruction takes 1 cycle without it
+    #[clap(long, value_parser)]
+    cost_model: Option<String>,
+
+    /// decode every instruction again when it gets executed instead of using the decoded instructions.
+    /// Only useful to compare the speed
+    #[clap(long, action)]
+    no_decode_cache: bool,
+
+    /// interpreter or blocks (runs translated basic blocks, faster on loops).
+    /// The interpreter is always used with the debugger, tracing, profiling, coverage or history
+    #[clap(long, value_parser = Engine::parse, default_value = "interpreter")]
+    engine: Engine,
+
+    /// run the programm this many times without printing its output and print the time per run.
+    /// The input is read once from stdin and passed to every run
+    #[clap(long, value_parser)]
+    bench: Option<u32>,
+
+    /// run the programm with the selected engine and with the plain interpreter without decode cache
+    /// and compare them after every instruction (the block engine after every block). Prints the first divergence.
+    /// The input is read once from stdin and passed to both
+    #[clap(long, action)]
+    lockstep: bool,
+
+    /// assemble and run this many random programms, starting with the programm of --seed,
+    /// and stop at the first one that makes the assembler or the interpreter panic.
+    /// No binary is loaded
+    #[clap(long, value_parser)]
+    fuzz: Option<u64>,
+
+    /// assemble and run the test programm or all test programms in the directory and compare
+    /// them with the expectations in their comments (`; expect stdout: "42\n"`). No binary is loaded
+    #[clap(long, value_parser)]
+    test: Option<String>,
+
+    /// directory that is searched for the included files of the test programms.
+    /// Can be used multiple times
+    #[clap(long, value_parser)]
+    include_dir: Vec<String>,
+
     /// Name of the additional debug information file
     #[clap(long, value_parser)]
     debug_information: Option<String>,
 
-    /// size of the memory
-    #[clap(short, long, value_parser, default_value_t = 1024)]
+    /// amount of executed instructions that are recorded for
+    /// stepping backwards in the debugger
+    #[clap(long, value_parser)]
+    history: Option<usize>,
+
+    /// directory of the assembly source files. used by the debugger to show the source
+    #[clap(long, value_parser, default_value = ".")]
+    source_dir: String,
+
+    /// size of the memory (decimal or 0x hex).
+    /// memory is only allocated when it is used
+    #[clap(short, long, value_parser = parse_mem_size, default_value = "1024")]
     mem_size: u64,
 
+    /// highest adress of the stack. default: end of the memory
+    #[clap(long, value_parser = parse_adress)]
+    stack_top: Option<u64>,
+
+    /// maximum amount of values on the stack
+    #[clap(long, value_parser = parse_adress)]
+    stack_size: Option<u64>,
+
+    /// first adress of the heap. default: directly after the code
+    #[clap(long, value_parser = parse_adress)]
+    heap_base: Option<u64>,
+
+    /// highest adress of the heap. default: right below the stack
+    #[clap(long, value_parser = parse_adress)]
+    heap_limit: Option<u64>,
+
     /// maximum cycles the machine is allowed to execute
     #[clap(short, long,  value_parser, default_value_t = 10_000_000_000)]
     cycle_limit: u128,
@@ -49,50 +187,497 @@ struct Args {
     /// maximum cycles the machine is allowed to execute
     #[clap(short, long, action)]
     verbose: bool,
+
+    /// seed for the random number generator (`Rand` syscall).
+    /// use the same seed for reproducible runs
+    #[clap(long, value_parser)]
+    seed: Option<u64>,
+
+    /// directory the programm is allowed to access with the file syscalls.
+    /// file access is disabled if no directory is given
+    #[clap(long, value_parser)]
+    sandbox: Option<String>,
+
+    /// guard page as BASE:SIZE. every access to it traps like a NULL dereference
+    #[clap(long, value_parser = parse_guard)]
+    guard: Vec<(u64,u64)>,
+
+    /// map a character console to this adress (decimal or 0x hex)
+    #[clap(long, value_parser = parse_adress)]
+    console_at: Option<u64>,
+
+    /// map a cycle timer to this adress (decimal or 0x hex)
+    #[clap(long, value_parser = parse_adress)]
+    timer_at: Option<u64>,
+
+    /// map a text-mode framebuffer to this adress (decimal or 0x hex)
+    #[clap(long, value_parser = parse_adress)]
+    framebuffer_at: Option<u64>,
+
+    /// size of the framebuffer as WIDTHxHEIGHT
+    #[clap(long, value_parser = parse_framebuffer_size, default_value = "80x25")]
+    framebuffer_size: (u64,u64),
+
+    /// file the framebuffer gets written to when the machine stops
+    #[clap(long, value_parser)]
+    framebuffer_dump: Option<String>,
+}
+
+fn parse_adress(s: &str) -> Result<u64,String>
+{
+    match s.strip_prefix("0x")
+    {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }.map_err(|e| format!("invalid adress {}: {}", s, e))
+}
+
+fn parse_mem_size(s: &str) -> Result<u64,String>
+{
+    match parse_adress(s)?
+    {
+        0 => Err("the memory needs at least one value".into()),
+        size => Ok(size),
+    }
+}
+
+fn parse_guard(s: &str) -> Result<(u64,u64),String>
+{
+    let (base,size) = s.split_once(':').ok_or(format!("invalid guard page {}, expected BASE:SIZE", s))?;
+    Ok((parse_adress(base)?, parse_adress(size)?))
+}
+
+fn parse_framebuffer_size(s: &str) -> Result<(u64,u64),String>
+{
+    let (w,h) = s.split_once('x').ok_or(format!("invalid size {}, expected WIDTHxHEIGHT", s))?;
+    let w = w.parse::<u64>().map_err(|e| e.to_string())?;
+    let h = h.parse::<u64>().map_err(|e| e.to_string())?;
+
+    if w == 0 || h == 0
+    {
+        return Err("the framebuffer can not be empty".into())
+    }
+
+    Ok((w,h))
+}
+
+
+
+fn map_devices(machine: &mut MachineState, args: &Args) -> Result<(),String>
+{
+    if let Some(addr) = args.console_at
+    {
+        machine.mem_state.map_device(addr, CONSOLE_SIZE, Box::new(ConsoleDevice::new()))?;
+    }
+    if let Some(addr) = args.timer_at
+    {
+        machine.mem_state.map_device(addr, TIMER_SIZE, Box::new(TimerDevice::new()))?;
+    }
+    if let Some(addr) = args.framebuffer_at
+    {
+        let (w,h) = args.framebuffer_size;
+        let fb = FramebufferDevice::new(w, h, args.framebuffer_dump.clone().map(|f| f.into()))?;
+        machine.mem_state.map_device(addr, fb.size(), Box::new(fb))?;
+    }
+    Ok(())
+}
+
+fn init_tracer(machine: &mut MachineState, file: &str, args: &Args) -> Result<(),String>
+{
+    let out = File::create(file).map_err(|e| format!("could not create {}: {}", file, e))?;
+    let mut tracer = Tracer::new(Box::new(io::BufWriter::new(out)), args.trace_format);
+
+    for range in &args.trace_range
+    {
+        let (start,end) = range.split_once("..").ok_or(format!("invalid range {}, expected START..END", range))?;
+        tracer.add_range(machine.debug.resolve(start)?, machine.debug.resolve(end)?);
+    }
+
+    for label in &args.trace_label
+    {
+        let (start,end) = machine.debug.label_range(label)?;
+        tracer.add_range(start, end);
+    }
+
+    machine.set_tracer(tracer);
+    Ok(())
 }
 
+/// the binary, cost model and input of a machine without debugging and output
+struct Headless
+{
+    binary: Binary,
+    cost_model: CostModel,
+    input: Vec<u8>,
+}
+
+impl Headless
+{
+    /// reads in.bin, the cost model and the whole input from stdin
+    fn load(args: &Args) -> Result<Self,String>
+    {
+        let mut binary = Binary::new();
+        binary.load_file("in.bin".to_string())?;
+
+        let cost_model = match &args.cost_model
+        {
+            Some(file) => CostModel::load(file)?,
+            None => CostModel::default(),
+        };
 
+        let mut input = vec![];
+        io::stdin().read_to_end(&mut input).map_err(|e| format!("could not read the input: {}", e))?;
+
+        Ok(Self{binary, cost_model, input})
+    }
+
+    /// a machine with the binary loaded that buffers its output
+    fn machine(&self, args: &Args, init: MachineInitInfo) -> Result<MachineState,String>
+    {
+        let mut machine = MachineState::init(MachineInitInfo{
+            max_cycles: args.cycle_limit,
+            layout: MemLayout{
+                mem_size: args.mem_size,
+                stack_top: args.stack_top,
+                stack_size: args.stack_size,
+                heap_base: args.heap_base,
+                heap_limit: args.heap_limit,
+            },
+            ..init
+        });
+        machine.decode_cache = DecodeCache::new(!args.no_decode_cache);
+        machine.block_engine = args.engine.block_engine();
+        machine.cost_model = self.cost_model.clone();
+        machine.machine_information.set_input(InputSource::from_bytes(&self.input));
+        machine.machine_information.buffer_output();
+
+        machine.laod_into_state(self.binary.clone())?;
+        Ok(machine)
+    }
+}
+
+fn bench(args: &Args, runs: u32) -> Result<(),String>
+{
+    let headless = Headless::load(args)?;
+
+    let mut cycles = 0;
+    let now = Instant::now();
+
+    for _ in 0..runs.max(1)
+    {
+        let mut machine = headless.machine(args, MachineInitInfo{seed: args.seed, ..Default::default()})?;
+        machine.run()?;
+        cycles = machine.machine_information.get_cycles();
+    }
+
+    let per_run = now.elapsed().as_secs_f64() / runs.max(1) as f64;
+    println!("runs: {}, cycles per run: {}, time per run: {:.3} ms, {:.2} million cycles per second",
+        runs.max(1), cycles, per_run * 1000.0, cycles as f64 / per_run / 1_000_000.0);
+    Ok(())
+}
+
+/// runs the programm with the configuration of the arguments and with the plain
+/// interpreter side by side and stops at the first instruction where they differ
+fn lockstep(args: &Args) -> Result<(),String>
+{
+    let headless = Headless::load(args)?;
+
+    // both machines need the same random numbers
+    let seed = args.seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64));
+    let init = || MachineInitInfo{
+        seed: Some(seed),
+        symbols: load_bin::load_symbols(),
+        line_table: load_bin::load_line_table(args.source_dir.clone().into()),
+        ..Default::default()
+    };
+
+    let mut reference = headless.machine(args, init())?;
+    reference.decode_cache = DecodeCache::new(false);
+    reference.block_engine = None;
+
+    let machine = headless.machine(args, init())?;
+    let name = match (args.engine, args.no_decode_cache)
+    {
+        (Engine::Blocks, _) => "blocks",
+        (Engine::Interpreter, false) => "decode cache",
+        (Engine::Interpreter, true) => "interpreter",
+    };
+
+    let end = Lockstep::new(("reference", reference), (name, machine))?.run()?;
+    match end.error
+    {
+        Some(error) => println!("lockstep: no divergence in {} instructions, both stopped with: {}", end.instructions, error),
+        None => println!("lockstep: no divergence in {} instructions", end.instructions),
+    }
+    Ok(())
+}
+
+/// runs the random programms of the fuzzer and prints the report of the first failed check
+fn fuzz(args: &Args, runs: u64) -> Result<(),String>
+{
+    let first = args.seed.unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64));
+    let mut ended = 0;
+
+    for seed in (0..runs).map(|ii| first.wrapping_add(ii))
+    {
+        fuzz::fuzz_assembler(seed)?;
+        if fuzz::fuzz_programm(seed)?.is_none()
+        {
+            ended += 1;
+        }
+    }
+    println!("fuzz: {} programms from seed {}, {} ended, {} stopped with an error", runs, first, ended, runs - ended);
+    Ok(())
+}
 
 fn main() {
     let args = Args::parse();
 
-    // add breakpoints form file
-    let mut breakpoints = HashSet::new(); 
-    
-    // TODO: remove
-    
-    breakpoints.insert(1);
-    //breakpoints.insert(2);
-    //breakpoints.insert(3);
-    //breakpoints.insert(5);
-    //breakpoints.insert(6);
-    //breakpoints.insert(7);
-    //breakpoints.insert(8);
-    //breakpoints.insert(9);
-    //breakpoints.insert(10);
+    if let Some(path) = &args.test
+    {
+        let include_dirs:Vec<std::path::PathBuf> = args.include_dir.iter().map(|dir| dir.into()).collect();
+        match golden::run_tests(path.as_ref(), &include_dirs, &mut io::stdout())
+        {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(x) =>
+            {
+                eprintln!("{}", x);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(runs) = args.fuzz
+    {
+        if let Err(x) = fuzz(&args, runs)
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+        return
+    }
+
+    if args.lockstep
+    {
+        if let Err(x) = lockstep(&args)
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+        return
+    }
+
+    if let Some(runs) = args.bench
+    {
+        if let Err(x) = bench(&args, runs)
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+        return
+    }
 
     let symbols = load_bin::load_symbols();
 
     let init = MachineInitInfo{
             max_cycles: args.cycle_limit,
-            mem_size: args.mem_size,
-            debug_mode: Some(breakpoints), //if args.debug {Some(breakpoints)} else {None},
+            layout: MemLayout{
+                mem_size: args.mem_size,
+                stack_top: args.stack_top,
+                stack_size: args.stack_size,
+                heap_base: args.heap_base,
+                heap_limit: args.heap_limit,
+            },
+            debug_mode: if args.debug {Some(HashSet::new())} else {None},
             write_to_file: args.output_to_file,
-            symbols
+            symbols,
+            line_table: load_bin::load_line_table(args.source_dir.clone().into()),
+            history_size: args.history,
+            seed: args.seed,
+            file_system: match args.sandbox.clone()
+            {
+                Some(dir) => FileSystem::Sandbox(dir.into()),
+                None => FileSystem::None,
+            },
         };
     
-    let mut b = Binary::new();
-    b.load_file("in.bin".to_string());
-
     // init machine in debug mode by providing Some(breakpoints)
     let mut machine = MachineState::init(init);
-    
+    machine.decode_cache = DecodeCache::new(!args.no_decode_cache);
+    machine.block_engine = args.engine.block_engine();
+
+    if let Some(file) = &args.resume
+    {
+        if let Err(x) = snapshot::load_file(&mut machine, file)
+        {
+            eprintln!("could not resume: {}", x);
+            std::process::exit(1);
+        }
+    }
+    else
+    {
+        let mut b = Binary::new();
+
+        if let Err(x) = b.load_file("in.bin".to_string()).and_then(|_| machine.laod_into_state(b))
+        {
+            eprintln!("could not load the binary: {}", x);
+            std::process::exit(1);
+        }
+    }
+
+    // breakpoints are resolved after loading, so labels can be used
+    if args.debug || args.debug_script.is_some()
+    {
+        for loc in &args.breakpoints
+        {
+            match machine.debug.resolve(loc)
+            {
+                Ok(addr) => {machine.debug.debug_mode.get_or_insert_with(HashSet::new).insert(addr);},
+                Err(x) => {eprintln!("invalid breakpoint: {}", x); std::process::exit(1)},
+            }
+        }
+
+        // without breakpoints the debugger stops before the first instruction
+        if args.breakpoints.is_empty()
+        {
+            machine.debug.debug_mode.get_or_insert_with(HashSet::new).insert(machine.next_ptr);
+        }
+    }
 
+    if let Some(file) = &args.cost_model
+    {
+        match CostModel::load(file)
+        {
+            Ok(model) => machine.cost_model = model,
+            Err(x) => {eprintln!("{}", x); std::process::exit(1)},
+        }
+    }
 
-    machine.laod_into_state(b);
+    if let Some(file) = &args.trace
+    {
+        if let Err(x) = init_tracer(&mut machine, file, &args)
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+    }
+
+    for (base,size) in &args.guard
+    {
+        if let Err(x) = machine.mem_state.add_guard(*base, *size)
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(x) = map_devices(&mut machine, &args)
+    {
+        eprintln!("{}", x);
+        std::process::exit(1);
+    }
+
+    if let Some(dap) = args.dap
+    {
+        let server = match dap.as_str()
+        {
+            "stdio" => Ok(DapServer::stdio()),
+            port => port.parse().map_err(|_| format!("invalid DAP port: {}", port)).and_then(DapServer::tcp),
+        };
+
+        if let Err(x) = server.and_then(|server| server.run(&mut machine))
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+        return
+    }
+
+    if let Some(port) = args.gdb
+    {
+        if let Err(x) = GdbStub::tcp(port).and_then(|stub| stub.run(&mut machine))
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+        return
+    }
+
+    if let Some(file) = args.debug_script
+    {
+        let log: Box<dyn Write> = match &args.debug_log
+        {
+            Some(log) => match File::create(log)
+            {
+                Ok(f) => Box::new(f),
+                Err(x) => {eprintln!("could not create {}: {}", log, x); std::process::exit(1)},
+            },
+            None => Box::new(io::stdout()),
+        };
+
+        let script = match DebugScript::load(&file, args.batch, log)
+        {
+            Ok(script) => script,
+            Err(x) => {eprintln!("{}", x); std::process::exit(1)},
+        };
+
+        if !args.batch
+        {
+            script.attach(&mut machine);
+        }
+        else if let Err(x) = script.run_batch(&mut machine)
+        {
+            eprintln!("{}", x);
+            std::process::exit(1);
+        }
+        else
+        {
+            return
+        }
+    }
+
+    if args.profile.is_some() || args.profile_folded.is_some()
+    {
+        machine.profiler = Some(Profiler::new());
+    }
+
+    if args.coverage.is_some()
+    {
+        if machine.debug.lines.is_none()
+        {
+            eprintln!("coverage needs the line table (lines.dbg) of the assembler");
+            std::process::exit(1);
+        }
+        machine.coverage = Some(Coverage::new());
+    }
 
     machine.run_program();
 
+    if let (Some(coverage),Some(lines),Some(file)) = (&machine.coverage, &machine.debug.lines, &args.coverage)
+    {
+        if let Err(x) = fs::write(file, coverage.lcov(lines, &machine.mem_state))
+        {
+            eprintln!("could not write {}: {}", file, x);
+        }
+        eprint!("{}", coverage.summary(lines, &machine.mem_state));
+    }
+
+    if let Some(profiler) = &machine.profiler
+    {
+        let files = [(&args.profile, profiler.report(&machine.debug)), (&args.profile_folded, profiler.folded(&machine.debug))];
+        for (file,content) in files
+        {
+            if let Some(file) = file
+            {
+                if let Err(x) = fs::write(file, content)
+                {
+                    eprintln!("could not write {}: {}", file, x);
+                }
+            }
+        }
+    }
+
     //println!("Hello, world!");
 }
 
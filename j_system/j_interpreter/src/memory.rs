@@ -1,4 +1,12 @@
 use std::cmp::Ordering;
+use std::cell::RefCell;
+use std::collections::{HashMap,HashSet};
+use std::hash::{BuildHasherDefault,Hasher};
+
+use crate::device::Device;
+use crate::snapshot::get_u64;
+
+use serde_json::{json,Value};
 
 /// The whole memory that is visible to the VM
 
@@ -23,44 +31,165 @@ use std::cmp::Ordering;
 
 const RWX_ROM: bool = true;
 
-struct Mem(Vec<u64>);
+/// amount of values per page (as power of 2)
+const PAGE_BITS: u32 = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+/// the maximum amount of values `mem_copy` and `mem_fill` write at once
+pub const MAX_COPY_SIZE: u64 = 1 << 24;
+
+/// amount of values `mem_copy` buffers at once
+const COPY_CHUNK_SIZE: usize = PAGE_SIZE;
+
+/// sparse memory. Pages only get allocated when a value
+/// other than 0 is written to them, so large adress spaces 
+/// do not have to be allocated up front.
+struct Mem
+{
+    size: u64,
+    pages: HashMap<u64,Box<[u64]>,BuildHasherDefault<PageHasher>>,
+}
+
+/// hashes the page numbers for every memory access.
+/// The default hasher is resistant against collision attacks,
+/// which is not needed for page numbers and much slower
+#[derive(Default)]
+struct PageHasher(u64);
+
+impl Hasher for PageHasher
+{
+    fn finish(&self) -> u64
+    {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8])
+    {
+        bytes.iter().for_each(|b| self.write_u64(*b as u64));
+    }
+
+    fn write_u64(&mut self, n: u64)
+    {
+        // fibonacci hashing spreads consecutive pages over the buckets
+        self.0 = (self.0.rotate_left(5) ^ n).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    }
+}
 
 impl Mem {
-    pub fn new(size: usize) -> Self
+    pub fn new(size: u64) -> Self
     {
-        let mut mem = Vec::with_capacity(size);
-        // pre-init memory
-        (0..size).for_each(|_| mem.push(0));
-        Self{0:mem}
+        Self{size, pages: HashMap::default()}
     }
 
     pub fn store(&mut self, adress: u64, value: u64) -> Result<(),String>
     {
-        if adress >= self.0.len() as u64
+        if adress >= self.size
         {
             return Err("this adress is outside of the adress space".into())
         }
 
-        self.0[adress as usize] = value;
+        let offset = (adress as usize) & (PAGE_SIZE-1);
+
+        match self.pages.get_mut(&(adress >> PAGE_BITS))
+        {
+            Some(page) => page[offset] = value,
+            // unallocated pages are already 0
+            None if value == 0 => (),
+            None =>
+            {
+                let mut page = vec![0; PAGE_SIZE].into_boxed_slice();
+                page[offset] = value;
+                self.pages.insert(adress >> PAGE_BITS, page);
+            },
+        }
 
         Ok(())
     }
 
     pub fn read(&self, adress: u64) -> Option<u64>
     {
-        if adress >= self.0.len() as u64
+        if adress >= self.size
         {
             return None
         }
 
-        Some(self.0[adress as usize])
+        let offset = (adress as usize) & (PAGE_SIZE-1);
+        Some(self.pages.get(&(adress >> PAGE_BITS)).map(|page| page[offset]).unwrap_or(0))
+    }
+
+    pub fn allocated_pages(&self) -> usize
+    {
+        self.pages.len()
+    }
+
+    /// the allocated pages sorted by their number
+    fn sorted_pages(&self) -> Vec<(u64,&[u64])>
+    {
+        let mut pages:Vec<_> = self.pages.iter().map(|(nr,page)| (*nr, &page[..])).collect();
+        pages.sort_by_key(|(nr,_)| *nr);
+        pages
     }
 }
 
+/// where heap and stack are placed in the adress space.
+/// Rom and code are placed at the bases the assembler relocated them to.
+/// Everything that is `None` is derived from the binary when it gets loaded.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct MemLayout
+{
+    /// amount of adressable values, adresses go from 0 to `mem_size-1`
+    pub mem_size: u64,
+
+    /// highest adress of the stack, the stack grows down from here.
+    /// default: `mem_size-1`
+    pub stack_top: Option<u64>,
+
+    /// maximum amount of values on the stack.
+    /// default: the stack can grow until it reaches the code
+    pub stack_size: Option<u64>,
+
+    /// first adress that can be used by the heap.
+    /// default: directly after the code
+    pub heap_base: Option<u64>,
+
+    /// highest adress that can be used by the heap.
+    /// default: right below the stack
+    pub heap_limit: Option<u64>,
+}
+
+impl MemLayout
+{
+    pub fn new(mem_size: u64) -> Self
+    {
+        Self{mem_size, stack_top: None, stack_size: None, heap_base: None, heap_limit: None}
+    }
+}
+
+impl Default for MemLayout
+{
+    fn default() -> Self
+    {
+        Self::new(1024)
+    }
+}
+
+/// a device that is mapped to the adresses `base..base+size`
+struct MappedDevice
+{
+    base: u64,
+    size: u64,
+    // the devices can change their state on reads (e.g. reading input)
+    device: RefCell<Box<dyn Device>>,
+}
+
 pub struct MemModel
 {
     mem: Mem,
 
+    /// memory mapped devices. Accesses to their adresses 
+    /// are passed to the device instead of the memory
+    devices: Vec<MappedDevice>,
+
     /// holds a list sorted all allocations that were made through `malloc`
     ///  
     alloc_tabel: AllocationTable,
@@ -70,7 +199,6 @@ pub struct MemModel
     heap_cutoff: u64,
 
     /// rom base pointer
-    // TODO: should always be 1, right?
     rom_base_ptr: u64,
     rom_size: u64,
 
@@ -82,6 +210,34 @@ pub struct MemModel
     /// alocate combined. this includes rom, code,
     /// heap and stack
     mem_size:u64,
+
+    layout: MemLayout,
+
+    /// highest adress of the stack
+    stack_top: u64,
+    /// lowest adress the stack is allowed to use
+    stack_limit: u64,
+
+    /// first adress of the heap
+    heap_base: u64,
+    /// highest adress the heap is allowed to use
+    heap_limit: u64,
+
+    /// adress ranges `(base,size)` that trap on every access.
+    /// Adress 0 (NULL) always traps
+    guards: Vec<(u64,u64)>,
+
+    /// adresses the debugger watches for writes
+    watchpoints: HashSet<u64>,
+    /// writes to watched adresses since the last `take_watch_hits`: (adress, old value, new value)
+    watch_hits: Vec<(u64,u64,u64)>,
+
+    /// is `Some` while all writes are recorded for the journal: (adress, old value, new value)
+    recorded_writes: Option<Vec<(u64,u64,u64)>>,
+
+    /// writes to the code section since the last `take_code_writes`.
+    /// The decoded instructions at these adresses are outdated
+    code_writes: Vec<u64>,
 }
 
 impl MemModel
@@ -89,35 +245,195 @@ impl MemModel
 
     pub fn new(mem_size: u64) -> Self
     {
+        Self::with_layout(MemLayout::new(mem_size))
+    }
+
+    /// the layout gets checked when the binary is loaded with `prepare_mem`
+    pub fn with_layout(layout: MemLayout) -> Self
+    {
+        let mem_size = layout.mem_size;
+        let stack_top = layout.stack_top.unwrap_or(mem_size.saturating_sub(1));
+
         MemModel{ 
-            mem: Mem::new(mem_size as usize),
+            mem: Mem::new(mem_size),
+            devices: vec![],
             alloc_tabel: AllocationTable::new(),
-            heap_cutoff: mem_size -1,
+            heap_cutoff: stack_top,
             rom_base_ptr: 1,
             rom_size: 0,
             code_base_ptr: 0,
             code_size: 0,
             mem_size,
+            layout,
+            stack_top,
+            stack_limit: 1,
+            heap_base: 1,
+            heap_limit: stack_top,
+            guards: vec![],
+            watchpoints: HashSet::new(),
+            watch_hits: vec![],
+            recorded_writes: None,
+            code_writes: vec![],
         } 
+    }
 
+    /// inserts rom and code into the memory modell, the rom at 0 and the code 
+    /// directly after it, and places heap and stack according to the layout
+    pub fn prepare_mem(&mut self,rom: Vec<u64>,code:Vec<u64>) -> Result<(),String>
+    {
+        let code_base = rom.len() as u64;
+        self.prepare_sections(0, rom, code_base, code)
     }
 
-    /// inserts rom and code into the memory modell
-    pub fn prepare_mem(&mut self,rom: Vec<u64>,code:Vec<u64>) 
+    /// inserts rom and code at their base adresses and places heap and stack
+    /// according to the layout. Heap and stack are placed behind the section that ends last
+    pub fn prepare_sections(&mut self, rom_base: u64, rom: Vec<u64>, code_base: u64, code: Vec<u64>) -> Result<(),String>
     {
+        if self.mem_size == 0
+        {
+            return Err("the memory needs at least one value".into());
+        }
+
         // make sure programm and constants fit in memory
-        if !(rom.len() + code.len() <= self.mem_size as usize)
+        let section_end = |name: &str, base: u64, len: usize| base.checked_add(len as u64).filter(|end| *end <= self.mem_size)
+            .ok_or(format!("not enough memory: the {} needs {} values at {} but the memory only has {}", name, len, base, self.mem_size));
+        let rom_end = section_end("rom", rom_base, rom.len())?;
+        let code_end = section_end("code", code_base, code.len())?;
+
+        if !rom.is_empty() && !code.is_empty() && rom_base < code_end && code_base < rom_end
         {
-            panic!("not enough memory");
+            return Err(format!("the rom ({}..{}) and the code ({}..{}) overlap", rom_base, rom_end, code_base, code_end));
         }
 
-        self.rom_base_ptr = 0;
-        self.code_base_ptr= self.rom_base_ptr+ rom.len() as u64;
+        let program_end = rom_end.max(code_end);
+        let MemLayout{stack_top, stack_size, heap_base, heap_limit, ..} = self.layout;
+
+        let stack_top = stack_top.unwrap_or(self.mem_size-1);
+        if stack_top >= self.mem_size
+        {
+            return Err(format!("the stack top {} is outside of the memory", stack_top));
+        }
+
+        let stack_limit = match stack_size
+        {
+            Some(size) => (stack_top+1).checked_sub(size).filter(|limit| *limit >= program_end)
+                .ok_or(format!("a stack of size {} does not fit below {}", size, stack_top))?,
+            None => program_end.max(1),
+        };
+
+        let heap_base = heap_base.unwrap_or(program_end);
+        if heap_base < program_end
+        {
+            return Err(format!("the heap base {} is inside of the programm (ends at {})", heap_base, program_end));
+        }
+
+        // without a stack size the heap is limited by the stack at runtime
+        let heap_limit = heap_limit.unwrap_or(if stack_size.is_some() {stack_limit-1} else {stack_top});
+        if heap_limit < heap_base || (stack_size.is_some() && heap_limit >= stack_limit)
+        {
+            return Err(format!("invalid heap limit {}", heap_limit));
+        }
+
+        self.rom_base_ptr = rom_base;
+        self.rom_size = rom.len() as u64;
+        self.code_base_ptr = code_base;
         self.code_size = code.len() as u64;
 
-        rom.iter().enumerate().for_each(|(ii,val)| self.mem.store(self.rom_base_ptr + ii as u64, *val).unwrap());
-        code.iter().enumerate().for_each(|(ii,val)| self.mem.store(self.code_base_ptr + ii as u64, *val).unwrap());
+        self.stack_top = stack_top;
+        self.stack_limit = stack_limit;
+        self.heap_base = heap_base;
+        self.heap_limit = heap_limit;
+        self.heap_cutoff = heap_limit;
 
+        rom.iter().enumerate().try_for_each(|(ii,val)| self.mem.store(self.rom_base_ptr + ii as u64, *val))?;
+        code.iter().enumerate().try_for_each(|(ii,val)| self.mem.store(self.code_base_ptr + ii as u64, *val))?;
+
+        Ok(())
+    }
+
+    /// memory, layout and allocations for a snapshot.
+    /// The devices are not part of the snapshot
+    pub(crate) fn save_state(&self) -> Value
+    {
+        json!({
+            "mem_size": self.mem_size,
+            "rom_base": self.rom_base_ptr,
+            "rom_size": self.rom_size,
+            "code_base": self.code_base_ptr,
+            "code_size": self.code_size,
+            "stack_top": self.stack_top,
+            "stack_limit": self.stack_limit,
+            "heap_base": self.heap_base,
+            "heap_limit": self.heap_limit,
+            "heap_cutoff": self.heap_cutoff,
+            "guards": self.guards,
+            "allocations": self.alloc_tabel.elem.iter().map(|a| [a.ptr, a.size]).collect::<Vec<_>>(),
+            "pages": self.mem.sorted_pages(),
+        })
+    }
+
+    /// replaces memory, layout and allocations with the ones of a snapshot
+    pub(crate) fn load_state(&mut self, state: &Value) -> Result<(),String>
+    {
+        let pairs = |name: &str| -> Result<Vec<(u64,u64)>,String>
+        {
+            state[name].as_array().ok_or(format!("{} is missing", name))?.iter()
+                .map(|pair| Some((pair[0].as_u64()?, pair[1].as_u64()?)))
+                .collect::<Option<_>>().ok_or(format!("invalid {}", name))
+        };
+
+        let mem_size = get_u64(state, "mem_size")?;
+        let mut mem = Mem::new(mem_size);
+
+        for page in state["pages"].as_array().ok_or("pages are missing")?
+        {
+            let nr = page[0].as_u64().ok_or("invalid page number")?;
+            let values = page[1].as_array().filter(|values| values.len() == PAGE_SIZE).ok_or(format!("invalid page {}", nr))?;
+
+            for (ii,val) in values.iter().enumerate()
+            {
+                mem.store((nr << PAGE_BITS) + ii as u64, val.as_u64().ok_or(format!("invalid value in page {}", nr))?)?;
+            }
+        }
+
+        self.mem = mem;
+        self.mem_size = mem_size;
+        // snapshots of older versions have no bases, their rom starts at 0 and the code directly after it
+        self.rom_base_ptr = state["rom_base"].as_u64().unwrap_or(0);
+        self.rom_size = get_u64(state, "rom_size")?;
+        self.code_base_ptr = state["code_base"].as_u64().unwrap_or(self.rom_base_ptr + self.rom_size);
+        self.code_size = get_u64(state, "code_size")?;
+        self.stack_top = get_u64(state, "stack_top")?;
+        self.stack_limit = get_u64(state, "stack_limit")?;
+        self.heap_base = get_u64(state, "heap_base")?;
+        self.heap_limit = get_u64(state, "heap_limit")?;
+        self.heap_cutoff = get_u64(state, "heap_cutoff")?;
+        self.guards = pairs("guards")?;
+        self.alloc_tabel = AllocationTable{elem: pairs("allocations")?.into_iter().map(|(ptr,size)| Allocation{ptr, size}).collect()};
+        self.layout = MemLayout{mem_size, stack_top: Some(self.stack_top), stack_size: None, 
+                                heap_base: Some(self.heap_base), heap_limit: Some(self.heap_limit)};
+        Ok(())
+    }
+
+    pub fn get_stack_top(&self) -> u64
+    {
+        self.stack_top
+    }
+
+    pub fn get_stack_limit(&self) -> u64
+    {
+        self.stack_limit
+    }
+
+    pub fn get_heap_base(&self) -> u64
+    {
+        self.heap_base
+    }
+
+    /// amount of pages that are actually allocated
+    pub fn get_allocated_pages(&self) -> usize
+    {
+        self.mem.allocated_pages()
     }
 
     pub fn get_heap_cutoff(&self) -> u64
@@ -125,9 +441,10 @@ impl MemModel
         self.heap_cutoff
     }
 
+    /// the heap cutoff never exceeds the heap limit of the layout
     pub fn set_heap_cutoff(&mut self, val: u64)
     {
-        self.heap_cutoff = val
+        self.heap_cutoff = val.min(self.heap_limit)
     }
 
     pub fn get_mem_size(&self) -> u64
@@ -135,13 +452,328 @@ impl MemModel
         self.mem_size
     }
 
+    pub fn get_rom_base_ptr(&self) -> u64
+    {
+        self.rom_base_ptr
+    }
+
+    pub fn get_rom_size(&self) -> u64
+    {
+        self.rom_size
+    }
+
+    pub fn get_code_base_ptr(&self) -> u64
+    {
+        self.code_base_ptr
+    }
+
+    pub fn get_code_size(&self) -> u64
+    {
+        self.code_size
+    }
+
+    /// checks that the range `[addr, addr+size)` is not NULL, lies completely
+    /// inside of the adress space and does not touch a guard page
+    fn check_range(&self, addr: u64, size: u64) -> Result<(),String>
+    {
+        if addr == 0
+        {
+            return Err("tried to deref NULL!".into())
+        }
+
+        let end = match addr.checked_add(size)
+        {
+            Some(end) if end <= self.mem_size => end,
+            _ => return Err(format!("range {}..{}+{} is not in the adressspace", addr, addr, size)),
+        };
+
+        match self.guards.iter().find(|(base,size)| *base < end && addr < base + size)
+        {
+            Some((base,size)) => Err(format!("range {}..{} touches the guard page {}..{}", addr, end, base, base+size)),
+            None => Ok(()),
+        }
+    }
+
+    /// copies `size` values from `src` to `dest`.
+    /// The ranges are allowed to overlap.
+    pub fn mem_copy(&mut self, dest: u64, src: u64, size: u64) -> Result<(),String>
+    {
+        if size > MAX_COPY_SIZE
+        {
+            return Err(format!("mem_copy can copy at most {} values at once, not {}", MAX_COPY_SIZE, size))
+        }
+
+        self.check_range(src, size)?;
+        self.check_range(dest, size)?;
+
+        // copy from the back if the destination starts inside of the source,
+        // so every value is read before it gets overwritten
+        let backwards = dest > src && dest < src + size;
+        let chunks = size.div_ceil(COPY_CHUNK_SIZE as u64);
+
+        for nr in 0..chunks
+        {
+            let offset = if backwards {chunks - 1 - nr} else {nr} * COPY_CHUNK_SIZE as u64;
+            let len = (size - offset).min(COPY_CHUNK_SIZE as u64);
+            let chunk = (src+offset..src+offset+len).map(|addr| self.read(addr)).collect::<Result<Vec<_>,_>>()?;
+
+            for (ii,val) in chunk.into_iter().enumerate()
+            {
+                self.store(val, dest + offset + ii as u64)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// sets `size` values starting at `dest` to `val`.
+    /// Unallocated pages already hold 0, so large ranges are cleared
+    /// by only writing to the allocated pages and the devices
+    pub fn mem_fill(&mut self, dest: u64, val: u64, size: u64) -> Result<(),String>
+    {
+        self.check_range(dest, size)?;
+        let end = dest + size;
+
+        let ranges = match val
+        {
+            0 if size > MAX_COPY_SIZE => self.used_ranges(dest, end),
+            _ => vec![(dest,end)],
+        };
+
+        let values:u64 = ranges.iter().map(|(start,end)| end - start).sum();
+        if values > MAX_COPY_SIZE
+        {
+            return Err(format!("mem_fill can write at most {} values at once, not {}", MAX_COPY_SIZE, values))
+        }
+
+        ranges.into_iter().try_for_each(|(start,end)| (start..end).try_for_each(|addr| self.store(val, addr)))
+    }
+
+    /// the parts of `start..end` that are in allocated pages or devices, sorted and without overlaps
+    fn used_ranges(&self, start: u64, end: u64) -> Vec<(u64,u64)>
+    {
+        let pages = self.mem.pages.keys().map(|nr| (nr << PAGE_BITS, (nr << PAGE_BITS).saturating_add(PAGE_SIZE as u64)));
+        let devices = self.devices.iter().map(|d| (d.base, d.base + d.size));
+
+        let mut ranges:Vec<(u64,u64)> = pages.chain(devices)
+            .map(|(base,limit)| (base.max(start), limit.min(end)))
+            .filter(|(base,limit)| base < limit)
+            .collect();
+        ranges.sort_unstable();
+
+        let mut merged:Vec<(u64,u64)> = vec![];
+        for (base,limit) in ranges
+        {
+            match merged.last_mut()
+            {
+                Some(last) if base <= last.1 => last.1 = last.1.max(limit),
+                _ => merged.push((base,limit)),
+            }
+        }
+        merged
+    }
+
+    /// maps a device to the adresses `base..base+size`.
+    /// Devices can be mapped outside of the memory and take
+    /// precedence over the memory if they are mapped inside of it.
+    pub fn map_device(&mut self, base: u64, size: u64, device: Box<dyn Device>) -> Result<(),String>
+    {
+        if base == 0 || size == 0
+        {
+            return Err("a device can not be mapped to NULL or with a size of 0".into())
+        }
+
+        let end = base.checked_add(size).ok_or("device mapping is outside of the adressspace")?;
+
+        // devices are placed behind the programm like heap and stack
+        let program_end = (self.rom_base_ptr + self.rom_size).max(self.code_base_ptr + self.code_size);
+        if base < program_end
+        {
+            return Err(format!("device mapping {}..{} overlaps with the programm", base, end))
+        }
+
+        if self.devices.iter().any(|d| base < d.base + d.size && d.base < end)
+        {
+            return Err(format!("device mapping {}..{} overlaps with another device", base, end))
+        }
+
+        self.devices.push(MappedDevice{base, size, device: RefCell::new(device)});
+        Ok(())
+    }
+
+    /// adds a guard page. Every access to `base..base+size` traps
+    pub fn add_guard(&mut self, base: u64, size: u64) -> Result<(),String>
+    {
+        if size == 0 || base.checked_add(size).is_none()
+        {
+            return Err(format!("invalid guard page {}..{}+{}", base, base, size))
+        }
+
+        if self.guards.iter().any(|(b,s)| base < b + s && *b < base + size)
+        {
+            return Err(format!("guard page {}..{} overlaps with another guard page", base, base + size))
+        }
+
+        self.guards.push((base,size));
+        Ok(())
+    }
+
+    /// checks that the adress is neither NULL nor inside of a guard page
+    pub fn check_guard(&self, addr: u64) -> Result<(),String>
+    {
+        if addr == 0
+        {
+            return Err("null pointer dereference".into())
+        }
+
+        match self.guards.iter().find(|(base,size)| *base <= addr && addr < base + size)
+        {
+            Some((base,size)) => Err(format!("access to guard page {}..{}", base, base+size)),
+            None => Ok(()),
+        }
+    }
+
+    /// every write to a watched adress gets recorded (see `take_watch_hits`)
+    pub fn watch(&mut self, addr: u64)
+    {
+        self.watchpoints.insert(addr);
+    }
+
+    /// returns true if the adress was watched
+    pub fn unwatch(&mut self, addr: u64) -> bool
+    {
+        self.watchpoints.remove(&addr)
+    }
+
+    pub fn get_watchpoints(&self) -> &HashSet<u64>
+    {
+        &self.watchpoints
+    }
+
+    /// returns and clears all writes to watched adresses: (adress, old value, new value)
+    pub fn take_watch_hits(&mut self) -> Vec<(u64,u64,u64)>
+    {
+        std::mem::take(&mut self.watch_hits)
+    }
+
+    /// starts or stops recording all writes to the memory
+    pub fn record_writes(&mut self, record: bool)
+    {
+        self.recorded_writes = if record {Some(vec![])} else {None};
+    }
+
+    /// returns and clears the recorded writes: (adress, old value, new value)
+    pub fn take_recorded_writes(&mut self) -> Vec<(u64,u64,u64)>
+    {
+        self.recorded_writes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// writes a value back without recording it or triggering watchpoints.
+    /// Used to revert writes
+    pub fn restore(&mut self, addr: u64, val: u64) -> Result<(),String>
+    {
+        self.note_code_write(addr);
+        self.mem.store(addr, val)
+    }
+
+    /// checks that the debugger is allowed to change the value at the adress
+    pub fn check_debug_store(&self, addr: u64) -> Result<(),String>
+    {
+        self.check_guard(addr)?;
+
+        if self.find_device(addr).is_some()
+        {
+            return Err(format!("adress {} belongs to a device", addr))
+        }
+
+        match addr < self.mem_size
+        {
+            true => Ok(()),
+            false => Err(format!("adress is not in the adressspace: {}", addr)),
+        }
+    }
+
+    /// changes a value for the debugger without recording it or triggering watchpoints.
+    /// Unlike `restore` the adress is checked, devices can not be written this way
+    pub fn debug_store(&mut self, addr: u64, val: u64) -> Result<(),String>
+    {
+        self.check_debug_store(addr)?;
+        self.restore(addr, val)
+    }
+
+    /// returns and clears the adresses in the code section that were written
+    pub fn take_code_writes(&mut self) -> Vec<u64>
+    {
+        std::mem::take(&mut self.code_writes)
+    }
+
+    pub fn has_code_writes(&self) -> bool
+    {
+        !self.code_writes.is_empty()
+    }
+
+    fn note_code_write(&mut self, addr: u64)
+    {
+        if addr >= self.code_base_ptr && addr < self.code_base_ptr + self.code_size
+        {
+            self.code_writes.push(addr);
+        }
+    }
+
+    pub fn has_devices(&self) -> bool
+    {
+        !self.devices.is_empty()
+    }
+
+    fn find_device(&self, addr: u64) -> Option<&MappedDevice>
+    {
+        self.devices.iter().find(|d| d.base <= addr && addr < d.base + d.size)
+    }
+
+    /// passes the current cycle count to all devices
+    pub fn tick_devices(&mut self, cycle: u128)
+    {
+        self.devices.iter_mut().for_each(|d| d.device.get_mut().tick(cycle));
+    }
+
+    /// the output the devices wrote since the last call
+    pub fn take_device_output(&mut self) -> String
+    {
+        self.devices.iter_mut().filter_map(|d| d.device.get_mut().take_output()).collect()
+    }
+
+    /// informs all devices that the machine stopped
+    pub fn finish_devices(&mut self) -> Result<(),String>
+    {
+        self.devices.iter_mut().try_for_each(|d| d.device.get_mut().finish())
+    }
+
     pub fn store(&mut self,val: u64 ,addr: u64) -> Result<(),String>
     {
-        if self.mem_size-1 < addr
+        self.check_guard(addr)?;
+
+        if let Some(d) = self.find_device(addr)
+        {
+            return d.device.borrow_mut().write(addr - d.base, val)
+        }
+
+        if addr >= self.mem_size
         {
             return Err(format!("adress is not in the adressspace?: {}",addr))
         }
 
+        if !self.watchpoints.is_empty() && self.watchpoints.contains(&addr)
+        {
+            let old = self.mem.read(addr).unwrap_or(0);
+            self.watch_hits.push((addr, old, val));
+        }
+
+        if let Some(writes) = &mut self.recorded_writes
+        {
+            writes.push((addr, self.mem.read(addr).unwrap_or(0), val));
+        }
+
+        self.note_code_write(addr);
         self.mem.store(addr, val)
     }
 
@@ -149,14 +781,35 @@ impl MemModel
     /// If the given adress adress cant be accessed `None` will be returnd
     pub fn read(&self, addr: u64) -> Result<u64,String>
     {
-        if self.mem_size -1 < addr
+        self.check_guard(addr)?;
+
+        if let Some(d) = self.find_device(addr)
+        {
+            return d.device.borrow_mut().read(addr - d.base)
+        }
+
+        if addr >= self.mem_size
         {
             return Err("adress is not in the adressspace?".into())
         }
 
-        if addr == 0
+        self.mem.read(addr).ok_or("".into())
+    }
+
+    /// returns the value at the adress like `read`, but without the side effects of devices.
+    /// Used by the debugger and the cost model
+    pub fn peek(&self, addr: u64) -> Result<u64,String>
+    {
+        self.check_guard(addr)?;
+
+        if let Some(d) = self.find_device(addr)
         {
-            return Err("tried to deref NULL!".into())
+            return d.device.borrow().peek(addr - d.base)
+        }
+
+        if addr >= self.mem_size
+        {
+            return Err("adress is not in the adressspace?".into())
         }
 
         self.mem.read(addr).ok_or("".into())
@@ -174,23 +827,22 @@ impl MemModel
         let mut possible_spot_ptr: Option<u64> = None;
         let mut possible_spot_size: Option<u64> = None;
 
-        // first usable adress after the code section
-        let code_end = self.code_base_ptr + self.code_size;
+        // first usable adress of the heap
+        let heap_base = self.heap_base;
 
         // if there are no allocations in the allocations-table we can directly check 
         // between the end of the stack and the end of the code section
         if self.alloc_tabel.len() == 0
         {
-            if self.heap_cutoff - code_end  >= size
+            if self.heap_cutoff.saturating_sub(heap_base) >= size
             {
                 // allocation adress should be as low as possible to 
                 // give as much space to the stack as possible.
-                let ptr = code_end;
+                let ptr = heap_base;
 
                 self.alloc_tabel.insert(Allocation{ptr, size});
                 
-                // clear memory
-                (1+code_end..=code_end+size).for_each(|x| self.mem.store(x, 0).unwrap());
+                self.clear(ptr, size);
 
                 return Some(ptr);
             }
@@ -201,14 +853,14 @@ impl MemModel
         }
 
         // check between first allocation and the end of the code-section
-        if self.alloc_tabel.peek(0).ptr - code_end >= size
+        if self.alloc_tabel.peek(0).ptr - heap_base >= size
         {
             // allocation adress should be as low as possible to 
             // give as much space to the stack as possible.
-            let ptr = code_end + size;
+            let ptr = heap_base + size;
 
             possible_spot_ptr = Some(ptr);
-            possible_spot_size = Some(self.alloc_tabel.peek(0).ptr - code_end);
+            possible_spot_size = Some(self.alloc_tabel.peek(0).ptr - heap_base);
         }
 
         // check between allocations 
@@ -247,11 +899,18 @@ impl MemModel
         // if a allocation is happenening the memory must be nulled?
         if let Some(ptr) = possible_spot_ptr
         {
-            (ptr..ptr+size).for_each(|x| self.mem.store(x, 0).unwrap());
+            self.clear(ptr, size);
         } 
         possible_spot_ptr
     }
 
+    /// sets the memory of a new allocation to 0. The writes go through `store`,
+    /// so they are recorded for the journal and trigger watchpoints like the writes of instructions
+    fn clear(&mut self, ptr: u64, size: u64)
+    {
+        (ptr..ptr+size).for_each(|addr| {let _ = self.store(0, addr);});
+    }
+
     pub fn heap_free(&mut self, ptr: u64)
     {
         // TODO: should the memory be nulled?
@@ -409,14 +1068,234 @@ mod tests
         let mut mm = MemModel::new(10);
 
         // set heap boundaries
-        mm.code_base_ptr = 1;
-        mm.code_size = 1;
+        mm.prepare_mem(vec![0], vec![1]).unwrap();
 
         let maybe_ptr = mm.malloc(3);
 
         assert_eq!(maybe_ptr,Some(2))
     }
 
+    #[test]
+    fn malloc_records_clear_test()
+    {
+        let mut mm = MemModel::new(10);
+        mm.prepare_mem(vec![0], vec![1]).unwrap();
+
+        mm.store(7, 3).unwrap();
+        mm.watch(3);
+        mm.record_writes(true);
+
+        assert_eq!(mm.malloc(3), Some(2));
+        assert_eq!(mm.read(3), Ok(0));
+        assert_eq!(mm.take_recorded_writes(), vec![(2,0,0), (3,7,0), (4,0,0)]);
+        assert_eq!(mm.take_watch_hits(), vec![(3,7,0)]);
+    }
+
+    #[test]
+    fn mem_copy_overlapping_test()
+    {
+        let mut mm = MemModel::new(10);
+
+        (1..=4).for_each(|x| mm.store(x, x).unwrap());
+
+        assert_eq!(mm.mem_copy(2, 1, 4), Ok(()));
+        assert_eq!((2..=5).map(|x| mm.read(x).unwrap()).collect::<Vec<_>>(), vec![1,2,3,4]);
+    }
+
+    #[test]
+    fn mem_fill_test()
+    {
+        let mut mm = MemModel::new(10);
+
+        assert_eq!(mm.mem_fill(3, 7, 3), Ok(()));
+        assert_eq!((2..=6).map(|x| mm.read(x).unwrap()).collect::<Vec<_>>(), vec![0,7,7,7,0]);
+    }
+
+    #[test]
+    fn mem_copy_chunks_test()
+    {
+        let size = 3 * COPY_CHUNK_SIZE as u64 + 5;
+        let mut mm = MemModel::new(10_000);
+        (1..=size).for_each(|x| mm.store(x, x).unwrap());
+
+        // the destination starts inside of the source
+        assert_eq!(mm.mem_copy(3, 1, size), Ok(()));
+        assert!((3..size+3).all(|x| mm.read(x) == Ok(x - 2)));
+
+        // the source starts inside of the destination
+        assert_eq!(mm.mem_copy(2, 3, size), Ok(()));
+        assert!((2..size+2).all(|x| mm.read(x) == Ok(x - 1)));
+    }
+
+    #[test]
+    fn huge_mem_fill_test()
+    {
+        let mut mm = MemModel::new(1 << 41);
+        mm.store(7, 5).unwrap();
+        mm.store(7, 1 << 35).unwrap();
+        mm.store(7, (1 << 40) + 1).unwrap();
+
+        // only the allocated pages are cleared
+        assert_eq!(mm.mem_fill(1, 0, 1 << 40), Ok(()));
+        assert_eq!(mm.read(5), Ok(0));
+        assert_eq!(mm.read(1 << 35), Ok(0));
+        assert_eq!(mm.read((1 << 40) + 1), Ok(7));
+
+        assert!(mm.mem_fill(1, 7, 1 << 40).is_err());
+        assert!(mm.mem_copy(1 << 40, 1, 1 << 40).is_err());
+        assert_eq!(mm.get_allocated_pages(), 3);
+
+        // a guard page inside of the range is not skipped
+        mm.add_guard(1 << 30, 1).unwrap();
+        assert!(mm.mem_fill(1, 0, 1 << 40).is_err());
+    }
+
+    #[test]
+    fn mem_copy_out_of_bounds_test()
+    {
+        let mut mm = MemModel::new(10);
+
+        assert!(mm.mem_copy(8, 1, 3).is_err());
+        assert!(mm.mem_copy(1, 8, 3).is_err());
+        assert!(mm.mem_fill(0, 1, 1).is_err());
+        assert!(mm.mem_fill(1, 1, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn sparse_memory_test()
+    {
+        let mut mm = MemModel::new(u64::MAX);
+        mm.prepare_mem(vec![0], vec![1,2]).unwrap();
+
+        mm.store(7, (1 << 40) + 1).unwrap();
+        mm.store(0, 1 << 50).unwrap();
+
+        assert_eq!(mm.read((1 << 40) + 1), Ok(7));
+        assert_eq!(mm.read(1 << 50), Ok(0));
+        assert_eq!(mm.read(u64::MAX - 1), Ok(0));
+        assert!(mm.read(u64::MAX).is_err());
+
+        // the programm and the value above
+        assert_eq!(mm.get_allocated_pages(), 2);
+    }
+
+    #[test]
+    fn mem_layout_test()
+    {
+        let layout = MemLayout{stack_top: Some(99), stack_size: Some(10), ..MemLayout::new(200)};
+
+        let mut mm = MemModel::with_layout(layout);
+        mm.prepare_mem(vec![0;5], vec![1;5]).unwrap();
+
+        assert_eq!(mm.get_stack_top(), 99);
+        assert_eq!(mm.get_stack_limit(), 90);
+        assert_eq!(mm.get_heap_base(), 10);
+
+        // the heap can not grow into the stack
+        mm.set_heap_cutoff(150);
+        assert_eq!(mm.get_heap_cutoff(), 89);
+        assert_eq!(mm.malloc(80), None);
+        assert_eq!(mm.malloc(79), Some(10));
+
+        // programm does not fit
+        assert!(MemModel::new(8).prepare_mem(vec![0;5], vec![1;5]).is_err());
+        // stack overlaps with the programm
+        assert!(MemModel::with_layout(MemLayout{stack_size: Some(15), ..MemLayout::new(20)}).prepare_mem(vec![0;5], vec![1;5]).is_err());
+        // heap base inside of the programm
+        assert!(MemModel::with_layout(MemLayout{heap_base: Some(3), ..MemLayout::new(20)}).prepare_mem(vec![0;5], vec![1;5]).is_err());
+        // stack outside of the memory
+        assert!(MemModel::with_layout(MemLayout{stack_top: Some(20), ..MemLayout::new(20)}).prepare_mem(vec![0;5], vec![1;5]).is_err());
+    }
+
+    #[test]
+    fn section_bases_test()
+    {
+        let mut mm = MemModel::new(100);
+        mm.prepare_sections(50, vec![7;3], 10, vec![1;5]).unwrap();
+
+        assert_eq!(mm.get_rom_base_ptr(), 50);
+        assert_eq!(mm.get_code_base_ptr(), 10);
+        assert_eq!(mm.read(51), Ok(7));
+        assert_eq!(mm.read(14), Ok(1));
+
+        // heap and stack start behind the rom, which ends last
+        assert_eq!(mm.get_heap_base(), 53);
+        assert_eq!(mm.get_stack_limit(), 53);
+        assert!(mm.map_device(52, 1, Box::new(crate::device::TimerDevice::new())).is_err());
+
+        // overlapping sections
+        assert!(MemModel::new(100).prepare_sections(10, vec![7;3], 12, vec![1;5]).is_err());
+        // a section behind the end of the memory
+        assert!(MemModel::new(100).prepare_sections(0, vec![7;3], 98, vec![1;5]).is_err());
+        assert!(MemModel::new(100).prepare_sections(u64::MAX, vec![7;3], 0, vec![1;5]).is_err());
+        // no memory at all
+        assert!(MemModel::new(0).prepare_mem(vec![], vec![]).is_err());
+        assert!(MemModel::new(0).read(0).is_err());
+    }
+
+    #[test]
+    fn null_and_guard_test()
+    {
+        let mut mm = MemModel::new(100);
+
+        assert!(mm.store(1, 0).is_err());
+        assert!(mm.add_guard(50, 0).is_err());
+        mm.add_guard(50, 10).unwrap();
+        assert!(mm.add_guard(55, 10).is_err());
+
+        assert!(mm.read(50).is_err());
+        assert!(mm.store(1, 59).is_err());
+        assert_eq!(mm.store(1, 60), Ok(()));
+        assert_eq!(mm.read(49), Ok(0));
+    }
+
+    #[test]
+    fn peek_device_test()
+    {
+        use crate::device::{ConsoleDevice,FramebufferDevice};
+        use crate::input::InputSource;
+
+        let mut mm = MemModel::new(100);
+        mm.map_device(20, 2, Box::new(ConsoleDevice::with_input(InputSource::from_bytes(b"x")))).unwrap();
+        mm.map_device(30, 2, Box::new(FramebufferDevice::new(2, 1, None).unwrap())).unwrap();
+        mm.store(7, 31).unwrap();
+
+        assert_eq!(mm.peek(31), Ok(7));
+        assert_eq!(mm.peek(5), Ok(0));
+        assert!(mm.peek(0).is_err());
+
+        // peeking does not consume the input of the console
+        assert!(mm.peek(21).is_err());
+        assert_eq!(mm.read(21), Ok(b'x' as u64));
+    }
+
+    #[test]
+    fn mapped_device_test()
+    {
+        use crate::device::FramebufferDevice;
+
+        let mut mm = MemModel::new(10);
+
+        // mapped partly inside and partly outside of the memory
+        mm.map_device(8, 4, Box::new(FramebufferDevice::new(2, 2, None).unwrap())).unwrap();
+        assert!(mm.map_device(11, 2, Box::new(FramebufferDevice::new(1, 2, None).unwrap())).is_err());
+        assert!(mm.map_device(0, 1, Box::new(FramebufferDevice::new(1, 1, None).unwrap())).is_err());
+
+        mm.store(42, 11).unwrap();
+        assert_eq!(mm.read(11), Ok(42));
+        assert_eq!(mm.read(8), Ok(0));
+
+        // the memory below the device is not touched
+        mm.store(7, 7).unwrap();
+        assert_eq!(mm.read(7), Ok(7));
+        assert!(mm.read(12).is_err());
+
+        let mut mm = MemModel::new(20);
+        mm.prepare_mem(vec![0;2], vec![1;3]).unwrap();
+        assert!(mm.map_device(4, 2, Box::new(FramebufferDevice::new(1, 2, None).unwrap())).is_err());
+        assert_eq!(mm.map_device(5, 2, Box::new(FramebufferDevice::new(1, 2, None).unwrap())), Ok(()));
+    }
+
     // #[test]
     // fn stack_read_basic_test()
     // {
@@ -0,0 +1,249 @@
+use crate::debug::DebugInformation;
+
+use j_system_definition::instructions::InstructionEnum;
+
+use std::collections::HashMap;
+
+/// number of instructions in the list of the most executed instructions
+const HOT_INSTRUCTIONS: usize = 20;
+
+/// counts how often every instruction is executed and how many cycles
+/// are spent in the functions, which are tracked by `call` and `ret`
+#[derive(Default)]
+pub struct Profiler
+{
+    /// executions and cycles per instruction adress
+    counts: HashMap<u64,(u64,u64)>,
+
+    /// the active calls, the first frame is the start of the programm
+    stack: Vec<Frame>,
+
+    /// Key: adress of the function
+    functions: HashMap<u64,FunctionStats>,
+
+    /// cycles per call stack (adresses of the functions, outermost first)
+    folded: HashMap<Vec<u64>,u64>,
+
+    cycles: u64,
+}
+
+struct Frame
+{
+    function: u64,
+
+    /// cycles of the profiler when the function was called
+    start: u64,
+}
+
+#[derive(Default,Clone,Copy)]
+struct FunctionStats
+{
+    calls: u64,
+
+    /// cycles in the function and the functions it called
+    inclusive: u64,
+
+    /// cycles in the function itself
+    exclusive: u64,
+}
+
+impl Profiler
+{
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// counts an executed instruction that took `cost` cycles.
+    /// `next_pc` is the value of `pc` after the instruction
+    pub fn record(&mut self, pc: u64, instruction: InstructionEnum, next_pc: u64, cost: u64)
+    {
+        if self.stack.is_empty()
+        {
+            self.enter(pc);
+        }
+
+        // the costs of a cost model can be large enough to overflow
+        self.cycles = self.cycles.saturating_add(cost);
+        let (executions,cycles) = self.counts.entry(pc).or_insert((0,0));
+        *executions += 1;
+        *cycles = cycles.saturating_add(cost);
+
+        let path:Vec<u64> = self.stack.iter().map(|frame| frame.function).collect();
+        let exclusive = &mut self.functions.entry(path[path.len()-1]).or_default().exclusive;
+        *exclusive = exclusive.saturating_add(cost);
+        let folded = self.folded.entry(path).or_insert(0);
+        *folded = folded.saturating_add(cost);
+
+        match instruction
+        {
+            InstructionEnum::call => self.enter(next_pc),
+            // returning from the start of the programm is not a call that ends
+            InstructionEnum::ret if self.stack.len() > 1 => self.leave(),
+            _ => {},
+        }
+    }
+
+    fn enter(&mut self, function: u64)
+    {
+        self.functions.entry(function).or_default().calls += 1;
+        self.stack.push(Frame{function, start: self.cycles});
+    }
+
+    fn leave(&mut self)
+    {
+        let frame = self.stack.pop().unwrap();
+
+        // the cycles of a recursive call are already part of the outer call
+        if self.stack.iter().all(|outer| outer.function != frame.function)
+        {
+            let inclusive = &mut self.functions.get_mut(&frame.function).unwrap().inclusive;
+            *inclusive = inclusive.saturating_add(self.cycles - frame.start);
+        }
+    }
+
+    /// the function statistics including the calls that did not return yet
+    fn function_stats(&self) -> HashMap<u64,FunctionStats>
+    {
+        let mut functions = self.functions.clone();
+
+        for (ii,frame) in self.stack.iter().enumerate()
+        {
+            if self.stack[..ii].iter().all(|outer| outer.function != frame.function)
+            {
+                let inclusive = &mut functions.get_mut(&frame.function).unwrap().inclusive;
+                *inclusive = inclusive.saturating_add(self.cycles - frame.start);
+            }
+        }
+        functions
+    }
+
+    /// the report of the hot functions, labels and instructions, sorted by cycles
+    pub fn report(&self, debug: &DebugInformation) -> String
+    {
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.cycles.max(1) as f64;
+        let mut s = format!("profile: {} cycles\n", self.cycles);
+
+        let mut functions:Vec<_> = self.function_stats().into_iter().collect();
+        functions.sort_by_key(|(addr,stats)| (std::cmp::Reverse(stats.inclusive), *addr));
+
+        s.push_str("\nfunctions:\n");
+        s.push_str(&format!("{:>12} {:>7} {:>12} {:>7} {:>8}  function\n", "inclusive", "%", "exclusive", "%", "calls"));
+        for (addr,stats) in functions
+        {
+            s.push_str(&format!("{:>12} {:>6.2}% {:>12} {:>6.2}% {:>8}  {}\n", stats.inclusive, percent(stats.inclusive),
+                stats.exclusive, percent(stats.exclusive), stats.calls, function_name(debug, addr)));
+        }
+
+        let mut labels:HashMap<String,u64> = HashMap::new();
+        for (addr,(_,cycles)) in &self.counts
+        {
+            let label = debug.enclosing_label(*addr).map_or("<no label>".to_string(), |(_,name)| name.to_string());
+            *labels.entry(label).or_insert(0) += cycles;
+        }
+
+        let mut labels:Vec<_> = labels.into_iter().collect();
+        labels.sort_by(|(l1,c1),(l2,c2)| c2.cmp(c1).then(l1.cmp(l2)));
+
+        s.push_str("\nlabels:\n");
+        s.push_str(&format!("{:>12} {:>7}  label\n", "cycles", "%"));
+        for (label,count) in labels
+        {
+            s.push_str(&format!("{:>12} {:>6.2}%  {}\n", count, percent(count), label));
+        }
+
+        let mut counts:Vec<_> = self.counts.iter().collect();
+        counts.sort_by_key(|(addr,(_,cycles))| (std::cmp::Reverse(*cycles), **addr));
+
+        s.push_str("\nhot instructions:\n");
+        s.push_str(&format!("{:>12} {:>7} {:>12}  adress\n", "cycles", "%", "executions"));
+        for (addr,(executions,cycles)) in counts.into_iter().take(HOT_INSTRUCTIONS)
+        {
+            s.push_str(&format!("{:>12} {:>6.2}% {:>12}  {}\n", cycles, percent(*cycles), executions, debug.location(*addr)));
+        }
+        s
+    }
+
+    /// the cycles per call stack in the folded format of flamegraph.pl and inferno:
+    /// `start;main;loop 120`
+    pub fn folded(&self, debug: &DebugInformation) -> String
+    {
+        let mut lines:Vec<String> = self.folded.iter()
+            .map(|(path,cycles)|
+            {
+                let names:Vec<String> = path.iter().map(|addr| function_name(debug, *addr)).collect();
+                format!("{} {}", names.join(";"), cycles)
+            })
+            .collect();
+
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
+
+/// the label of the function or its adress if there is no label
+fn function_name(debug: &DebugInformation, addr: u64) -> String
+{
+    match debug.enclosing_label(addr)
+    {
+        Some((ptr,name)) if ptr == addr => name.to_string(),
+        _ => format!("{}", addr),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::{MachineState,MachineInitInfo};
+    use crate::test_util::assembled_machine;
+
+    fn profile() -> MachineState
+    {
+        let mut machine = assembled_machine(MachineInitInfo::default(), "
+_rom
+_code
+    .start:
+        mov a, 0
+    .loop:
+        call .double
+        add a, 1
+        cmp a, 3
+        jl .loop
+        push 9
+        sys
+
+    .double:
+        add b, b
+        ret 0
+");
+
+        machine.profiler = Some(Profiler::new());
+        machine.run().unwrap();
+        machine
+    }
+
+    #[test]
+    fn profile_test()
+    {
+        let machine = profile();
+        let profiler = machine.profiler.as_ref().unwrap();
+
+        // 1 + 3*(call + 2 + add, cmp, jl) + push, the last sys ends the machine
+        assert_eq!(profiler.cycles, 1 + 3*6 + 1);
+        assert_eq!(profiler.cycles as u128, machine.machine_information.get_cycles());
+        assert_eq!(profiler.counts[&14], (3,3));
+
+        let double = profiler.function_stats()[&14];
+        assert_eq!((double.calls, double.inclusive, double.exclusive), (3, 6, 6));
+
+        let start = profiler.function_stats()[&1];
+        assert_eq!((start.calls, start.inclusive, start.exclusive), (1, 20, 14));
+
+        assert_eq!(profiler.folded(&machine.debug), "start 14\nstart;double 6\n");
+
+        let report = profiler.report(&machine.debug);
+        assert!(report.contains("          20 100.00%           14  70.00%        1  start\n"), "{}", report);
+        assert!(report.contains("          13  65.00%  loop\n"), "{}", report);
+    }
+}
@@ -0,0 +1,51 @@
+/// pseudo random number generator (splitmix64).
+/// The same seed always generates the same numbers
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Rng
+{
+    state: u64,
+}
+
+impl Rng
+{
+    pub fn new(seed: u64) -> Self
+    {
+        Self{state: seed}
+    }
+
+    /// the seed that continues the sequence from here on, used for snapshots
+    pub fn state(&self) -> u64
+    {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64
+    {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// a number in the range `0..bound`. A bound of 0 returns the full 64 bit range
+    pub fn below(&mut self, bound: u64) -> u64
+    {
+        if bound == 0
+        {
+            return self.next_u64();
+        }
+
+        // reject values of the incomplete last "bucket" to
+        // not prefer the lower numbers
+        let zone = u64::MAX - (u64::MAX % bound);
+        loop
+        {
+            let val = self.next_u64();
+            if val < zone
+            {
+                return val % bound;
+            }
+        }
+    }
+}
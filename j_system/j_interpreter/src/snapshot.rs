@@ -0,0 +1,209 @@
+use crate::machine::MachineState;
+
+use j_system_definition::register::{Register,RegisterState};
+
+use serde_json::{json,Map,Value};
+
+use std::fs;
+
+const FORMAT: &str = "j-snapshot";
+const VERSION: u64 = 1;
+
+const REGISTERS: [Register;10] = [Register::a, Register::b, Register::c, Register::d, Register::e, Register::f,
+                                  Register::s, Register::pc, Register::tos, Register::bos];
+
+/// the complete state of the machine as json: registers, memory, allocations,
+/// layout, output, cycle count and the state of the random number generator.
+///
+/// Mapped devices, open files, the position in the input and the
+/// debugger (breakpoints, history) are not part of the snapshot,
+/// `save_file` refuses to write a snapshot while the programm uses them
+pub fn save(machine: &MachineState) -> String
+{
+    let registers:Map<String,Value> = REGISTERS.iter()
+        .map(|reg| (format!("{:?}", reg), json!(machine.reg_state.read(*reg))))
+        .collect();
+
+    json!({
+        "format": FORMAT,
+        "version": VERSION,
+        "registers": registers,
+        "machine": machine.machine_information.save_state(),
+        "memory": machine.mem_state.save_state(),
+    }).to_string()
+}
+
+/// replaces the state of the machine with the snapshot.
+/// The machine continues at the instruction `pc` points to
+pub fn load(machine: &mut MachineState, snapshot: &str) -> Result<(),String>
+{
+    let snapshot:Value = serde_json::from_str(snapshot).map_err(|e| format!("invalid snapshot: {}", e))?;
+
+    if snapshot["format"] != FORMAT
+    {
+        return Err("the file is not a snapshot".into())
+    }
+    if snapshot["version"] != VERSION
+    {
+        return Err(format!("unsupported snapshot version {}, expected {}", snapshot["version"], VERSION))
+    }
+
+    let mut regs = RegisterState::new();
+    for reg in REGISTERS
+    {
+        let val = get_u64(&snapshot["registers"], &format!("{:?}", reg))?;
+        match reg
+        {
+            Register::pc | Register::s => regs.store_to_read_only(reg, val),
+            _ => regs.store(reg, val),
+        }
+    }
+
+    machine.mem_state.load_state(&snapshot["memory"])?;
+    machine.decode_cache.reset(&mut machine.mem_state);
+    if let Some(engine) = &mut machine.block_engine
+    {
+        engine.reset();
+    }
+    machine.machine_information.load_state(&snapshot["machine"])?;
+    machine.reg_state = regs;
+    machine.next_ptr = regs.read(Register::pc);
+    Ok(())
+}
+
+/// fails if the machine uses state that a snapshot can't contain,
+/// the resumed machine would continue differently
+pub fn check_complete(machine: &MachineState) -> Result<(),String>
+{
+    if machine.machine_information.input_was_read()
+    {
+        return Err("the programm already read input, the position in the input can't be saved".into())
+    }
+    if machine.files.has_open_files()
+    {
+        return Err("the programm has open files, they can't be saved".into())
+    }
+    if machine.mem_state.has_devices()
+    {
+        return Err("the state of the mapped devices can't be saved".into())
+    }
+    Ok(())
+}
+
+pub fn save_file(machine: &MachineState, file: &str) -> Result<(),String>
+{
+    check_complete(machine)?;
+    fs::write(file, save(machine)).map_err(|e| format!("could not write {}: {}", file, e))
+}
+
+pub fn load_file(machine: &mut MachineState, file: &str) -> Result<(),String>
+{
+    let snapshot = fs::read_to_string(file).map_err(|e| format!("could not read {}: {}", file, e))?;
+    load(machine, &snapshot)
+}
+
+pub(crate) fn get_u64(state: &Value, name: &str) -> Result<u64,String>
+{
+    state[name].as_u64().ok_or(format!("{} is missing or invalid", name))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::MachineInitInfo;
+    use crate::test_util::assembled_machine;
+
+    fn machine(max_cycles: u128) -> MachineState
+    {
+        assembled_machine(MachineInitInfo{max_cycles, seed: Some(7), ..Default::default()}, "
+_rom
+_code
+    .start:
+        mov a, 0
+    .loop:
+        add a, 1
+        push a
+        cmp a, 50
+        jl .loop
+        push 9
+        sys
+")
+    }
+
+    #[test]
+    fn resume_from_snapshot_test()
+    {
+        let mut full = machine(u128::MAX);
+        full.run().unwrap();
+
+        // stop in the middle of the loop
+        let mut first = machine(60);
+        assert_eq!(first.run(), Err("cycle count surpassed".into()));
+        first.mem_state.malloc(10).unwrap();
+        first.machine_information.next_rand(0);
+
+        let snapshot = save(&first);
+
+        let mut resumed = MachineState::init(MachineInitInfo{max_cycles: u128::MAX, ..Default::default()});
+        load(&mut resumed, &snapshot).unwrap();
+
+        assert_eq!(resumed.machine_information.get_cycles(), 60);
+        assert_eq!(resumed.mem_state.malloc(5), first.mem_state.malloc(5));
+        assert_eq!(resumed.machine_information.next_rand(0), first.machine_information.next_rand(0));
+
+        resumed.run().unwrap();
+
+        assert_eq!(resumed.machine_information.get_cycles(), full.machine_information.get_cycles());
+        for reg in REGISTERS
+        {
+            assert_eq!(resumed.reg_state.read(reg), full.reg_state.read(reg));
+        }
+        let tos = full.reg_state.read(Register::tos);
+        for addr in tos..=full.mem_state.get_stack_top()
+        {
+            assert_eq!(resumed.mem_state.read(addr), full.mem_state.read(addr));
+        }
+    }
+
+    #[test]
+    fn invalid_snapshot_test()
+    {
+        let mut machine = machine(10);
+
+        assert!(load(&mut machine, "{}").is_err());
+        assert!(load(&mut machine, r#"{"format": "j-snapshot", "version": 99}"#).unwrap_err().contains("version"));
+
+        let mut snapshot:Value = serde_json::from_str(&save(&machine)).unwrap();
+        snapshot["registers"]["a"] = json!("one");
+        assert!(load(&mut machine, &snapshot.to_string()).is_err());
+    }
+
+    #[test]
+    fn incomplete_snapshot_test()
+    {
+        use crate::device::TimerDevice;
+        use crate::file_system::{FileSystem,FileTable,OpenMode};
+        use crate::input::InputSource;
+
+        let mut machine = machine(10);
+        assert_eq!(check_complete(&machine), Ok(()));
+
+        machine.machine_information.set_input(InputSource::from_bytes(b"1"));
+        machine.machine_information.get_input().read_byte();
+        assert!(check_complete(&machine).unwrap_err().contains("input"));
+        assert!(save_file(&machine, "unused.json").is_err());
+
+        // a new input starts at the beginning again
+        machine.machine_information.set_input(InputSource::from_bytes(b""));
+        assert_eq!(check_complete(&machine), Ok(()));
+
+        machine.files = FileTable::new(FileSystem::Virtual(Default::default()));
+        let fd = machine.files.open("out.txt", OpenMode::Write).unwrap();
+        assert!(check_complete(&machine).unwrap_err().contains("files"));
+        machine.files.close(fd).unwrap();
+
+        machine.mem_state.map_device(1000, 2, Box::new(TimerDevice::new())).unwrap();
+        assert!(check_complete(&machine).unwrap_err().contains("devices"));
+    }
+}
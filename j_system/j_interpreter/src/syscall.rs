@@ -1,18 +1,34 @@
 use j_system_definition::register::Register;
 use crate::machine::*;
+use crate::file_system::OpenMode;
+use crate::syscall_handler::SyscallContext;
 
 
-use std::io;
+use std::time::{SystemTime,UNIX_EPOCH};
 
 // const definition of param offsets
 const PARAM1:u64 = 0;
 const PARAM2:u64 = 1;
 const PARAM3:u64 = 2;
 const PARAM4:u64 = 3;
+const PARAM5:u64 = 4;
 
 /// syscall types that can be called from the programm 
 /// running in the interpreter 
 /// can be accessed by the "sys" command
+/// 
+/// ### Calling convention
+/// The parameters are pushed onto the stack in reverse order, 
+/// so the syscall number is pushed last and ends up on top of the stack:  
+/// 
+/// \[tos+0\] syscall number (PARAM1)  
+/// \[tos+1\] PARAM2  
+/// \[tos+2\] PARAM3  
+/// ...  
+/// 
+/// The syscall removes all of its parameters from the stack.
+/// Values that are returned by a syscall are written to register `f`.
+#[derive(Clone,Copy,PartialEq,Debug)]
 pub enum SysCallType
 {
     /// takes a Ineger as value that dictates the size
@@ -22,13 +38,87 @@ pub enum SysCallType
     /// cant be fitted in the remaining free space 
     Malloc,
     Free,
+
+    /// copies or fills a block of memory.  
+    /// PARAM2: 1 = copy, 2 = fill  
+    /// PARAM3: destination pointer  
+    /// PARAM4: source pointer (copy) or the value (fill)  
+    /// PARAM5: amount of values  
+    /// fails if one of the ranges is not inside the adress space
     MemCopy,
+
+    /// returns information about the machine.  
+    /// PARAM2: 1 = memory size, 2 = heap-cutoff, 3 = cycle count,
+    /// 4 = code base pointer, 5 = code size, 6 = rom base pointer, 7 = rom size
     SysInfo,
+
+    /// returns a pseudo random number. The generator can be seeded with `--seed`.  
+    /// PARAM2: upper bound (exclusive). 0 returns the full 64 bit range
     Rand,
+
+    /// returns the time.  
+    /// PARAM2: 1 = cycle count, 2 = milliseconds since the start of the machine (monotonic),
+    /// 3 = seconds since the unix epoch
     Time,
+
+    /// reads from stdin. Never aborts the machine on EOF.  
+    /// PARAM2: read type  
+    /// 1 = line (PARAM3: buffer pointer, PARAM4: buffer size incl. null termination),
+    /// 2 = bytes (PARAM3: buffer pointer, PARAM4: amount of bytes),
+    /// 3 = single char, 4 = decimal integer, 5 = hex integer  
+    /// returns the amount of read bytes (1, 2), the char (3) or the integer (4, 5) in `f`.
+    /// register `e` contains the status: 0 = ok, 1 = EOF, 2 = invalid input.
+    /// The file syscalls use the same status codes, 2 means the operation failed.
     ReadFromStdIn,
+
+    /// prints to stdout.
+    /// PARAM2: print type
+    /// PARAM3: content
+    /// 1 = register (content is the register number, printed as `a:123` with a newline),
+    /// 2 = null terminated string with a trailing newline (content is the pointer),
+    /// 3 = value as decimal, 4 = value as hex, 5 = value as binary,
+    /// 6 = single ascii char, 7 = null terminated string without newline,
+    /// 8 = buffer (content is the pointer, PARAM4 the length)
     PrintToStdOut,
     End,
+
+    /// opens a file of the file system that was given to the interpreter
+    /// (e.g. the `--sandbox` directory).  
+    /// PARAM2: pointer to the null terminated file name  
+    /// PARAM3: mode: 1 = read, 2 = write (create/truncate), 3 = append, 4 = read and write  
+    /// returns the file descriptor in `f` (`u64::MAX` if the file could not be opened)
+    FileOpen,
+
+    /// reads from a file into a buffer.  
+    /// PARAM2: file descriptor (0 = stdin)  
+    /// PARAM3: buffer pointer  
+    /// PARAM4: maximum amount of bytes  
+    /// returns the amount of read bytes in `f`
+    FileRead,
+
+    /// writes a buffer to a file. every value in the buffer must fit into a byte.  
+    /// PARAM2: file descriptor (1 = stdout)  
+    /// PARAM3: buffer pointer  
+    /// PARAM4: amount of bytes  
+    /// returns the amount of written bytes in `f`
+    FileWrite,
+
+    /// closes a file.  
+    /// PARAM2: file descriptor
+    FileClose,
+
+    /// changes the position in a file.  
+    /// PARAM2: file descriptor  
+    /// PARAM3: 0 = from the start, 1 = from the current position, 2 = from the end  
+    /// PARAM4: offset (two's complement)  
+    /// returns the new position in `f`
+    FileSeek,
+
+    /// stops the machine with an error if the condition is 0.
+    /// The error contains the source line of the `sys` instruction if the line table is loaded.  
+    /// PARAM2: condition  
+    /// PARAM3: pointer to a null terminated message (0 = no message)
+    Assert,
 }
 
 pub enum SysCallAction
@@ -36,51 +126,117 @@ pub enum SysCallAction
     Ok,
     End,
     Ptr(u64),
+    /// a value that is returned to the programm in register `f`
+    Value(u64),
     Err(String),
 }
 
-pub fn identify_syscall(state: &mut MachineState) -> SysCallType
+/// reads the parameter of the syscall at the given offset from the top of the stack
+fn read_param(state: &MachineState, offset: u64) -> Result<u64,String>
 {
-    // get the type of syscall from the top of the stack
-    // the syscall type is the first parameter and due to
-    // this calling convention it is on the top of the stack
+    state.mem_state.read(state.reg_state.read(Register::tos).wrapping_add(offset))
+}
+
+impl SysCallType
+{
+    /// all syscalls that are implemented by the interpreter
+    pub const ALL: [SysCallType; 15] = [
+        SysCallType::Malloc,
+        SysCallType::Free,
+        SysCallType::MemCopy,
+        SysCallType::SysInfo,
+        SysCallType::Rand,
+        SysCallType::Time,
+        SysCallType::ReadFromStdIn,
+        SysCallType::PrintToStdOut,
+        SysCallType::End,
+        SysCallType::FileOpen,
+        SysCallType::FileRead,
+        SysCallType::FileWrite,
+        SysCallType::FileClose,
+        SysCallType::FileSeek,
+        SysCallType::Assert,
+    ];
+
+    /// the number that has to be pushed on the stack to call this syscall
+    pub fn number(&self) -> u64
+    {
+        match self
+        {
+            SysCallType::Malloc         => 1,
+            SysCallType::Free           => 2,
+            SysCallType::MemCopy        => 3,
+            SysCallType::SysInfo        => 4,
+            SysCallType::Rand           => 5,
+            SysCallType::Time           => 6,
+            SysCallType::ReadFromStdIn  => 7,
+            SysCallType::PrintToStdOut  => 8,
+            SysCallType::End            => 9,
+            SysCallType::FileOpen       => 10,
+            SysCallType::FileRead       => 11,
+            SysCallType::FileWrite      => 12,
+            SysCallType::FileClose      => 13,
+            SysCallType::FileSeek       => 14,
+            SysCallType::Assert         => 15,
+        }
+    }
 
-    match state.mem_state.read(state.reg_state.read(Register::tos)+PARAM1).ok()
+    /// the function that implements this syscall
+    pub(crate) fn builtin(&self) -> fn(&mut MachineState) -> SysCallAction
     {
-        Some(1) => SysCallType::Malloc,
-        Some(2) => SysCallType::Free,
-        Some(3) => SysCallType::MemCopy,
-        Some(4) => SysCallType::SysInfo,
-        Some(5) => SysCallType::Rand,
-        Some(6) => SysCallType::Time,
-        Some(7) => SysCallType::ReadFromStdIn,
-        Some(8) => SysCallType::PrintToStdOut,
-        Some(9) => SysCallType::End,
-        x       => panic!("unkonwn syscall {:#?}",x)
+        match self
+        {
+            SysCallType::PrintToStdOut  => print_to_std_out,
+            SysCallType::End            => end,
+            SysCallType::Malloc         => malloc,
+            SysCallType::Free           => free,
+            SysCallType::ReadFromStdIn  => input,
+            SysCallType::MemCopy        => mem_copy,
+            SysCallType::SysInfo        => sys_info,
+            SysCallType::Rand           => rand,
+            SysCallType::Time           => time,
+            SysCallType::FileOpen       => file_open,
+            SysCallType::FileRead       => file_read,
+            SysCallType::FileWrite      => file_write,
+            SysCallType::FileClose      => file_close,
+            SysCallType::FileSeek       => file_seek,
+            SysCallType::Assert         => assert,
+        }
     }
 }
 
 pub fn syscall(state: &mut MachineState) -> SysCallAction
 {
-    // update heap-cutoff
-    state.mem_state.set_heap_cutoff(state.reg_state.read(Register::tos)-1);
+    // update heap-cutoff. The syscall fails below if the stack is empty
+    state.mem_state.set_heap_cutoff(state.reg_state.read(Register::tos).saturating_sub(1));
 
-    match identify_syscall(state)
+    // get the type of syscall from the top of the stack
+    // the syscall type is the first parameter and due to
+    // this calling convention it is on the top of the stack
+    let number = match read_param(state, PARAM1)
     {
-        SysCallType::PrintToStdOut  => print_to_std_out(state),
-        SysCallType::End            => end(state),
-        SysCallType::Malloc         => malloc(state),
-        SysCallType::Free           => free(state),
-        SysCallType::ReadFromStdIn  => input(state),
+        Ok(number) => number,
+        Err(x) => return SysCallAction::Err(x),
+    };
 
-        // TODO: other syscalls
-        _                           => SysCallAction::Err("could not identify syscall".to_string()),
-    }
+    // move the registry out of the state, so the handler 
+    // can get mutable access to the rest of the machine
+    let mut registry = std::mem::take(&mut state.syscalls);
+
+    let action = match registry.get_mut(number)
+    {
+        Some(handler) => handler.handle(&mut SyscallContext::new(state)),
+        None => SysCallAction::Err(format!("unkonwn syscall {}", number)),
+    };
+
+    state.syscalls = registry;
+
+    action
 }
 
 pub fn malloc(state: &mut MachineState) -> SysCallAction
 {
-    if let Ok(arg) = state.mem_state.read(state.reg_state.read(Register::tos)+PARAM2)
+    if let Ok(arg) = state.mem_state.read(state.reg_state.read(Register::tos).wrapping_add(PARAM2))
     {
         if let Some(ptr) = state.mem_state.malloc(arg)
         {
@@ -94,7 +250,7 @@ pub fn malloc(state: &mut MachineState) -> SysCallAction
 
 pub fn free(state: &mut MachineState) -> SysCallAction
 {
-    if let Ok(arg) = state.mem_state.read(state.reg_state.read(Register::tos)+PARAM2)
+    if let Ok(arg) = state.mem_state.read(state.reg_state.read(Register::tos).wrapping_add(PARAM2))
     {
         let _ = remove_stack_entries(state, 2);
         state.mem_state.heap_free(arg)
@@ -106,13 +262,76 @@ pub fn free(state: &mut MachineState) -> SysCallAction
 
 pub fn mem_copy(state: &mut MachineState) -> SysCallAction
 {
-    let mut size =      0u64;
-    let mut from_ptr =  0u64;
-    let mut to_ptr =    0u64;
+    let params = (read_param(state, PARAM2), read_param(state, PARAM3), read_param(state, PARAM4), read_param(state, PARAM5));
+
+    let (kind, to_ptr, from_ptr_or_val, size) = match params
+    {
+        (Ok(kind), Ok(to_ptr), Ok(from_ptr_or_val), Ok(size)) => (kind, to_ptr, from_ptr_or_val, size),
+        _ => return SysCallAction::Err("could not read the parameters of mem_copy".into()),
+    };
 
+    let res = match kind
+    {
+        1 => state.mem_state.mem_copy(to_ptr, from_ptr_or_val, size),
+        2 => state.mem_state.mem_fill(to_ptr, from_ptr_or_val, size),
+        x => Err(format!("non defined mem_copy-type: {}", x)),
+    };
+
+    if let Err(x) = res
+    {
+        return SysCallAction::Err(x)
+    }
+
+    let _ = remove_stack_entries(state, 5);
     SysCallAction::Ok
 }
 
+pub fn sys_info(state: &mut MachineState) -> SysCallAction
+{
+    let val = match read_param(state, PARAM2)
+    {
+        Ok(1) => state.mem_state.get_mem_size(),
+        Ok(2) => state.mem_state.get_heap_cutoff(),
+        Ok(3) => state.machine_information.get_cycles() as u64,
+        Ok(4) => state.mem_state.get_code_base_ptr(),
+        Ok(5) => state.mem_state.get_code_size(),
+        Ok(6) => state.mem_state.get_rom_base_ptr(),
+        Ok(7) => state.mem_state.get_rom_size(),
+        Ok(x) => return SysCallAction::Err(format!("non defined sys_info-type: {}", x)),
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    let _ = remove_stack_entries(state, 2);
+    SysCallAction::Value(val)
+}
+
+pub fn rand(state: &mut MachineState) -> SysCallAction
+{
+    let bound = match read_param(state, PARAM2)
+    {
+        Ok(bound) => bound,
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    let _ = remove_stack_entries(state, 2);
+    SysCallAction::Value(state.machine_information.next_rand(bound))
+}
+
+pub fn time(state: &mut MachineState) -> SysCallAction
+{
+    let val = match read_param(state, PARAM2)
+    {
+        Ok(1) => state.machine_information.get_cycles() as u64,
+        Ok(2) => state.machine_information.get_uptime_millis(),
+        Ok(3) => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        Ok(x) => return SysCallAction::Err(format!("non defined time-type: {}", x)),
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    let _ = remove_stack_entries(state, 2);
+    SysCallAction::Value(val)
+}
+
 pub fn end(state: &mut MachineState) -> SysCallAction
 {
     // remoev the syscall number
@@ -123,45 +342,80 @@ pub fn end(state: &mut MachineState) -> SysCallAction
 
 pub fn print_to_std_out(state: &mut MachineState) -> SysCallAction
 {
+    let (print_type, content) = match (read_param(state, PARAM2), read_param(state, PARAM3))
+    {
+        (Ok(print_type), Ok(content)) => (print_type, content),
+        _ => return SysCallAction::Err("could not read the parameters of print".into()),
+    };
 
-    if let (Ok(print_type), Ok(content)) 
-    =   (state.mem_state.read(state.reg_state.read(Register::tos)+PARAM2)
-        ,state.mem_state.read(state.reg_state.read(Register::tos)+PARAM3))
+    // amount of parameters that have to be removed from the stack
+    let mut param_count = 3;
+
+    let res = match print_type
     {
-        match print_type
+        // print a register
+        1 => print_register(state,content),
+
+        // print a null terminated String that is uncompressed
+        // (uncompressed: can only contain one char in the 64bit value)
+        // content is the pointer to the string
+        2 => print_string_terminated_uncompressed(state,content,true),
+
+        // print a value as decimal, hex or binary number
+        3 => {state.machine_information.push_str(format!("{}",content)); Ok(())},
+        4 => {state.machine_information.push_str(format!("{:x}",content)); Ok(())},
+        5 => {state.machine_information.push_str(format!("{:b}",content)); Ok(())},
+
+        // print a single ascii character
+        6 => print_char(state,content),
+
+        // print a null terminated String without a trailing newline
+        7 => print_string_terminated_uncompressed(state,content,false),
+
+        // print a buffer with a given length.
+        // content is the pointer to the buffer and PARAM4 the length
+        8 => 
         {
-            // print a register
-            1 => 
+            param_count = 4;
+            match read_param(state, PARAM4)
             {
-                if let Err(x) = print_register(state,content)
-                {
-                    return SysCallAction::Err(x)
-                }
-            },
-
-            // print a null terminated String that is uncompressed
-            // (uncompressed: can only contain one char in the 64bit value)
-            // content is the pointer to the string?
-            2 => 
-            {
-                if let Err(x) = print_string_terminated_uncompressed(state,content)
-                {
-                    return SysCallAction::Err(x)
-                }
+                Ok(len) => print_buffer(state,content,len),
+                Err(x) => Err(x),
             }
+        },
 
-            // TODO: more print types ...
-            x => return SysCallAction::Err(format!("non defined print-type: {}", x)),
-        }
+        x => Err(format!("non defined print-type: {}", x)),
+    };
+
+    if let Err(x) = res
+    {
+        return SysCallAction::Err(x)
     }
+
     // remove the stack params
-    // TODO: handle result
-    let _ = remove_stack_entries(state, 3);
+    let _ = remove_stack_entries(state, param_count);
 
     SysCallAction::Ok
 }
 
-pub fn print_string_terminated_uncompressed(state: &mut MachineState, mut adress: u64) -> Result<(),String>
+/// converts a value from memory into a char.
+/// Only ascii values are allowed
+fn value_to_ascii(c: u64, adress: u64) -> Result<char,String>
+{
+    // check if the value can be representetd as ascii symbol
+    if c <= 127
+    {
+        Ok(c as u8 as char)
+    }
+    else
+    {
+        Err(format!("non acii value {} at {}", c, adress))
+    }
+}
+
+/// reads a null terminated string that is uncompressed 
+/// (only one char per 64bit value)
+pub fn read_string_terminated_uncompressed(state: &MachineState, mut adress: u64) -> Result<String,String>
 {
     let mut s = String::from("");
 
@@ -169,76 +423,356 @@ pub fn print_string_terminated_uncompressed(state: &mut MachineState, mut adress
     {
         let c = state.mem_state.read(adress)?;
 
-        // check if the value can be representetd as ascii symbol
-        if c <= 127
+        // stop when the null termination is reached
+        if c == 0
         {
-            // stop when the null termination is reached
-            if c == 0
-            {
-                break
-            }
-            s.push(c as u8 as char)
-        }
-        else
-        {
-            return Err(format!("non acii value {} at {}", c, adress))
+            break
         }
+        s.push(value_to_ascii(c, adress)?);
         adress += 1;
     }
-    // push string and add newline
-    // TODO: maybe the newline should be included in the string?
-    //       the assembler cant do that yet
-    state.machine_information.push_str(s+ "\n");
+
+    Ok(s)
+}
+
+pub fn print_string_terminated_uncompressed(state: &mut MachineState, adress: u64, newline: bool) -> Result<(),String>
+{
+    let mut s = read_string_terminated_uncompressed(state, adress)?;
+
+    if newline
+    {
+        s.push('\n');
+    }
+
+    state.machine_information.push_str(s);
     Ok(())
 }
 
-pub fn input(state: &mut MachineState) -> SysCallAction
+pub fn print_char(state: &mut MachineState, c: u64) -> Result<(),String>
+{
+    let c = value_to_ascii(c, 0).map_err(|_| format!("can not print {} as a char", c))?;
+    state.machine_information.push_str(c.to_string());
+    Ok(())
+}
+
+pub fn print_buffer(state: &mut MachineState, adress: u64, len: u64) -> Result<(),String>
 {
-    if let (Ok(buffer_size), Ok(buffer_ptr)) 
-    =   (state.mem_state.read(state.reg_state.read(Register::tos)+PARAM2)
-        ,state.mem_state.read(state.reg_state.read(Register::tos)+PARAM3))
+    let mut s = String::from("");
+
+    for addr in adress..adress.saturating_add(len)
     {
-        let mut user_input = String::new();
-        io::stdin().read_line(&mut user_input).unwrap();
+        s.push(value_to_ascii(state.mem_state.read(addr)?, addr)?);
+    }
 
-        user_input = user_input.trim().to_string();
+    state.machine_information.push_str(s);
+    Ok(())
+}
 
-        let input_len = user_input.len();
+pub fn input(state: &mut MachineState) -> SysCallAction
+{
+    let read_type = match read_param(state, PARAM2)
+    {
+        Ok(read_type) => read_type,
+        Err(x) => return SysCallAction::Err(x),
+    };
 
-        let chars = user_input.chars();
+    let (status, val, param_count) = match read_type
+    {
+        // read a line or bytes into a buffer
+        1 | 2 =>
+        {
+            let (buffer_ptr, buffer_size) = match (read_param(state, PARAM3), read_param(state, PARAM4))
+            {
+                (Ok(buffer_ptr), Ok(buffer_size)) => (buffer_ptr, buffer_size),
+                _ => return SysCallAction::Err("could not read the parameters of input".into()),
+            };
 
-        //let mut idk:Vec<_> = user_input.chars().collect();
+            let res = if read_type == 1
+            {
+                input_line(state, buffer_ptr, buffer_size)
+            }
+            else
+            {
+                input_bytes(state, buffer_ptr, buffer_size)
+            };
 
-        // all chars have to be ascii
-        if !user_input.chars().all(|x| x.is_ascii())
+            match res
+            {
+                Ok((status, len)) => (status, len, 4),
+                Err(x) => return SysCallAction::Err(x),
+            }
+        },
+
+        // read a single char
+        3 => match state.machine_information.get_input().read_byte()
         {
-            return SysCallAction::Err("all input characters have to be ascii".into())
-        }
+            Some(c) => (STATUS_OK, c as u64, 2),
+            None => (STATUS_EOF, 0, 2),
+        },
 
-        // since at this point all chars are ascii
-        // the len of the string equals the amount of u8 elements?
-        // +1 because of Null termination to the input that will be added later
-        if input_len + 1 > buffer_size as usize
+        // read a decimal or hex integer
+        4 | 5 =>
         {
-            return SysCallAction::Err("provided buffer is to small to store the input".into())
-        }
+            let radix = if read_type == 4 {10} else {16};
+
+            match state.machine_information.get_input().read_token()
+            {
+                Some(token) => match parse_integer(&token, radix)
+                {
+                    Some(val) => (STATUS_OK, val, 2),
+                    None => (STATUS_INVALID, 0, 2),
+                },
+                None => (STATUS_EOF, 0, 2),
+            }
+        },
+
+        x => return SysCallAction::Err(format!("non defined input-type: {}", x)),
+    };
 
-        // convert chars to u64
-        let vec_buffer: Vec<_> = chars.map(|c| c as u8 as u64).collect();
+    state.reg_state.store(Register::e, status);
+    let _ = remove_stack_entries(state, param_count);
+
+    SysCallAction::Value(val)
+}
+
+/// status that is written to register `e` by the input and file syscalls
+const STATUS_OK:u64 = 0;
+const STATUS_EOF:u64 = 1;
+const STATUS_INVALID:u64 = 2;
+
+/// file descriptor that is returned when a file can not be opened
+const INVALID_FD:u64 = u64::MAX;
+
+/// reads a line into the buffer and null terminates it.
+/// returns the status and the amount of bytes that were read
+fn input_line(state: &mut MachineState, buffer_ptr: u64, buffer_size: u64) -> Result<(u64,u64),String>
+{
+    if buffer_size == 0
+    {
+        return Err("provided buffer is to small to store the input".into())
+    }
 
-        // write chars to buffer
-        for ii in 0..input_len as u64
+    // -1 because of the null termination that will be added later
+    let line = state.machine_information.get_input().read_line((buffer_size - 1) as usize);
+
+    let (status, line) = match line
+    {
+        Some(line) => (STATUS_OK, line),
+        None => (STATUS_EOF, vec![]),
+    };
+
+    write_bytes(state, buffer_ptr, &line)?;
+
+    // add null termination directly after the input
+    let end = buffer_ptr.checked_add(line.len() as u64).ok_or("the input does not fit into the adress space")?;
+    state.mem_state.store(0, end)?;
+
+    Ok((status, line.len() as u64))
+}
+
+/// reads up to `buffer_size` bytes into the buffer without null terminating it.
+/// returns the status and the amount of bytes that were read
+fn input_bytes(state: &mut MachineState, buffer_ptr: u64, buffer_size: u64) -> Result<(u64,u64),String>
+{
+    let bytes = state.machine_information.get_input().read_bytes(buffer_size as usize);
+    write_bytes(state, buffer_ptr, &bytes)?;
+
+    let status = if bytes.is_empty() && buffer_size > 0 {STATUS_EOF} else {STATUS_OK};
+    Ok((status, bytes.len() as u64))
+}
+
+fn write_bytes(state: &mut MachineState, buffer_ptr: u64, bytes: &[u8]) -> Result<(),String>
+{
+    for (ii,b) in bytes.iter().enumerate()
+    {
+        let addr = buffer_ptr.checked_add(ii as u64).ok_or("the input does not fit into the adress space")?;
+        state.mem_state.store(*b as u64, addr)?;
+    }
+    Ok(())
+}
+
+/// parses an integer with the given radix.
+/// hex numbers may start with `0x`
+fn parse_integer(token: &str, radix: u32) -> Option<u64>
+{
+    let digits = if radix == 16
+    {
+        token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).unwrap_or(token)
+    }
+    else
+    {
+        token
+    };
+
+    u64::from_str_radix(digits, radix).ok()
+}
+
+/// reads the parameters of the file syscalls
+fn read_file_params(state: &MachineState) -> Result<(u64,u64,u64),String>
+{
+    match (read_param(state, PARAM2), read_param(state, PARAM3), read_param(state, PARAM4))
+    {
+        (Ok(p2), Ok(p3), Ok(p4)) => Ok((p2,p3,p4)),
+        _ => Err("could not read the parameters of the file syscall".into()),
+    }
+}
+
+/// sets the status register `e` and removes the parameters of a file syscall.
+/// failed file operations do not stop the machine, instead `err_val` is returned.
+fn file_result(state: &mut MachineState, res: Result<u64,String>, param_count: u8, err_val: u64) -> SysCallAction
+{
+    let (status, val) = match res
+    {
+        Ok(val) => (STATUS_OK, val),
+        Err(_) => (STATUS_INVALID, err_val),
+    };
+
+    state.reg_state.store(Register::e, status);
+    let _ = remove_stack_entries(state, param_count);
+
+    SysCallAction::Value(val)
+}
+
+pub fn file_open(state: &mut MachineState) -> SysCallAction
+{
+    let (name_ptr, mode) = match (read_param(state, PARAM2), read_param(state, PARAM3))
+    {
+        (Ok(name_ptr), Ok(mode)) => (name_ptr, mode),
+        _ => return SysCallAction::Err("could not read the parameters of the file syscall".into()),
+    };
+
+    let name = match read_string_terminated_uncompressed(state, name_ptr)
+    {
+        Ok(name) => name,
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    let res = match OpenMode::from_u64(mode)
+    {
+        Some(mode) => state.files.open(&name, mode),
+        None => Err(format!("invalid open mode: {}", mode)),
+    };
+
+    // 0 is stdin and can not be used to signal an error
+    file_result(state, res, 3, INVALID_FD)
+}
+
+pub fn file_read(state: &mut MachineState) -> SysCallAction
+{
+    let (fd, buffer_ptr, max) = match read_file_params(state)
+    {
+        Ok(params) => params,
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    let res = if fd == 0
+    {
+        Ok(state.machine_information.get_input().read_bytes(max as usize))
+    }
+    else
+    {
+        state.files.read(fd, max)
+    };
+
+    let bytes = match res
+    {
+        Ok(bytes) => bytes,
+        Err(x) => return file_result(state, Err(x), 4, 0),
+    };
+
+    if let Err(x) = write_bytes(state, buffer_ptr, &bytes)
+    {
+        return SysCallAction::Err(x)
+    }
+
+    let res = file_result(state, Ok(bytes.len() as u64), 4, 0);
+
+    if bytes.is_empty() && max > 0
+    {
+        state.reg_state.store(Register::e, STATUS_EOF);
+    }
+
+    res
+}
+
+pub fn file_write(state: &mut MachineState) -> SysCallAction
+{
+    let (fd, buffer_ptr, len) = match read_file_params(state)
+    {
+        Ok(params) => params,
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    let mut bytes = vec![];
+    for addr in buffer_ptr..buffer_ptr.saturating_add(len)
+    {
+        match state.mem_state.read(addr)
         {
-            state.mem_state.store(vec_buffer[ii as usize], buffer_ptr+ii).unwrap();
+            Ok(val) if val <= u8::MAX as u64 => bytes.push(val as u8),
+            Ok(val) => return SysCallAction::Err(format!("value {} at {} does not fit into a byte", val, addr)),
+            Err(x) => return SysCallAction::Err(x),
         }
+    }
 
-        // add null termination
-        state.mem_state.store(0, buffer_ptr + buffer_size -1).unwrap();
+    let res = if fd == 1
+    {
+        state.machine_information.push_str(String::from_utf8_lossy(&bytes).into_owned());
+        Ok(bytes.len() as u64)
     }
+    else
+    {
+        state.files.write(fd, &bytes)
+    };
 
-    let _ = remove_stack_entries(state, 3);
+    file_result(state, res, 4, 0)
+}
 
-    SysCallAction::Ok
+pub fn file_close(state: &mut MachineState) -> SysCallAction
+{
+    let res = match read_param(state, PARAM2)
+    {
+        Ok(fd) => state.files.close(fd).map(|_| 0),
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    file_result(state, res, 2, 0)
+}
+
+pub fn file_seek(state: &mut MachineState) -> SysCallAction
+{
+    let (fd, whence, offset) = match read_file_params(state)
+    {
+        Ok(params) => params,
+        Err(x) => return SysCallAction::Err(x),
+    };
+
+    let res = state.files.seek(fd, whence, offset as i64);
+    file_result(state, res, 4, 0)
+}
+
+pub fn assert(state: &mut MachineState) -> SysCallAction
+{
+    let (condition, msg_ptr) = match (read_param(state, PARAM2), read_param(state, PARAM3))
+    {
+        (Ok(condition), Ok(msg_ptr)) => (condition, msg_ptr),
+        _ => return SysCallAction::Err("could not read the parameters of assert".into()),
+    };
+
+    if condition != 0
+    {
+        let _ = remove_stack_entries(state, 3);
+        return SysCallAction::Ok
+    }
+
+    match msg_ptr
+    {
+        0 => SysCallAction::Err("assertion failed".into()),
+        ptr => match read_string_terminated_uncompressed(state, ptr)
+        {
+            Ok(msg) => SysCallAction::Err(format!("assertion failed: {}", msg)),
+            Err(x) => SysCallAction::Err(format!("assertion failed, the message could not be read: {}", x)),
+        },
+    }
 }
 
 pub fn print_register(state: &mut MachineState, content: u64) -> Result<(),String>
@@ -282,4 +816,174 @@ pub fn remove_stack_entries(state: &mut MachineState, amount: u8) -> Result<(),S
     } 
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::check_instruction::StateHelperFunctions;
+    use crate::file_system::FileSystem;
+    use crate::input::InputSource;
+    use crate::memory::MemLayout;
+    use std::collections::HashMap;
+
+    fn seeded_state(seed: u64, file_system: FileSystem) -> MachineState
+    {
+        MachineState::init(MachineInitInfo{layout: MemLayout::new(100), write_to_file: true, seed: Some(seed), file_system, ..Default::default()})
+    }
+
+    fn test_state(file_system: FileSystem) -> MachineState
+    {
+        seeded_state(1, file_system)
+    }
+
+    /// pushes the parameters in reverse order and executes the syscall
+    fn call(state: &mut MachineState, params: &[u64]) -> SysCallAction
+    {
+        params.iter().rev().for_each(|p| state.push_stack(*p).unwrap());
+        syscall(state)
+    }
+
+    fn value(action: SysCallAction) -> u64
+    {
+        match action
+        {
+            SysCallAction::Value(x) => x,
+            _ => panic!("syscall did not return a value"),
+        }
+    }
+
+    #[test]
+    fn rand_test()
+    {
+        let (mut first, mut second) = (seeded_state(7, FileSystem::None), seeded_state(7, FileSystem::None));
+        let sequence:Vec<u64> = (0..20).map(|_| value(call(&mut first, &[5, 0]))).collect();
+        assert_eq!(sequence, (0..20).map(|_| value(call(&mut second, &[5, 0]))).collect::<Vec<_>>());
+
+        let mut other = seeded_state(8, FileSystem::None);
+        assert_ne!(sequence, (0..20).map(|_| value(call(&mut other, &[5, 0]))).collect::<Vec<_>>());
+
+        // bounded values stay below the bound
+        assert!((0..1000).all(|_| value(call(&mut first, &[5, 10])) < 10));
+        assert!((0..100).all(|_| value(call(&mut first, &[5, 1])) == 0));
+
+        // all parameters were removed from the stack
+        assert_eq!(first.reg_state.read(Register::tos), first.mem_state.get_mem_size());
+    }
+
+    #[test]
+    fn sys_info_time_test()
+    {
+        let mut state = test_state(FileSystem::None);
+        state.mem_state.prepare_mem(vec![1;3], vec![2;5]).unwrap();
+        state.machine_information.add_cycles(42);
+
+        assert_eq!(value(call(&mut state, &[4, 1])), 100);
+        assert_eq!(value(call(&mut state, &[4, 3])), 42);
+        assert_eq!(value(call(&mut state, &[4, 4])), 3);
+        assert_eq!(value(call(&mut state, &[4, 5])), 5);
+        assert_eq!(value(call(&mut state, &[4, 6])), 0);
+        assert_eq!(value(call(&mut state, &[4, 7])), 3);
+
+        // the heap-cutoff is right below the parameters of the syscall
+        assert_eq!(value(call(&mut state, &[4, 2])), 97);
+        assert!(matches!(call(&mut state, &[4, 8]), SysCallAction::Err(_)));
+
+        assert_eq!(value(call(&mut state, &[6, 1])), 42);
+        assert!(matches!(call(&mut state, &[6, 4]), SysCallAction::Err(_)));
+    }
+
+    #[test]
+    fn print_types_test()
+    {
+        let mut state = test_state(FileSystem::None);
+        b"hi\0".iter().enumerate().for_each(|(ii,c)| state.mem_state.store(*c as u64, 10 + ii as u64).unwrap());
+
+        for params in [&[8, 3, 42][..], &[8, 4, 255], &[8, 5, 5], &[8, 6, b'x' as u64], &[8, 7, 10], &[8, 8, 10, 1], &[8, 8, 10, 2]]
+        {
+            assert!(matches!(call(&mut state, params), SysCallAction::Ok));
+        }
+
+        // only PARAM4 values of the buffer are printed
+        assert_eq!(state.machine_information.print_output(false), "42ff101xhihhi");
+
+        // all parameters were removed from the stack, including the length of the buffer
+        assert_eq!(state.reg_state.read(Register::tos), state.mem_state.get_mem_size());
+
+        assert!(matches!(call(&mut state, &[8, 6, 300]), SysCallAction::Err(_)));
+        assert!(matches!(call(&mut state, &[8, 9, 1]), SysCallAction::Err(_)));
+    }
+
+    #[test]
+    fn mem_copy_syscall_test()
+    {
+        let mut state = test_state(FileSystem::None);
+        (10..15).for_each(|addr| state.mem_state.store(addr, addr).unwrap());
+
+        assert!(matches!(call(&mut state, &[3, 1, 20, 10, 5]), SysCallAction::Ok));
+        assert_eq!((20..25).map(|addr| state.mem_state.read(addr).unwrap()).collect::<Vec<_>>(), vec![10,11,12,13,14]);
+
+        assert!(matches!(call(&mut state, &[3, 2, 30, 7, 3]), SysCallAction::Ok));
+        assert_eq!((29..34).map(|addr| state.mem_state.read(addr).unwrap()).collect::<Vec<_>>(), vec![0,7,7,7,0]);
+        assert_eq!(state.reg_state.read(Register::tos), state.mem_state.get_mem_size());
+
+        // ranges outside of the adress space and unknown types fail
+        assert!(matches!(call(&mut state, &[3, 1, 98, 10, 5]), SysCallAction::Err(_)));
+        assert!(matches!(call(&mut state, &[3, 3, 20, 10, 5]), SysCallAction::Err(_)));
+    }
+
+    #[test]
+    fn input_line_buffer_test()
+    {
+        let mut state = test_state(FileSystem::None);
+        state.machine_information.set_input(InputSource::from_bytes(b"abc\nxyz\n"));
+
+        assert_eq!(value(call(&mut state, &[7, 1, 90, 10])), 3);
+        assert_eq!(read_string_terminated_uncompressed(&state, 90), Ok("abc".to_string()));
+
+        // the buffer reaches the end of the memory
+        assert!(matches!(call(&mut state, &[7, 1, 98, 10]), SysCallAction::Err(_)));
+        assert!(matches!(call(&mut state, &[7, 1, u64::MAX, 10]), SysCallAction::Err(_)));
+    }
+
+    #[test]
+    fn virtual_file_syscalls_test()
+    {
+        let mut files = HashMap::new();
+        files.insert("in.txt".to_string(), b"abc".to_vec());
+        let mut state = test_state(FileSystem::Virtual(files));
+
+        // file name at address 10
+        b"in.txt\0".iter().enumerate().for_each(|(ii,c)| state.mem_state.store(*c as u64, 10 + ii as u64).unwrap());
+
+        let fd = value(call(&mut state, &[10, 10, 1]));
+        assert_eq!(state.reg_state.read(Register::e), STATUS_OK);
+
+        // read into buffer at 20
+        assert_eq!(value(call(&mut state, &[11, fd, 20, 10])), 3);
+        assert_eq!(state.mem_state.read(21), Ok(b'b' as u64));
+        assert_eq!(value(call(&mut state, &[11, fd, 20, 10])), 0);
+        assert_eq!(state.reg_state.read(Register::e), STATUS_EOF);
+
+        // all parameters were removed from the stack
+        assert_eq!(state.reg_state.read(Register::tos), state.mem_state.get_mem_size());
+
+        // writing to a read only file fails without stopping the machine
+        value(call(&mut state, &[12, fd, 20, 3]));
+        assert_eq!(state.reg_state.read(Register::e), STATUS_INVALID);
+
+        value(call(&mut state, &[13, fd]));
+        assert_eq!(state.reg_state.read(Register::e), STATUS_OK);
+    }
+
+    #[test]
+    fn file_open_without_file_system_test()
+    {
+        let mut state = test_state(FileSystem::None);
+        state.mem_state.store(b'x' as u64, 10).unwrap();
+
+        assert_eq!(value(call(&mut state, &[10, 10, 1])), INVALID_FD);
+        assert_eq!(state.reg_state.read(Register::e), STATUS_INVALID);
+    }
+}
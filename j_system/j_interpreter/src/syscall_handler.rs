@@ -0,0 +1,232 @@
+use j_system_definition::register::Register;
+
+use crate::machine::MachineState;
+use crate::syscall::{SysCallAction, SysCallType, remove_stack_entries, read_string_terminated_uncompressed};
+
+use std::collections::HashMap;
+
+/// a function that can be called from the programm with the `sys` instruction.
+///
+/// The handler gets the parameters of the syscall from the stack
+/// (see `SysCallType` for the calling convention) and has to remove
+/// them with `SyscallContext::remove_params`.
+/// The returned `SysCallAction` decides how the machine continues:
+/// `Value`/`Ptr` write to register `f`, `End` stops the machine
+/// and `Err` stops the machine with a fault.
+pub trait SyscallHandler
+{
+    fn handle(&mut self, ctx: &mut SyscallContext) -> SysCallAction;
+}
+
+impl<F> SyscallHandler for F
+where F: FnMut(&mut SyscallContext) -> SysCallAction
+{
+    fn handle(&mut self, ctx: &mut SyscallContext) -> SysCallAction
+    {
+        self(ctx)
+    }
+}
+
+/// the syscalls that are implemented by the interpreter itself
+struct Builtin(fn(&mut MachineState) -> SysCallAction);
+
+impl SyscallHandler for Builtin
+{
+    fn handle(&mut self, ctx: &mut SyscallContext) -> SysCallAction
+    {
+        (self.0)(ctx.state)
+    }
+}
+
+/// access to the machine for syscall handlers
+pub struct SyscallContext<'a>
+{
+    state: &'a mut MachineState,
+}
+
+impl<'a> SyscallContext<'a>
+{
+    pub fn new(state: &'a mut MachineState) -> Self
+    {
+        Self{state}
+    }
+
+    /// returns the n-th parameter of the syscall.
+    /// PARAM1 is the syscall number, the arguments start at PARAM2
+    pub fn param(&self, n: u64) -> Result<u64,String>
+    {
+        if n == 0
+        {
+            return Err("the parameters start at 1".into())
+        }
+
+        let tos = self.state.reg_state.read(Register::tos);
+        self.state.mem_state.read(tos.checked_add(n-1).ok_or("parameter is outside of the adressspace")?)
+    }
+
+    /// removes the given amount of parameters (including the syscall number) from the stack
+    pub fn remove_params(&mut self, amount: u8)
+    {
+        let _ = remove_stack_entries(self.state, amount);
+    }
+
+    pub fn read_register(&self, reg: Register) -> u64
+    {
+        self.state.reg_state.read(reg)
+    }
+
+    /// writes to a register. The read only registers `s` and `pc` can not be written
+    pub fn write_register(&mut self, reg: Register, val: u64) -> Result<(),String>
+    {
+        match reg
+        {
+            Register::s | Register::pc => Err(format!("{:?} is a read only register", reg)),
+            _ => {self.state.reg_state.store(reg, val); Ok(())},
+        }
+    }
+
+    pub fn read_mem(&self, addr: u64) -> Result<u64,String>
+    {
+        self.state.mem_state.read(addr)
+    }
+
+    pub fn write_mem(&mut self, addr: u64, val: u64) -> Result<(),String>
+    {
+        self.state.mem_state.store(val, addr)
+    }
+
+    /// reads a null terminated string (one char per value)
+    pub fn read_string(&self, addr: u64) -> Result<String,String>
+    {
+        read_string_terminated_uncompressed(self.state, addr)
+    }
+
+    /// writes to the output of the machine
+    pub fn print(&mut self, s: String)
+    {
+        self.state.machine_information.push_str(s)
+    }
+
+    pub fn get_cycles(&self) -> u128
+    {
+        self.state.machine_information.get_cycles()
+    }
+}
+
+/// all syscalls that can be called by the programm.
+/// Key: syscall number
+pub struct SyscallRegistry
+{
+    handlers: HashMap<u64,Box<dyn SyscallHandler>>,
+}
+
+impl Default for SyscallRegistry
+{
+    fn default() -> Self
+    {
+        Self::empty()
+    }
+}
+
+impl SyscallRegistry
+{
+    /// registry without any syscalls
+    pub fn empty() -> Self
+    {
+        Self{handlers: HashMap::new()}
+    }
+
+    /// registry that contains all syscalls that are defined in `SysCallType`
+    pub fn with_defaults() -> Self
+    {
+        let mut reg = Self::empty();
+
+        for teip in SysCallType::ALL
+        {
+            reg.register(teip.number(), Builtin(teip.builtin()));
+        }
+
+        reg
+    }
+
+    /// adds a handler for the syscall number.
+    /// returns the handler that was registered before
+    pub fn register(&mut self, number: u64, handler: impl SyscallHandler + 'static) -> Option<Box<dyn SyscallHandler>>
+    {
+        self.handlers.insert(number, Box::new(handler))
+    }
+
+    pub fn unregister(&mut self, number: u64) -> Option<Box<dyn SyscallHandler>>
+    {
+        self.handlers.remove(&number)
+    }
+
+    pub fn contains(&self, number: u64) -> bool
+    {
+        self.handlers.contains_key(&number)
+    }
+
+    pub fn get_mut(&mut self, number: u64) -> Option<&mut Box<dyn SyscallHandler>>
+    {
+        self.handlers.get_mut(&number)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::check_instruction::StateHelperFunctions;
+    use crate::machine::MachineInitInfo;
+    use crate::memory::MemLayout;
+    use crate::syscall::syscall;
+
+    fn test_state() -> MachineState
+    {
+        MachineState::init(MachineInitInfo{layout: MemLayout::new(100), write_to_file: true, ..Default::default()})
+    }
+
+    #[test]
+    fn custom_handler_test()
+    {
+        let mut state = test_state();
+
+        // adds the two parameters
+        state.syscalls.register(100, |ctx: &mut SyscallContext| {
+            match (ctx.param(2), ctx.param(3))
+            {
+                (Ok(x), Ok(y)) => {ctx.remove_params(3); SysCallAction::Value(x+y)},
+                _ => SysCallAction::Err("missing parameters".into()),
+            }
+        });
+
+        state.push_stack(2).unwrap();
+        state.push_stack(40).unwrap();
+        state.push_stack(100).unwrap();
+
+        match syscall(&mut state)
+        {
+            SysCallAction::Value(x) => assert_eq!(x, 42),
+            _ => panic!("custom syscall did not return a value"),
+        }
+        assert_eq!(state.reg_state.read(Register::tos), state.mem_state.get_mem_size());
+
+        // the registry is available again after the call
+        assert!(state.syscalls.contains(100));
+    }
+
+    #[test]
+    fn unknown_and_overridden_syscall_test()
+    {
+        let mut state = test_state();
+
+        state.push_stack(200).unwrap();
+        assert!(matches!(syscall(&mut state), SysCallAction::Err(_)));
+
+        // replace the end syscall with a handler that faults
+        assert!(state.syscalls.register(SysCallType::End.number(), |_: &mut SyscallContext| SysCallAction::Err("no end".into())).is_some());
+
+        state.push_stack(SysCallType::End.number()).unwrap();
+        assert!(matches!(syscall(&mut state), SysCallAction::Err(x) if x == "no end"));
+    }
+}
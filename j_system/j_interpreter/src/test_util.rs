@@ -0,0 +1,61 @@
+use crate::load_bin::{self,Binary};
+use crate::machine::{MachineInitInfo,MachineState};
+
+use j_assembler::assembler::assemble;
+
+use std::cell::RefCell;
+use std::io::{self,Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// collects what gets written to it, the clones share the buffer
+#[derive(Clone,Default)]
+pub struct SharedBuf(pub Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuf
+{
+    pub fn text(&self) -> String
+    {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuf
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        Ok(())
+    }
+}
+
+/// a machine with the default settings, the code starts at adress 1 behind the NULL value of the rom
+pub fn machine_with_code(code: &[u64]) -> MachineState
+{
+    machine_with(MachineInitInfo::default(), code)
+}
+
+pub fn machine_with(init: MachineInitInfo, code: &[u64]) -> MachineState
+{
+    let mut machine = MachineState::init(init);
+    machine.laod_into_state(Binary::from_sections(vec![0], code.to_vec(), 1)).unwrap();
+    machine
+}
+
+/// assembles the source as `t.asm` and loads it with its labels and line table
+pub fn assembled_machine(init: MachineInitInfo, source: &str) -> MachineState
+{
+    let assembly = assemble(source.lines().map(|line| line.to_string()).collect(), "t.asm".to_string()).unwrap();
+
+    let mut machine = MachineState::init(MachineInitInfo{
+        symbols: Some(load_bin::symbols_of(&assembly)),
+        line_table: Some(load_bin::line_table_of(&assembly, PathBuf::new())),
+        ..init
+    });
+    machine.laod_into_state(Binary::from_assembly(&assembly)).unwrap();
+    machine
+}
@@ -0,0 +1,171 @@
+use j_system_definition::register::{Register,RegisterState};
+
+use serde_json::{json,Map};
+
+use std::io::{self,Write};
+
+/// registers that are compared after every instruction.
+/// `pc` is left out since the next line of the trace shows it
+const REGISTERS: [Register;9] = [Register::a, Register::b, Register::c, Register::d, Register::e, Register::f,
+                                 Register::s, Register::tos, Register::bos];
+
+#[derive(Clone,Copy,PartialEq,Debug)]
+pub enum TraceFormat
+{
+    /// one line per instruction: `<cycle>\t<pc>\t<instruction>\t<changes>`,
+    /// e.g. `12\t5\tmov [c], a\t[600]=3`
+    Text,
+
+    /// one json object per line:
+    /// `{"cycle":12,"pc":5,"asm":"mov [c], a","regs":{},"writes":[[600,3]]}`
+    Json,
+}
+
+impl TraceFormat
+{
+    pub fn parse(s: &str) -> Result<Self,String>
+    {
+        match s
+        {
+            "text"  => Ok(TraceFormat::Text),
+            "json"  => Ok(TraceFormat::Json),
+            _       => Err(format!("unknown trace format: {} (use text or json)", s)),
+        }
+    }
+}
+
+/// logs every executed instruction with the registers and memory it changed
+pub struct Tracer
+{
+    out: Box<dyn Write>,
+    format: TraceFormat,
+
+    /// only instructions in these adress ranges `start..end` are logged.
+    /// Everything is logged if there are no ranges
+    ranges: Vec<(u64,u64)>,
+
+    /// the first error while writing the trace
+    error: Option<io::Error>,
+}
+
+impl Tracer
+{
+    pub fn new(out: Box<dyn Write>, format: TraceFormat) -> Self
+    {
+        Self{out, format, ranges: vec![], error: None}
+    }
+
+    /// only logs instructions in `start..end` (and the other added ranges)
+    pub fn add_range(&mut self, start: u64, end: u64)
+    {
+        self.ranges.push((start, end));
+    }
+
+    /// should the instruction at the adress be logged?
+    pub fn contains(&self, pc: u64) -> bool
+    {
+        self.ranges.is_empty() || self.ranges.iter().any(|(start,end)| (*start..*end).contains(&pc))
+    }
+
+    /// logs an executed instruction. `writes` are the memory writes: (adress, old value, new value)
+    pub fn record(&mut self, cycle: u128, pc: u64, asm: &str, before: &RegisterState, after: &RegisterState, writes: &[(u64,u64,u64)])
+    {
+        if self.error.is_some()
+        {
+            return
+        }
+
+        // `as_string` separates the instruction and the parameters with a tab
+        let asm = asm.split_whitespace().collect::<Vec<_>>().join(" ");
+        let changed = REGISTERS.iter().filter(|reg| before.read(**reg) != after.read(**reg));
+
+        let line = match self.format
+        {
+            TraceFormat::Text =>
+            {
+                let changes:Vec<String> = changed.map(|reg| format!("{:?}={}", reg, after.read(*reg)))
+                    .chain(writes.iter().map(|(addr,_,val)| format!("[{}]={}", addr, val)))
+                    .collect();
+
+                format!("{}\t{}\t{}\t{}", cycle, pc, asm, changes.join(" "))
+            },
+            TraceFormat::Json =>
+            {
+                let regs:Map<_,_> = changed.map(|reg| (format!("{:?}", reg), json!(after.read(*reg)))).collect();
+                let writes:Vec<_> = writes.iter().map(|(addr,_,val)| [addr, val]).collect();
+
+                json!({"cycle": cycle as u64, "pc": pc, "asm": asm, "regs": regs, "writes": writes}).to_string()
+            },
+        };
+
+        if let Err(x) = writeln!(self.out, "{}", line)
+        {
+            self.error = Some(x);
+        }
+    }
+
+    /// flushes the trace, returns the first error that happend while writing it
+    pub fn finish(&mut self) -> Result<(),String>
+    {
+        if let Some(x) = self.error.take()
+        {
+            return Err(format!("could not write the trace: {}", x))
+        }
+        self.out.flush().map_err(|e| format!("could not write the trace: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::machine::MachineInitInfo;
+    use crate::test_util::{SharedBuf,assembled_machine};
+
+    fn trace(format: TraceFormat, range: Option<(u64,u64)>) -> Vec<String>
+    {
+        let mut machine = assembled_machine(MachineInitInfo::default(), "
+_rom
+_code
+    .start:
+        mov a, 5
+        push a
+        add a, 1
+        push 9
+        sys
+");
+
+        let out = SharedBuf::default();
+        let mut tracer = Tracer::new(Box::new(out.clone()), format);
+        if let Some((start,end)) = range
+        {
+            tracer.add_range(start, end);
+        }
+
+        machine.set_tracer(tracer);
+        machine.run().unwrap();
+
+        out.text().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn text_trace_test()
+    {
+        let trace = trace(TraceFormat::Text, None);
+
+        assert_eq!(trace[0], "0\t1\tmov a, 5\ta=5");
+        assert_eq!(trace[1], "1\t3\tpush a\ttos=1023 [1023]=5");
+        assert_eq!(trace[2], "2\t4\tadd a, 1\ta=6");
+        assert_eq!(trace[4], "4\t8\tsys\ttos=1023");
+        assert_eq!(trace.len(), 5);
+    }
+
+    #[test]
+    fn filtered_json_trace_test()
+    {
+        let trace = trace(TraceFormat::Json, Some((3,5)));
+
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0], r#"{"asm":"push a","cycle":1,"pc":3,"regs":{"tos":1023},"writes":[[1023,5]]}"#);
+    }
+}
@@ -89,14 +89,15 @@ fn param_as_string(p: Option<Param>) -> Option<String>
         {
             Param::Register(reg)         => format!("{}",reg_as_string(reg)),
             Param::Constant(c)           => format!("{}", c),
-            Param::MemPtr(ptr)           => format!("{}", ptr),
+            Param::MemPtr(ptr)           => format!("[{}]", ptr),
             Param::MemPtrOffset(reg,off) => format!("[{}{}{}]",reg_as_string(reg), if off<0 {""} else {"+"}, off ),
         };
 
         Some(s.into())
     }
 
-fn ins_as_string(ins: InstructionEnum) -> String
+/// the name of the instruction in the assembly, e.g. `mov`
+pub fn ins_as_string(ins: InstructionEnum) -> String
 {
     match ins{
         InstructionEnum::add    => "add",
@@ -0,0 +1,56 @@
+import build.basic_build as bb
+import os
+import sys
+
+# runs the programs with every engine configuration next to the plain interpreter
+# and reports the first instruction where they differ.
+# usage: python3 lockstep.py [file.asm ...]
+# all programs in ./asm are used if no files are supplied
+
+programs = sorted(f for f in os.listdir("./asm") if f.endswith(".asm"))
+
+if len(sys.argv) >= 2:
+	programs = sys.argv[1:]
+
+# input for the programms that read from stdin
+inputs = {
+	"sieb.asm": "100\n",
+}
+
+# long programs are only compared up to the cycle limit
+cycle_limit = "10000000"
+
+configurations = {
+	"decode cache": [],
+	"blocks": ["--engine", "blocks"],
+}
+
+print("checking interpreter build... ",end="")
+if bb.check_build("./j_system/j_interpreter"):
+    print("OK")
+else:
+    print("failed")
+    exit()
+
+divergences = 0
+
+for file_name in programs:
+	bb.copy_asm_to_assembler(file_name)
+
+	if not bb.check_assembler_output(bb.run_assembler()):
+		print("{:<12} assembler failed".format(file_name))
+		continue
+
+	bb.copy_bin_to_interpreter()
+
+	for name, args in configurations.items():
+		ok, output = bb.run_interpreter_lockstep(args + ["--seed", "1", "--cycle-limit", cycle_limit], inputs.get(file_name, ""))
+
+		if ok:
+			print("{:<12} {:<14} {}".format(file_name, name, output.splitlines()[-1]))
+		else:
+			divergences += 1
+			print("{:<12} {:<14} failed:\n{}".format(file_name, name, output))
+
+print("--done-- {} divergences".format(divergences))
+exit(1 if divergences > 0 else 0)